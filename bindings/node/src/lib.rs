@@ -0,0 +1,53 @@
+//! Node.js bindings for mortem.
+//!
+//! ```js
+//! const mortem = require("./mortem.node");
+//!
+//! const guard = mortem.hard("./payload");
+//! // ...
+//! guard.delete(); // payload is deleted
+//! ```
+//!
+//! Node doesn't run finalizers at exit, so guards have to be deleted explicitly.
+//! Without a target, guards delete the host executable, which for scripts is node itself; only
+//! leave it out in single executable applications.
+
+use napi_derive::napi;
+
+/// Guard deleting its target when deleted or garbage collected.
+#[napi]
+pub struct Guard {
+    guard: Option<mortem::Guard>,
+}
+
+#[napi]
+impl Guard {
+    #[napi(constructor)]
+    pub fn new(target: Option<String>, hard: Option<bool>) -> Self {
+        let mut builder = mortem::Guard::builder().ensure(hard.unwrap_or(false));
+        if let Some(target) = target {
+            builder = builder.target(target);
+        }
+        Guard {
+            guard: Some(builder.build()),
+        }
+    }
+
+    /// Delete the target now.
+    #[napi]
+    pub fn delete(&mut self) {
+        self.guard.take();
+    }
+}
+
+/// Create a guard that tries to delete `target` once.
+#[napi]
+pub fn soft(target: Option<String>) -> Guard {
+    Guard::new(target, Some(false))
+}
+
+/// Create a guard that retries till `target` is deleted.
+#[napi]
+pub fn hard(target: Option<String>) -> Guard {
+    Guard::new(target, Some(true))
+}
@@ -0,0 +1,74 @@
+//! Python bindings for mortem.
+//!
+//! ```python
+//! import mortem
+//!
+//! with mortem.hard("./payload"):
+//!     ...  # payload is deleted when the block exits
+//! ```
+//!
+//! Without a target, guards delete the host executable, which for scripts is the interpreter
+//! itself; only leave it out in frozen applications.
+
+use pyo3::prelude::*;
+
+use std::path::PathBuf;
+
+/// Guard deleting its target when deleted, garbage collected or exiting a `with` block.
+#[pyclass(name = "Guard")]
+struct PyGuard {
+    guard: Option<::mortem::Guard>,
+}
+
+#[pymethods]
+impl PyGuard {
+    #[new]
+    #[pyo3(signature = (target=None, hard=false))]
+    fn new(target: Option<PathBuf>, hard: bool) -> Self {
+        let mut builder = ::mortem::Guard::builder().ensure(hard);
+        if let Some(target) = target {
+            builder = builder.target(target);
+        }
+        PyGuard {
+            guard: Some(builder.build()),
+        }
+    }
+
+    /// Delete the target now.
+    fn delete(&mut self) {
+        self.guard.take();
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, _args: &Bound<'_, pyo3::types::PyTuple>) -> bool {
+        self.delete();
+        false
+    }
+}
+
+/// Create a guard that tries to delete `target` once.
+#[pyfunction]
+#[pyo3(signature = (target=None))]
+fn soft(target: Option<PathBuf>) -> PyGuard {
+    PyGuard::new(target, false)
+}
+
+/// Create a guard that retries till `target` is deleted.
+#[pyfunction]
+#[pyo3(signature = (target=None))]
+fn hard(target: Option<PathBuf>) -> PyGuard {
+    PyGuard::new(target, true)
+}
+
+#[pymodule]
+#[pyo3(name = "mortem")]
+fn mortem_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyGuard>()?;
+    m.add_function(wrap_pyfunction!(soft, m)?)?;
+    m.add_function(wrap_pyfunction!(hard, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,6 @@
+// run with `RUSTFLAGS="-C panic=abort" cargo run --example abort`
+fn main() {
+    let _mortem = mortem::hard();
+
+    panic!("Goodbye!");
+}
@@ -0,0 +1,31 @@
+#ifndef MORTEM_H
+#define MORTEM_H
+
+/* C API of mortem, built with `cargo rustc --release --features ffi --crate-type staticlib`. */
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MORTEM_SUCCESS 0
+#define MORTEM_FAILURE -1
+
+typedef struct MortemGuard MortemGuard;
+
+/* Notified of the deletion outcome, MORTEM_SUCCESS or MORTEM_FAILURE. */
+typedef void (*mortem_callback)(int status, void *userdata);
+
+/* Create a guard deleting the host executable when freed; `hard` retries till deleted. */
+MortemGuard *mortem_guard_new(int hard, mortem_callback callback, void *userdata);
+
+/* Free a guard, deleting the host executable. */
+void mortem_guard_free(MortemGuard *guard);
+
+/* Delete the host executable at process exit. */
+int mortem_register_atexit(int hard, mortem_callback callback, void *userdata);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,74 @@
+//! Attribute macros for [mortem](https://docs.rs/mortem).
+//!
+//! Not meant to be depended on directly; enable the `macros` feature of mortem and use them
+//! through it, e.g. as `#[mortem::main]`.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Error, Ident, ItemFn};
+
+/// Guard `main`, deleting the executable once it returns.
+///
+/// The guard is created before anything else runs and dropped after everything else, whether
+/// `main` returns normally, early or through `?`. `#[mortem::main]` uses a soft guard, and
+/// `#[mortem::main(hard)]` a hard one.
+///
+/// Runtime attributes such as `#[tokio::main]` go below it, so the runtime shuts down before the
+/// guard drops.
+#[proc_macro_attribute]
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemFn);
+    let mode = match attr.is_empty() {
+        true => Ok(Ident::new("soft", Span::call_site())),
+        false => syn::parse::<Ident>(attr),
+    };
+    match mode.and_then(|mode| expand(mode, item.clone())) {
+        Ok(expanded) => expanded.into(),
+        // keep the function, so the error isn't buried under ones about it missing
+        Err(err) => {
+            let err = err.into_compile_error();
+            quote!(#err #item).into()
+        }
+    }
+}
+
+fn expand(mode: Ident, item: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let mode = match mode.to_string().as_str() {
+        "soft" => quote!(Soft),
+        "hard" => quote!(Hard),
+        _ => return Err(Error::new(mode.span(), "expected `soft` or `hard`")),
+    };
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    if !sig.inputs.is_empty() || !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig,
+            "`#[mortem::main]` only applies to functions without arguments or generics",
+        ));
+    }
+    if sig.asyncness.is_some() && attrs.is_empty() {
+        return Err(Error::new_spanned(
+            sig.asyncness,
+            "async `main` needs a runtime attribute, such as `#[tokio::main]`, below `#[mortem::main]`",
+        ));
+    }
+
+    // the original function is kept as is, attributes and all, nested in the guarded one
+    let ident = &sig.ident;
+    let mut outer = sig.clone();
+    outer.asyncness = None;
+    Ok(quote! {
+        #vis #outer {
+            #(#attrs)*
+            #sig #block
+
+            let _mortem = ::mortem::Guard::with_mode(::mortem::Mode::#mode);
+            #ident()
+        }
+    })
+}
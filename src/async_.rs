@@ -0,0 +1,319 @@
+//! Guards for async code.
+//!
+//! [`AsyncGuard`] is shaped after native async drop: once that is available it will delete
+//! the target asynchronously when dropped. Until then deletion is awaited explicitly through
+//! [`AsyncGuard::finish`], with dropping falling back to blocking on the same [`DeletionFuture`].
+//!
+//! By default deletion runs on a separate thread, so the executor is never blocked. With the
+//! `tokio` or `async-std` feature, deletion polled on that runtime is driven by the runtime
+//! itself instead, awaiting its timer between attempts.
+
+use crate::{Deletion, Guard, Inner, Step};
+
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+/// Sleep on the timer of a runtime.
+type Timer = fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Guard for async code, deleting the target without blocking the executor.
+///
+/// ### Usage
+/// ```rust
+/// #[tokio::main]
+/// async fn main() {
+///     let mortem = mortem::AsyncGuard::hard(); // register guard
+///
+///     // some code
+///     tokio::spawn(async {
+///         println!("Hello!")
+///     }).await;
+///
+///     mortem.finish().await; // executable is deleted
+/// }
+/// ```
+pub struct AsyncGuard {
+    guard: Option<Guard>,
+}
+
+impl AsyncGuard {
+    /// Create an async guard that tries to delete the host executable.
+    ///
+    /// See [`Guard::soft`].
+    pub fn soft() -> Self {
+        Guard::soft().into()
+    }
+
+    /// Create an async guard that waits till the host executable is successfully deleted.
+    ///
+    /// See [`hard`](crate::hard).
+    pub fn hard() -> Self {
+        Guard::hard().into()
+    }
+
+    /// Delete the target, completing once deletion is done.
+    ///
+    /// With the `tokio` or `async-std` feature, retries wait on the runtime's timer rather than
+    /// a separate thread. On Tokio, this needs the time driver, which `#[tokio::main]` enables.
+    pub fn finish(mut self) -> DeletionFuture {
+        DeletionFuture::new(self.guard.take().expect("guard is only taken once"))
+    }
+}
+
+impl From<Guard> for AsyncGuard {
+    fn from(guard: Guard) -> Self {
+        AsyncGuard { guard: Some(guard) }
+    }
+}
+
+impl Drop for AsyncGuard {
+    fn drop(&mut self) {
+        if let Some(guard) = self.guard.take() {
+            // the runtime can't drive deletion while its thread is blocked on it
+            block_on(DeletionFuture {
+                native: false,
+                ..DeletionFuture::new(guard)
+            });
+        }
+    }
+}
+
+/// Future deleting the target of a guard.
+///
+/// Deletion runs on a separate thread, so any runtime (or none at all) can drive the future,
+/// unless a runtime feature is enabled and the future is polled on that runtime. If dropped before
+/// being polled, or before deletion is done, the target is deleted synchronously.
+#[must_use = "futures do nothing unless polled; dropping it deletes the target synchronously"]
+pub struct DeletionFuture {
+    guard: Option<Guard>,
+    state: Arc<State>,
+    /// Whether deletion may be driven by the runtime polling, rather than a separate thread.
+    native: bool,
+    /// Deletion driven by the runtime.
+    driven: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+#[derive(Default)]
+struct State {
+    done: Mutex<bool>,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl DeletionFuture {
+    fn new(guard: Guard) -> Self {
+        DeletionFuture {
+            guard: Some(guard),
+            state: Arc::default(),
+            native: true,
+            driven: None,
+        }
+    }
+}
+
+impl Future for DeletionFuture {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.native && self.guard.is_some() {
+            if let Some(timer) = timer() {
+                let guard = self.guard.take().expect("guard is present");
+                self.driven = Some(Box::pin(drive(guard, timer)));
+            }
+        }
+        if let Some(driven) = &mut self.driven {
+            return driven.as_mut().poll(cx);
+        }
+
+        // register the waker before checking for completion, so a wake-up can't be missed
+        *self.state.waker.lock().unwrap() = Some(cx.waker().clone());
+
+        if let Some(guard) = self.guard.take() {
+            let state = Arc::clone(&self.state);
+            thread::spawn(move || {
+                drop(guard);
+                *state.done.lock().unwrap() = true;
+                if let Some(waker) = state.waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            });
+        }
+
+        if *self.state.done.lock().unwrap() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Timer of the runtime polling, if deletion can be driven by it.
+fn timer() -> Option<Timer> {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Some(|delay| Box::pin(tokio::time::sleep(delay)));
+    }
+    // its timer runs on a thread of its own, so works under any runtime
+    #[cfg(feature = "async-std")]
+    return Some(|delay| Box::pin(async_std::task::sleep(delay)));
+    #[cfg(not(feature = "async-std"))]
+    None
+}
+
+/// Delete the target of `guard`, waiting out the delay between attempts on `timer`.
+async fn drive(guard: Guard, timer: Timer) {
+    let inner = &*guard.inner;
+    if !inner.claim() {
+        return;
+    }
+    let deletion = match inner.begin(None) {
+        ControlFlow::Continue(deletion) => deletion,
+        ControlFlow::Break(_) => return,
+    };
+    let mut resume = Resume {
+        inner,
+        deletion: Some(deletion),
+    };
+    loop {
+        let deletion = resume
+            .deletion
+            .as_mut()
+            .expect("deletion is taken once done");
+        match inner.attempt(deletion) {
+            Step::Retry(delay) => timer(delay).await,
+            Step::Done(path, result) => {
+                let deletion = resume.deletion.take().expect("deletion is taken once done");
+                let _ = inner.end(deletion, path, result);
+                return;
+            }
+        }
+    }
+}
+
+/// Deletion driven by a runtime, finished synchronously if the future is dropped midway.
+struct Resume<'a> {
+    inner: &'a Inner,
+    deletion: Option<Deletion>,
+}
+
+impl Drop for Resume<'_> {
+    fn drop(&mut self) {
+        // not finished while unwinding, so a panicking task isn't held up retrying
+        if let (Some(deletion), false) = (self.deletion.take(), thread::panicking()) {
+            let _ = self.inner.proceed(deletion);
+        }
+    }
+}
+
+/// Block the current thread on `future`; the shim standing in for async drop.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GuardBuilder;
+
+    use std::path::PathBuf;
+    use std::thread::ThreadId;
+
+    /// Async guard of a fresh file for a test case, recording the thread deleting it.
+    fn guard(case: &str) -> (PathBuf, Arc<Mutex<Option<ThreadId>>>, AsyncGuard) {
+        let path = std::env::temp_dir().join(format!("mortem-async-{}-{case}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let (deleting, guard) = recording(GuardBuilder::new().target(&path).build());
+        (path, deleting, guard)
+    }
+
+    /// `guard` made async, recording the thread deleting its target.
+    fn recording(guard: Guard) -> (Arc<Mutex<Option<ThreadId>>>, AsyncGuard) {
+        let deleting = Arc::new(Mutex::new(None));
+        let recorded = Arc::clone(&deleting);
+        guard.run_before_delete(move || *recorded.lock().unwrap() = Some(thread::current().id()));
+        (deleting, guard.into())
+    }
+
+    #[test]
+    fn finishing_deletes_the_target_under_any_executor() {
+        let (path, deleting, guard) = guard("finish");
+        block_on(guard.finish());
+        assert!(!path.exists());
+        // without a runtime to drive it, deletion runs on a thread of its own
+        #[cfg(not(feature = "async-std"))]
+        assert_ne!(*deleting.lock().unwrap(), Some(thread::current().id()));
+        #[cfg(feature = "async-std")]
+        assert!(deleting.lock().unwrap().is_some());
+    }
+
+    #[test]
+    fn dropping_deletes_the_target() {
+        let (path, deleting, guard) = guard("drop");
+        drop(guard);
+        assert!(!path.exists());
+        assert!(deleting.lock().unwrap().is_some());
+    }
+
+    /// Async guard retrying deletion of a target that can't be deleted, a directory, three times.
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    fn retrying(case: &str) -> (PathBuf, Arc<Mutex<Option<ThreadId>>>, AsyncGuard) {
+        let path = std::env::temp_dir().join(format!("mortem-async-{}-{case}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let guard = GuardBuilder::new()
+            .target(&path)
+            .bounded(3, Duration::from_millis(20))
+            .reporter(crate::Reporter::Silent)
+            .build();
+        let (deleting, guard) = recording(guard);
+        (path, deleting, guard)
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn tokio_drives_deletion_on_its_timer() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let (path, deleting, guard) = retrying("tokio");
+
+        let started = std::time::Instant::now();
+        runtime.block_on(guard.finish());
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        // the single thread of the runtime drove deletion, sleeping on its timer in between
+        assert_eq!(*deleting.lock().unwrap(), Some(thread::current().id()));
+        std::fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "async-std")]
+    fn async_std_drives_deletion_on_its_timer() {
+        let (path, deleting, guard) = retrying("async-std");
+
+        let started = std::time::Instant::now();
+        async_std::task::block_on(guard.finish());
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert_eq!(*deleting.lock().unwrap(), Some(thread::current().id()));
+        std::fs::remove_dir(&path).unwrap();
+    }
+}
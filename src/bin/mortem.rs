@@ -0,0 +1,241 @@
+//! Command line interface to mortem, for scripts and other languages.
+
+use mortem::seal::Key;
+use mortem::{Guard, Outcome, Refusal, Strategy};
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: mortem delete [--hard] [--secure] [--strategy <strategy>]... [--wait-pid <pid>]
+                     [--status-file <file> [--status-key <file>]] <path>...
+
+Delete files the way a mortem guard would. Exits with 0 once all paths are deleted, 1 if any was
+refused or couldn't be deleted, and 2 on invalid arguments.
+
+options:
+    --hard                  retry till each path is deleted
+    --secure                overwrite the contents of each path before deleting it, resuming
+                            wipes left unfinished by killed processes
+    --strategy <strategy>   strategy to escalate through, in the order given: `unlink`,
+                            `clear-attributes`, `rename-away`, `schedule` or `helper`; `auto`
+                            (the default) uses the default strategies of mortem
+    --wait-pid <pid>        wait for process <pid> to exit first
+    --status-file <file>    record progress in <file>: `status=running` once started, then
+                            `status=succeeded exit=0` or `status=failed exit=<code>`
+    --status-key <file>     seal the status, and the progress of wipes, with the key read from
+                            <file>, encrypting and authenticating them; see `mortem::seal`";
+
+struct Delete {
+    hard: bool,
+    secure: bool,
+    strategies: Vec<Strategy>,
+    wait_pid: Option<u32>,
+    status_file: Option<PathBuf>,
+    status_key: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Delete, String> {
+    match args.next().as_deref() {
+        Some("delete") => {}
+        Some(command) => return Err(format!("unknown command `{command}`")),
+        None => return Err("missing command".into()),
+    }
+
+    let mut delete = Delete {
+        hard: false,
+        secure: false,
+        strategies: Vec::new(),
+        wait_pid: None,
+        status_file: None,
+        status_key: None,
+        paths: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hard" => delete.hard = true,
+            "--secure" => delete.secure = true,
+            "--strategy" => match args
+                .next()
+                .ok_or("missing value for `--strategy`")?
+                .as_str()
+            {
+                "auto" => delete.strategies.clear(),
+                "unlink" => delete.strategies.push(Strategy::Unlink),
+                "clear-attributes" => delete.strategies.push(Strategy::ClearAttributes),
+                "rename-away" => delete.strategies.push(Strategy::RenameAway),
+                "schedule" => delete.strategies.push(Strategy::Schedule),
+                "helper" => delete.strategies.push(Strategy::Helper),
+                strategy => return Err(format!("unknown strategy `{strategy}`")),
+            },
+            "--wait-pid" => {
+                let pid = args.next().ok_or("missing value for `--wait-pid`")?;
+                let pid = pid.parse().map_err(|_| format!("invalid pid `{pid}`"))?;
+                delete.wait_pid = Some(pid);
+            }
+            "--status-file" => {
+                let file = args.next().ok_or("missing value for `--status-file`")?;
+                delete.status_file = Some(file.into());
+            }
+            "--status-key" => {
+                let file = args.next().ok_or("missing value for `--status-key`")?;
+                delete.status_key = Some(file.into());
+            }
+            "-h" | "--help" => return Err(String::new()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{flag}`")),
+            path => delete.paths.push(path.into()),
+        }
+    }
+
+    if delete.paths.is_empty() {
+        return Err("missing path".into());
+    }
+    if delete.status_key.is_some() && delete.status_file.is_none() {
+        return Err("`--status-key` requires `--status-file`".into());
+    }
+    Ok(delete)
+}
+
+/// Block till the process `pid` exited.
+#[cfg(unix)]
+fn wait_pid(pid: u32) -> Result<(), String> {
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    let pid = libc::pid_t::try_from(pid).map_err(|_| format!("invalid pid `{pid}`"))?;
+    // SAFETY: signal 0 only checks whether the process exists
+    while unsafe { libc::kill(pid, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    {
+        sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn wait_pid(_pid: u32) -> Result<(), String> {
+    Err("`--wait-pid` is only supported on unix".into())
+}
+
+/// Replace the contents of the status file at `path` with `status`, sealed with `key` if given.
+///
+/// Written to a sibling first and renamed over, so readers never see a partial status.
+fn write_status(path: &Path, status: &str, key: Option<&Key>) -> io::Result<()> {
+    let mut partial = OsString::from(path);
+    partial.push(".partial");
+    let status = match key {
+        Some(key) => mortem::seal::seal(key, status.as_bytes())?,
+        None => status.to_owned(),
+    };
+    fs::write(&partial, format!("{status}\n"))?;
+    fs::rename(&partial, path)
+}
+
+fn main() -> ExitCode {
+    let delete = match parse(env::args().skip(1)) {
+        Ok(delete) => delete,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("error: {err}\n");
+            }
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let key = match delete.status_key.as_deref().map(fs::read).transpose() {
+        Ok(key) => key.map(Key::new),
+        Err(err) => {
+            eprintln!("error: failed to read status key: {err}");
+            return ExitCode::from(2);
+        }
+    };
+
+    // the absence of a status tells the helper never ran; a lingering `running` that it died
+    if let Some(file) = &delete.status_file {
+        if let Err(err) = write_status(file, "status=running", key.as_ref()) {
+            eprintln!("error: failed to write status file: {err}");
+            return ExitCode::from(2);
+        }
+    }
+
+    let code = run(&delete, key.as_ref());
+
+    if let Some(file) = &delete.status_file {
+        let status = match code {
+            0 => "status=succeeded exit=0".to_owned(),
+            code => format!("status=failed exit={code}"),
+        };
+        if let Err(err) = write_status(file, &status, key.as_ref()) {
+            eprintln!("error: failed to write status file: {err}");
+        }
+    }
+    ExitCode::from(code)
+}
+
+/// Wait for the process if given, then delete the paths, returning the exit code.
+///
+/// Progress of wipes is sealed with `key`, if given.
+fn run(delete: &Delete, key: Option<&Key>) -> u8 {
+    if let Some(pid) = delete.wait_pid {
+        if let Err(err) = wait_pid(pid) {
+            eprintln!("error: {err}");
+            return 2;
+        }
+    }
+
+    let mut code = 0;
+    for path in &delete.paths {
+        let mut builder = Guard::builder()
+            .ensure(delete.hard)
+            .wipe(delete.secure)
+            .target(path);
+        if !delete.strategies.is_empty() {
+            builder = builder.strategies(delete.strategies.iter().copied());
+        }
+        if let Some(key) = key {
+            builder = builder.seal_key(key.clone());
+        }
+        let outcome = match builder.build().execute() {
+            Ok(report) => report.map(|report| report.outcome),
+            Err(err) => Some(err.report().outcome),
+        };
+        match outcome {
+            Some(Outcome::Refused(refusal)) => {
+                eprintln!(
+                    "error: refused to delete {}: {}",
+                    path.display(),
+                    reason(refusal)
+                );
+                code = 1;
+            }
+            Some(Outcome::Failed(kind)) => {
+                eprintln!("error: failed to delete {}: {kind}", path.display());
+                code = 1;
+            }
+            _ if path.symlink_metadata().is_ok() => {
+                eprintln!("error: failed to delete {}", path.display());
+                code = 1;
+            }
+            _ => {}
+        }
+    }
+    code
+}
+
+/// Why deletion was refused, to complete `refused to delete <path>: `.
+fn reason(refusal: Refusal) -> String {
+    match refusal {
+        Refusal::SourcesDisagree => "the sources of its path disagree".into(),
+        Refusal::Setuid => "it has the setuid or setgid bit set".into(),
+        Refusal::MultiCall => "it has other hard links".into(),
+        Refusal::Packaged => "it's owned by a system package".into(),
+        Refusal::NixStore => "it lies in the read-only Nix store".into(),
+        refusal => format!("{refusal:?}"),
+    }
+}
@@ -0,0 +1,1437 @@
+//! Configuring guards before creating them.
+
+use crate::handle::{DirHandle, FileId, Handle};
+use crate::host::HOST;
+use crate::raw::RawPath;
+use crate::sources::Sources;
+use crate::state::{Limits, StateDir, Store, Tracked};
+use crate::{
+    handle, inherit, original_path, platform, provenance, report, resolve, Backoff,
+    Canonicalization, DebuggerPolicy, Degradation, Destructive, Error, Event, Guard, GuardConfig,
+    Hook, Inner, Mode, MultiCallPolicy, PackagePolicy, Refusal, Report, Reporter, ResolutionPolicy,
+    Retries, RetryPolicy, SetuidPolicy, SourcePolicy, Strategy, TempLocation, TempNaming,
+    Termination,
+};
+
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::mpsc::SyncSender;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+#[cfg(feature = "reexec-memfd")]
+use crate::memfd;
+#[cfg(feature = "tracing")]
+use crate::shown;
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+/// Builder for configuring a [`Guard`].
+///
+/// ### Usage
+/// ```rust
+/// fn main() {
+///     let _mortem = mortem::Guard::builder()
+///         .ensure(true)
+///         .reporter(mortem::Reporter::Stderr)
+///         .build();
+///
+///     // some code
+///     println!("Hello!")
+///
+///     // functions ends, _mortem drops and executable is deleted
+/// }
+/// ```
+///
+/// Retrying 10 times with 200ms between attempts, then giving up without a word:
+/// ```rust
+/// # use std::time::Duration;
+/// let _mortem = mortem::Guard::builder()
+///     .bounded(10, Duration::from_millis(200))
+///     .reporter(mortem::Reporter::Silent)
+///     .build();
+/// ```
+///
+/// With the `tracing` feature, verbosity is up to the subscriber; all events are logged under the
+/// `mortem` target, e.g. silenced with an `EnvFilter` of `mortem=off`.
+#[derive(Clone, Debug, Default)]
+pub struct GuardBuilder {
+    config: GuardConfig,
+    events: Option<SyncSender<Event>>,
+    condition: Option<Once<dyn FnOnce() -> bool + Send>>,
+    wipe_while: Option<Condition>,
+    callbacks: Callbacks,
+    /// Where run limits are kept track of.
+    state: Option<SharedStore>,
+    /// Key what's left on disk for later runs is sealed with.
+    #[cfg(feature = "seal")]
+    seal_key: Option<crate::seal::Key>,
+    /// Handle on the target passed in by the caller.
+    handle: Option<Handle>,
+}
+
+impl From<GuardConfig> for GuardBuilder {
+    fn from(config: GuardConfig) -> Self {
+        GuardBuilder {
+            config,
+            events: None,
+            condition: None,
+            wipe_while: None,
+            callbacks: Callbacks::default(),
+            state: None,
+            #[cfg(feature = "seal")]
+            seal_key: None,
+            handle: None,
+        }
+    }
+}
+
+/// Retries of [`Mode::Hard`] and [`GuardBuilder::ensure`], backing off from 10ms up to a second.
+pub(crate) const HARD_RETRIES: RetryPolicy =
+    RetryPolicy::exponential(Duration::from_millis(10), Duration::from_secs(1));
+
+impl GuardBuilder {
+    /// Create a builder for a soft guard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply the preset `mode`, replacing the retries, delay, strategies and wiping configured so far.
+    ///
+    /// See [`Mode`].
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self = self.retry_policy(HARD_RETRIES);
+        let config = &mut self.config;
+        config.strategies = Strategy::ESCALATION.to_vec();
+        config.wipe = false;
+        config.overwrite_passes = 1;
+        match mode {
+            Mode::Soft => config.retries = Retries::Never,
+            Mode::Bounded { retries, delay } => {
+                config.retries = Retries::Limit(retries);
+                config.delay = delay;
+                config.backoff = Backoff::Fixed;
+            }
+            Mode::Hard => {}
+            Mode::Secure => config.wipe = true,
+            Mode::Custom(strategies) => config.strategies = strategies,
+        }
+        self
+    }
+
+    /// Ensure deletion of the executable, retrying till it is deleted.
+    ///
+    /// Retries back off exponentially from 10ms up to a second between attempts, as with
+    /// [`Mode::Hard`]; [`GuardBuilder::retry_policy`] configures them otherwise. See [`hard`](crate::hard).
+    pub fn ensure(mut self, ensure: bool) -> Self {
+        match ensure {
+            true => self.retry_policy(HARD_RETRIES),
+            false => {
+                self.config.retries = Retries::Never;
+                self
+            }
+        }
+    }
+
+    /// Retry deletion up to `retries` times, waiting `delay` between attempts, then give up quietly.
+    ///
+    /// See [`bounded`](crate::bounded).
+    pub fn bounded(mut self, retries: u32, delay: Duration) -> Self {
+        self.config.retries = Retries::Limit(retries);
+        self.config.delay = delay;
+        self.config.backoff = Backoff::Fixed;
+        self
+    }
+
+    /// Retry deletion according to `policy`.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use mortem::RetryPolicy;
+    /// use std::time::Duration;
+    ///
+    /// let mut policy = RetryPolicy::exponential(Duration::from_millis(50), Duration::from_secs(5));
+    /// policy.jitter = true;
+    /// let _mortem = mortem::Guard::builder().retry_policy(policy).build();
+    /// ```
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        let RetryPolicy {
+            retries,
+            delay,
+            backoff,
+            jitter,
+        } = policy;
+        self.config.retries = retries;
+        self.config.delay = delay;
+        self.config.backoff = backoff;
+        self.config.jitter = jitter;
+        self
+    }
+
+    /// Set the final-resort reporter used when deletion fails.
+    ///
+    /// See [`Reporter`].
+    pub fn reporter(mut self, reporter: Reporter) -> Self {
+        self.config.reporter = Some(reporter);
+        self
+    }
+
+    /// Delete `path` instead of the host executable.
+    ///
+    /// Relative paths are resolved when the guard is built, against the [base
+    /// directory](Self::base_dir) or otherwise the current working directory, so changing the
+    /// working directory later on can't redirect the deletion.
+    pub fn target(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.target = Some(path.into());
+        self
+    }
+
+    /// Delete the open `file` instead of the host executable, e.g. a binary the caller spawned.
+    ///
+    /// The path of the file is read back through the handle, which fails with
+    /// [`io::ErrorKind::Unsupported`] on platforms other than Linux, macOS and Windows, and with
+    /// [`io::ErrorKind::NotFound`] if it has no name left. Deletion is skipped if the file at the
+    /// path is [replaced](Self::skip_if_replaced) by the time the guard drops. On Linux and
+    /// Android, the guard holds on to the handle to [follow renames](Self::follow_renames) of the
+    /// file; elsewhere, the handle is closed right away.
+    pub fn target_file(mut self, file: std::fs::File) -> io::Result<Self> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let handle = Handle::from(file.try_clone()?);
+        self.config.target = Some(handle::path_of(file)?);
+        self.config.skip_if_replaced = true;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            self.handle = Some(handle);
+        }
+        Ok(self)
+    }
+
+    /// Resolve a relative [target](Self::target) against `dir` instead of the current working
+    /// directory.
+    pub fn base_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.base_dir = Some(dir.into());
+        self
+    }
+
+    /// Follow the target if it gets renamed before the guard drops, deleting it under its new name.
+    ///
+    /// Self-updaters commonly rename the running binary away; without this the guard would try to
+    /// delete the stale original path instead.
+    /// If the target was already deleted by the time the guard drops, there is nothing left to do.
+    ///
+    /// Only supported on Linux and Android, where a handle on the target is held for the lifetime
+    /// of the guard. Elsewhere, or if no handle can be held (e.g. because the process is out of file
+    /// descriptors), the original path is used and [`Degradation::NoHandle`] is reported.
+    pub fn follow_renames(mut self, follow: bool) -> Self {
+        self.config.follow_renames = follow;
+        self
+    }
+
+    /// Hold a handle on the directory of the target, and delete the target relative to it.
+    ///
+    /// Deletion then still works after the process entered a `chroot` or `pivot_root`, from where
+    /// the original path may be unreachable, or after the directory was moved.
+    /// The handle is opened when the guard is built, so build it before changing roots.
+    ///
+    /// Only supported on Unix. If no handle can be held, the path is used and
+    /// [`Degradation::NoDirectoryHandle`] is reported.
+    pub fn hold_directory(mut self, hold: bool) -> Self {
+        self.config.hold_directory = hold;
+        self
+    }
+
+    /// Skip deletion if the file at the target path was replaced before the guard drops.
+    ///
+    /// Updaters commonly put a new version in place of the running binary; this avoids destroying
+    /// the freshly installed replacement. Files are compared by identity (device and inode) rather
+    /// than by name.
+    ///
+    /// Only supported on Unix. Elsewhere the target is always deleted.
+    pub fn skip_if_replaced(mut self, skip: bool) -> Self {
+        self.config.skip_if_replaced = skip;
+        self
+    }
+
+    /// Leave an empty, immutable placeholder at the path of the target once it's deleted.
+    ///
+    /// Some watchdogs and endpoint agents restore missing tools by downloading them again to where
+    /// they were; the placeholder keeps naive re-creation from succeeding. It's made immutable
+    /// with the immutable inode flag on Linux, which takes `CAP_LINUX_IMMUTABLE`, the user
+    /// immutable flag on macOS, and the read-only attribute elsewhere. Remove it again with
+    /// [`strategy::remove_placeholder`](crate::strategy::remove_placeholder).
+    ///
+    /// Only placed once the target was deleted. If it can't be placed or made immutable,
+    /// [`Degradation::NoPlaceholder`] is reported.
+    pub fn placeholder(mut self, placeholder: bool) -> Self {
+        self.config.placeholder = placeholder;
+        self
+    }
+
+    /// Also delete `path`, a wrapper script launching the target, once the target is deleted.
+    ///
+    /// Distribution packages commonly install a script in `PATH` that sets up the environment and
+    /// executes the real binary from elsewhere; deleting only the binary leaves the script behind.
+    /// Relative paths are resolved like the [target](Self::target). Wrappers are only removed if
+    /// the target was deleted, and failing to remove one reports [`Degradation::WrapperNotRemoved`].
+    pub fn wrapper(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.wrappers.push(path.into());
+        self
+    }
+
+    /// Also remove the file at `path` before the target, as [`Guard::also_remove`].
+    ///
+    /// Relative paths are resolved like the [target](Self::target).
+    pub fn also_remove(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.also_remove.push(path.into());
+        self
+    }
+
+    /// Also remove the directory at `path` with all its contents before the target, as
+    /// [`Guard::also_remove_dir_all`].
+    ///
+    /// Relative paths are resolved like the [target](Self::target). Only removed once
+    /// [unlocked](Self::i_understand_this_is_destructive); otherwise [`Degradation::Locked`] is
+    /// reported.
+    pub fn also_remove_dir_all(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.also_remove_dir_all.push(path.into());
+        self
+    }
+
+    /// Run `hook`, a program described declaratively, around deletion.
+    ///
+    /// Hooks for the same [point](crate::HookPoint) run in the order they were added, after the callback
+    /// for it. Unlike callbacks, hooks can be shipped in [configurations](GuardConfig).
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use mortem::{Hook, HookPoint};
+    ///
+    /// let _mortem = mortem::Guard::builder()
+    ///     .hook(Hook::new(HookPoint::OnSuccess, "logger").arg("tool uninstalled"))
+    ///     .build();
+    /// ```
+    pub fn hook(mut self, hook: Hook) -> Self {
+        self.config.hooks.push(hook);
+        self
+    }
+
+    /// Detect a [wrapper](Self::wrapper) script the host executable was launched through.
+    ///
+    /// A wrapper executing the binary with `exec -a "$0"` leaves `argv[0]` naming the script
+    /// rather than the binary; if `argv[0]` names a file starting with `#!`, it's taken for the
+    /// wrapper. Wrappers not passing on their name can't be detected; register them explicitly.
+    pub fn detect_wrapper(mut self, detect: bool) -> Self {
+        self.config.detect_wrapper = detect;
+        self
+    }
+
+    /// Send lifecycle [events](Event) of the guard to `sender`, e.g. for rendering a live view of
+    /// the cleanup.
+    ///
+    /// Events never block deletion: those sent while the channel is full are dropped, and
+    /// reported as [`Degradation::EventsDropped`]. Size the channel for the events of a whole
+    /// deletion if the receiver only drains it once the guard dropped, as hard guards may
+    /// [retry](Event::Retry) at a high rate.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use std::sync::mpsc::sync_channel;
+    /// use std::thread;
+    ///
+    /// let (sender, receiver) = sync_channel(16);
+    /// let view = thread::spawn(move || {
+    ///     for event in receiver {
+    ///         println!("{event:?}");
+    ///     }
+    /// });
+    ///
+    /// drop(mortem::Guard::builder().events(sender).build());
+    /// view.join().unwrap();
+    /// ```
+    pub fn events(mut self, sender: SyncSender<Event>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+
+    /// Only delete the target if `condition` holds when the guard drops.
+    ///
+    /// E.g. only once installation succeeded, or unless a `--keep` argument was passed. If it
+    /// doesn't hold, the guard does nothing, as if [disarmed](Guard::disarm). It's evaluated at
+    /// most once, when deletion would run; of guards built from clones of the builder, only the
+    /// first to get there evaluates it, and the others skip deletion.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let installed = Arc::new(AtomicBool::new(false));
+    /// let _mortem = mortem::Guard::builder()
+    ///     .only_if({
+    ///         let installed = Arc::clone(&installed);
+    ///         move || installed.load(Ordering::SeqCst)
+    ///     })
+    ///     .build();
+    ///
+    /// // install, then
+    /// installed.store(true, Ordering::SeqCst);
+    /// ```
+    pub fn only_if(mut self, condition: impl FnOnce() -> bool + Send + 'static) -> Self {
+        self.condition = Some(Once::new(Box::new(condition)));
+        self
+    }
+
+    /// Call `callback` with the path of the target right before deleting it.
+    ///
+    /// Runs at most once, after the [steps](Guard::run_before_delete) registered on the guard, and
+    /// not when deletion is skipped or refused. As with all callbacks, guards built from clones of
+    /// the builder share it, so it runs for the first of them to delete only.
+    pub fn before_delete(mut self, callback: impl FnOnce(&Path) + Send + 'static) -> Self {
+        self.callbacks.before_delete = Some(Once::new(Box::new(callback)));
+        self
+    }
+
+    /// Call `callback` with the report once deletion succeeds.
+    ///
+    /// As with [`Report::into_result`], this includes finding the target already deleted and
+    /// refusing to delete it; see [`Report::outcome`].
+    ///
+    /// ### Usage
+    /// ```rust
+    /// let _mortem = mortem::Guard::builder()
+    ///     .on_success(|report| println!("deleted after {} attempts", report.attempts))
+    ///     .on_failure(|err| eprintln!("{err}"))
+    ///     .build();
+    /// ```
+    pub fn on_success(mut self, callback: impl FnOnce(&Report) + Send + 'static) -> Self {
+        self.callbacks.on_success = Some(Once::new(Box::new(callback)));
+        self
+    }
+
+    /// Call `callback` with the error once deletion fails.
+    ///
+    /// Unlike the [reporter](GuardBuilder::reporter), this is called whether or not the `tracing`
+    /// feature is enabled, so applications can report or recover from failures themselves.
+    pub fn on_failure(mut self, callback: impl FnOnce(Error) + Send + 'static) -> Self {
+        self.callbacks.on_failure = Some(Once::new(Box::new(callback)));
+        self
+    }
+
+    /// Set what to do when the sources of the host executable's path disagree.
+    ///
+    /// Defaults to refusing deletion. See [`SourcePolicy`].
+    pub fn source_policy(mut self, policy: SourcePolicy) -> Self {
+        self.config.source_policy = policy;
+        self
+    }
+
+    /// Set what to do when the target has the setuid or setgid bit set.
+    ///
+    /// Defaults to refusing deletion. See [`SetuidPolicy`].
+    pub fn setuid_policy(mut self, policy: SetuidPolicy) -> Self {
+        self.config.setuid_policy = policy;
+        self
+    }
+
+    /// Set what to do when the target looks like a multi-call binary.
+    ///
+    /// Defaults to refusing deletion of targets with several hard links;
+    /// [`MultiCallPolicy::RefuseRenamed`] also refuses it for host executables run under another
+    /// name, and [`MultiCallPolicy::Proceed`] forces it. See [`MultiCallPolicy`].
+    pub fn multi_call_policy(mut self, policy: MultiCallPolicy) -> Self {
+        self.config.multi_call_policy = policy;
+        self
+    }
+
+    /// Set what to do when the target is owned by a system package.
+    ///
+    /// Defaults to deleting it like any other, without looking up ownership. See
+    /// [`PackagePolicy`].
+    pub fn package_policy(mut self, policy: PackagePolicy) -> Self {
+        self.config.package_policy = policy;
+        self
+    }
+
+    /// Remove the symlink the process was run through when the target lies in the Nix store.
+    ///
+    /// Deletion of targets in the [read-only store](resolve::is_nix_store) is always refused with
+    /// [`Refusal::NixStore`], rather than retried in vain. With this, a symlink outside the store
+    /// leading to the target, as found by [`resolve::nix_link`], is removed instead, so the tool is
+    /// no longer found and its store path can be garbage collected. If it can't be removed,
+    /// [`Degradation::NixLinkNotRemoved`] is reported.
+    pub fn remove_nix_link(mut self, remove: bool) -> Self {
+        self.config.remove_nix_link = remove;
+        self
+    }
+
+    /// Set what to do when the process runs under a debugger.
+    ///
+    /// Defaults to not checking. Whether a debugger was detected is reported through
+    /// [`Report::debugger`]; skipped deletions are refused with [`Refusal::Debugged`]. See
+    /// [`DebuggerPolicy`].
+    pub fn debugger_policy(mut self, policy: DebuggerPolicy) -> Self {
+        self.config.debugger_policy = policy;
+        self
+    }
+
+    /// Only delete the target if it's the same build as the host executable.
+    ///
+    /// Right before deleting, the build identifier embedded in the target is compared to the host
+    /// executable's: the GNU build-id note in ELF, the `LC_UUID` load command in Mach-O and the
+    /// CodeView record in PE. A guard configured with a [target](Self::target) then can't be
+    /// tricked into deleting another binary put at the same path. Deletion is refused with
+    /// [`Refusal::Provenance`] on mismatch, and if either identifier can't be read, e.g. because
+    /// the executable was linked without one.
+    pub fn verify_provenance(mut self, verify: bool) -> Self {
+        self.config.verify_provenance = verify;
+        self
+    }
+
+    /// Canonicalize the target path when the guard is built.
+    ///
+    /// Applying any canonicalization to the host executable freezes its path at that point.
+    /// See [`Guard::target_path`] and [`Guard::canonicalization`] for what was resolved.
+    pub fn canonicalize(mut self, canonicalization: Canonicalization) -> Self {
+        self.config.canonicalization = canonicalization;
+        self
+    }
+
+    /// Set where temporary files, such as targets [renamed away](Strategy::RenameAway), go.
+    ///
+    /// Defaults to the directory of the target. See [`TempLocation`].
+    pub fn temp_location(mut self, location: TempLocation) -> Self {
+        self.config.temp_location = location;
+        self
+    }
+
+    /// Set how temporary files, such as targets [renamed away](Strategy::RenameAway), are named.
+    ///
+    /// Defaults to a hidden name derived from the target. See [`TempNaming`].
+    pub fn temp_naming(mut self, naming: TempNaming) -> Self {
+        self.config.temp_naming = naming;
+        self
+    }
+
+    /// Overwrite the contents of the target with zeroes before deleting it.
+    ///
+    /// Wiping is best effort, and copy-on-write or journaling filesystems may keep the original
+    /// blocks around regardless. If the target can't be overwritten, it is deleted all the same and
+    /// [`Degradation::NotWiped`] is reported.
+    ///
+    /// Notably, a running executable can't be opened for writing on Linux or Windows, so the host
+    /// executable is deleted without being wiped there, reporting [`io::ErrorKind::ExecutableFileBusy`]
+    /// on Linux. Unlinking it first doesn't help, as the file stays busy for as long as it runs,
+    /// whatever its name.
+    ///
+    /// The first 4 KiB are zeroed before anything else, so a wipe cut short leaves no executable
+    /// header behind. Progress of large wipes is kept in a hidden `.<name>.part` file next to the
+    /// target, flushed to disk as it's recorded; should the process be killed midway, wiping the
+    /// same path again, say with `mortem delete --secure`, resumes from it. With the `seal`
+    /// feature, [`GuardBuilder::seal_key`] seals it.
+    pub fn wipe(mut self, wipe: bool) -> Self {
+        self.config.wipe = wipe;
+        self
+    }
+
+    /// Seal what's left on disk for later runs with `key`, such as the progress of an unfinished
+    /// [wipe](GuardBuilder::wipe), so it reveals nothing without the key.
+    ///
+    /// Later runs must use the same key to pick up where the guard left off; what they can't open
+    /// is started over.
+    ///
+    /// Only with the `seal` feature.
+    #[cfg(feature = "seal")]
+    pub fn seal_key(mut self, key: crate::seal::Key) -> Self {
+        self.seal_key = Some(key);
+        self
+    }
+
+    /// Overwrite the contents of the target `passes` times before deleting it, or not at all for
+    /// zero passes.
+    ///
+    /// All passes but the last write random data, and the last writes zeroes; each is flushed to
+    /// disk before the next. A single pass is what [wiping](Self::wipe) does. The same caveats
+    /// apply.
+    pub fn overwrite(mut self, passes: u32) -> Self {
+        self.config.wipe = passes > 0;
+        self.config.overwrite_passes = passes.max(1);
+        self
+    }
+
+    /// Go through deletion without deleting anything.
+    ///
+    /// The target is resolved and checked as usual, and all events, reports and
+    /// [callbacks](GuardBuilder::before_delete) are as they would be, but nothing is deleted,
+    /// wiped, stopped or handed to a package manager, and deletion ends with [`Outcome::DryRun`](crate::Outcome::DryRun).
+    /// Meant for testing self-deleting executables in place, without copying them first. See
+    /// [`Guard::plan`] for what would be done, without dropping the guard.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// let (sender, receiver) = std::sync::mpsc::sync_channel(16);
+    /// drop(mortem::Guard::builder().dry_run(true).events(sender).build());
+    ///
+    /// let done = receiver.try_iter().find_map(|event| match event {
+    ///     mortem::Event::Done(report) => Some(report),
+    ///     _ => None,
+    /// });
+    /// assert_eq!(done.unwrap().outcome, mortem::Outcome::DryRun);
+    /// assert!(std::env::current_exe().unwrap().exists());
+    /// ```
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.config.dry_run = dry_run;
+        self
+    }
+
+    /// Also delete the paths handed over by the process that executed this one.
+    ///
+    /// They're removed before the target, as [additional paths](Guard::also_remove), and taken
+    /// out of the environment when the guard is built, so only the first guard inheriting them
+    /// does. Ignored under secure execution, such as for setuid executables. See
+    /// [`Guard::hand_over`].
+    pub fn inherit(mut self, inherit: bool) -> Self {
+        self.config.inherit = inherit;
+        self
+    }
+
+    /// Delete on a background thread when dropped, rather than blocking till deletion is done.
+    ///
+    /// Dropping returns right away, which keeps hard guards from adding visible latency to the
+    /// shutdown of short-lived tools. Exiting the process stops the thread, deletion or not, so
+    /// [join](crate::DeletionHandle::join) the guard's [handle](Guard::handle) before exiting, e.g. with
+    /// the guard dropped as soon as the work is done, and joined once the output is written. If
+    /// the thread can't be spawned, deletion blocks as usual.
+    pub fn detached(mut self, detached: bool) -> Self {
+        self.config.detached = detached;
+        self
+    }
+
+    /// Keep [wiping](GuardBuilder::wipe) only while `condition` holds.
+    ///
+    /// For battery powered devices, e.g. only while on mains power or the battery isn't low, so a
+    /// large wipe doesn't drain it at the worst moment. Checked before every 64 KiB written; once
+    /// it doesn't hold, wiping stops and the target is truncated instead, which is quick and leaves
+    /// nothing of it to run, before being deleted. [`Degradation::NotWiped`] is reported with
+    /// [`io::ErrorKind::Interrupted`].
+    pub fn wipe_while(mut self, condition: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        self.wipe_while = Some(Condition(Arc::new(condition)));
+        self
+    }
+
+    /// Bypass the page cache when [wiping](GuardBuilder::wipe).
+    ///
+    /// For very large targets, this keeps the wipe from evicting everything else from the page
+    /// cache, and makes the writes reach the device as they're made rather than at the final flush.
+    /// Uses `O_DIRECT` on Linux for all but the trailing partial block, falling back to buffered
+    /// writes on filesystems without support, and `F_NOCACHE` on macOS. Elsewhere, writes stay
+    /// buffered.
+    pub fn unbuffered_wipe(mut self, unbuffered: bool) -> Self {
+        self.config.unbuffered_wipe = unbuffered;
+        self
+    }
+
+    /// Limit [wiping](GuardBuilder::wipe) to `bytes_per_second`, so wiping large targets or
+    /// [trees](crate::Guard::also_remove_dir_all) doesn't saturate the disk on shared hosts.
+    ///
+    /// Writes are paced by sleeping between them, so deletion takes correspondingly longer; pair
+    /// this with a [deadline](Self::deadline) where exiting in time matters more.
+    pub fn wipe_rate(mut self, bytes_per_second: u64) -> Self {
+        self.config.wipe_rate = Some(bytes_per_second);
+        self
+    }
+
+    /// Stop other processes running the target before deleting it.
+    ///
+    /// Deleting a running executable succeeds on Unix, but leaves its processes running; for
+    /// sibling or worker binaries, stopping them first is usually what's wanted. Processes are
+    /// matched by the identity of the file they run rather than its path, and are sent `SIGTERM`,
+    /// then `SIGKILL` once the grace period is up. The process of the guard itself is never
+    /// signalled. See [`Termination`].
+    ///
+    /// Only supported on Linux. If processes can't be found or survive, the target is deleted all
+    /// the same and [`Degradation::NotTerminated`] is reported. Processes are only stopped once
+    /// [unlocked](Self::i_understand_this_is_destructive).
+    pub fn terminate(mut self, termination: Termination) -> Self {
+        self.config.terminate = Some(termination);
+        self
+    }
+
+    /// Ask sibling instances, i.e. other processes running the target, to finish when deletion is
+    /// triggered.
+    ///
+    /// Unlike [stopping them](Self::terminate), the siblings are sent `SIGTERM` without being
+    /// waited for, so those handling it can wind down, running their own guards on the way out.
+    /// Siblings are matched by the identity of the file they run, and the process of the guard
+    /// itself is never signalled.
+    ///
+    /// Only supported on Linux. Elsewhere, or if siblings can't be found, the target is deleted
+    /// all the same and [`Degradation::NotBroadcast`] is reported. Siblings are only signalled once
+    /// [unlocked](Self::i_understand_this_is_destructive).
+    pub fn broadcast(mut self, broadcast: bool) -> Self {
+        self.config.broadcast = broadcast;
+        self
+    }
+
+    /// Unlock the [destructive operations](Destructive) of the guard, which reach beyond the
+    /// target.
+    ///
+    /// Without this, destructive operations that are configured are skipped, and reported as
+    /// [`Degradation::Locked`]. With it, each is recorded as [`Event::Authorized`] when the guard
+    /// is created or, for those requested later, when requested, and logged with the `tracing`
+    /// feature, leaving an audit trail of what was authorized.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use mortem::Termination;
+    ///
+    /// let _mortem = mortem::Guard::builder()
+    ///     .terminate(Termination::default())
+    ///     .i_understand_this_is_destructive()
+    ///     .build();
+    /// ```
+    pub fn i_understand_this_is_destructive(mut self) -> Self {
+        self.config.destructive = true;
+        self
+    }
+
+    /// What to do when the path of the host executable can't be resolved at deletion.
+    ///
+    /// By default, the path it had when the guard was built is deleted instead, without retrying.
+    /// Failing to resolve counts apart from failing to delete: it's retried as set here rather
+    /// than by the [retries](Retries) of deletion, and then either falls back or gives up, so hard
+    /// guards don't keep retrying where the path can never be resolved. See [`ResolutionPolicy`].
+    pub fn resolution_policy(mut self, policy: ResolutionPolicy) -> Self {
+        self.config.resolution = policy;
+        self
+    }
+
+    /// Give up deletion once it has been retried for `timeout`, rather than possibly blocking
+    /// forever.
+    ///
+    /// Meant for hard guards in services that must exit within the kill window of their
+    /// supervisor. The delay between attempts is cut short by the deadline. Once it passes, the
+    /// [fallback](Self::fallback) strategy is tried if set, and the deletion otherwise fails with
+    /// the error of the last attempt.
+    ///
+    /// Under a deadline, the target takes priority. [Wiping](Self::wipe) gets at most half of the
+    /// deadline, after which the target is truncated instead, leaving nothing of it to run, and
+    /// [additional paths](crate::Guard::also_remove) are removed after the target rather than
+    /// before, as long as the deadline hasn't passed. Those given up on are reported as
+    /// [`Degradation::NotRemoved`] with [`io::ErrorKind::TimedOut`].
+    pub fn deadline(mut self, timeout: Duration) -> Self {
+        self.config.deadline = Some(timeout);
+        self
+    }
+
+    /// Try `strategy` once when the [deadline](Self::deadline) passes, before giving up.
+    ///
+    /// E.g. [`Strategy::Schedule`] leaves the deletion to the service manager.
+    pub fn fallback(mut self, strategy: Strategy) -> Self {
+        self.config.fallback = Some(strategy);
+        self
+    }
+
+    /// Whether to delete the target when the guard drops while the thread is panicking.
+    ///
+    /// Disabling this keeps the executable around for post-mortem debugging of crashes, while it's
+    /// still deleted on regular exits. Applies to deletion from the panic hook as well. Defaults to
+    /// deleting regardless.
+    pub fn delete_on_panic(mut self, delete: bool) -> Self {
+        self.config.delete_on_panic = delete;
+        self
+    }
+
+    /// Only delete the target on its `runs`-th run, or any run after.
+    ///
+    /// Until then, guards are built [disarmed](Guard::disarm). Runs are counted as guards are
+    /// built, in the [state store](GuardBuilder::state), and forgotten once the target is deleted.
+    /// If the count can't be kept, the guard stays disarmed, and
+    /// [`Degradation::NoState`] is reported if the guard is run regardless. Along with
+    /// [`GuardBuilder::expires_after`], whichever limit is reached first applies.
+    ///
+    /// ### Usage
+    /// ```rust,no_run
+    /// // a trial that deletes itself on its tenth run
+    /// let _mortem = mortem::Guard::builder().max_runs(10).build();
+    /// ```
+    pub fn max_runs(mut self, runs: u32) -> Self {
+        self.config.max_runs = Some(runs);
+        self
+    }
+
+    /// Only delete the target once `after` passed since its first run.
+    ///
+    /// Kept track of as [`GuardBuilder::max_runs`] is, with the time of the first run kept at a
+    /// resolution of seconds. Along with it, whichever limit is reached first applies.
+    pub fn expires_after(mut self, after: Duration) -> Self {
+        self.config.expires_after = Some(after);
+        self
+    }
+
+    /// Keep track of [run limits](GuardBuilder::max_runs) in `store`.
+    ///
+    /// Defaults to [`StateDir::user`](crate::state::StateDir::user). See [`state`](crate::state)
+    /// for the stores provided.
+    pub fn state(mut self, store: impl Store + 'static) -> Self {
+        self.state = Some(SharedStore(Arc::new(store)));
+        self
+    }
+
+    /// Disable the guard when the environment variable `MORTEM_DISABLE` is set to `1`.
+    ///
+    /// An escape hatch for debugging self-deleting executables in the field without rebuilding
+    /// them. See [`respect_env_var`](Self::respect_env_var).
+    ///
+    /// ### Usage
+    /// ```rust
+    /// let _mortem = mortem::Guard::builder().respect_env().build();
+    /// # _mortem.disarm();
+    /// ```
+    pub fn respect_env(self) -> Self {
+        self.respect_env_var("MORTEM_DISABLE")
+    }
+
+    /// Disable the guard when the environment variable `name` is set to `1`.
+    ///
+    /// The variable is read when the guard is built. A disabled guard does nothing at all: deletion
+    /// doesn't run, not even when [triggered](Guard::trigger_handle) or
+    /// [rearmed](Guard::rearm), and its [plan](Guard::plan) is empty.
+    pub fn respect_env_var(mut self, name: impl Into<String>) -> Self {
+        self.config.disable_var = Some(name.into());
+        self
+    }
+
+    /// Report periodically while deletion keeps being retried, or `None` to stay quiet.
+    ///
+    /// Once deletion has been retried for longer than `interval`, and every `interval` after,
+    /// a `deletion-stalled` record with the error of the latest attempt is written to the
+    /// [reporter](Self::reporter), or a warning is logged with the `tracing` feature. To operators
+    /// watching an exiting process that seems hung, this tells why. Defaults to 30 seconds.
+    pub fn heartbeat(mut self, interval: Option<Duration>) -> Self {
+        self.config.heartbeat = interval;
+        self
+    }
+
+    /// Bound the time the whole of deletion takes when the guard drops, however much was
+    /// configured, so the latency of exiting the process is bounded.
+    ///
+    /// The budget is shared out between the steps of deletion: [steps before
+    /// deletion](Guard::run_before_delete) are started within the first quarter of it, and the
+    /// ones left after are skipped; [stopping other processes](Self::terminate) gets at most half of
+    /// what's left; attempts of deletion, and the delays between them, get the rest. Once it's
+    /// used up, deletion is given up with the error of the last attempt. Steps already running
+    /// can't be cut short, so a single slow step can still overrun it.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use std::time::{Duration, Instant};
+    ///
+    /// // a directory can't be deleted as a file, so a hard guard would block forever
+    /// let guard = mortem::Guard::builder()
+    ///     .ensure(true)
+    ///     .strategies([mortem::Strategy::Unlink])
+    ///     .target(std::env::temp_dir())
+    ///     .reporter(mortem::Reporter::Silent)
+    ///     .drop_budget(Duration::from_millis(100))
+    ///     .build();
+    ///
+    /// let started = Instant::now();
+    /// drop(guard);
+    /// assert!(started.elapsed() < Duration::from_secs(1));
+    /// ```
+    pub fn drop_budget(mut self, budget: Duration) -> Self {
+        self.config.drop_budget = Some(budget);
+        self
+    }
+
+    /// Positively confirm deletion through a filesystem event, rather than trusting the result of
+    /// the deletion call.
+    ///
+    /// Right before deleting, a short-lived inotify watch is installed on the directory of the
+    /// target, which must then report the name of the target removed from it; e.g. filesystems with
+    /// aggressive caching may claim success prematurely. Renaming the target away counts as removal.
+    ///
+    /// Only supported on Linux. If the watch can't be installed or the event isn't observed
+    /// in time, [`Degradation::Unconfirmed`] is reported.
+    #[cfg(feature = "watch")]
+    pub fn confirm_deletion(mut self, confirm: bool) -> Self {
+        self.config.confirm_deletion = confirm;
+        self
+    }
+
+    /// Re-execute the host executable from memory when the guard is built, and delete it right
+    /// away rather than once the guard drops.
+    ///
+    /// Building the guard copies the executable into an anonymous file and executes the copy in
+    /// place of the process, with the same arguments and environment. The guard built by the copy
+    /// deletes the executable on disk there and then, as configured, and has nothing left to do
+    /// when dropped. Everything before building the guard runs twice, so it's best built first
+    /// thing in `main`.
+    ///
+    /// Only supported on Linux, and only for the host executable. Refused under secure execution,
+    /// as the copy would run without the privileges of a setuid executable. If the executable
+    /// can't be re-executed, it's deleted when the guard drops as usual, and
+    /// [`Degradation::NotReexecuted`] is reported.
+    #[cfg(feature = "reexec-memfd")]
+    pub fn reexec_from_memory(mut self, reexec: bool) -> Self {
+        self.config.reexec_from_memory = reexec;
+        self
+    }
+
+    /// Strategies hard guards escalate through, in order.
+    ///
+    /// The first strategy is used by all guards, and by hard guards for their first few attempts.
+    /// Defaults to [`Strategy::Unlink`], [`Strategy::ClearAttributes`], then
+    /// [`Strategy::RenameAway`], and on Windows finally [`Strategy::Schedule`].
+    pub fn strategies(mut self, strategies: impl IntoIterator<Item = Strategy>) -> Self {
+        self.config.strategies = strategies.into_iter().collect();
+        self
+    }
+
+    /// Redact the target path when formatting the guard with [`Debug`](fmt::Debug) or
+    /// [`Display`](fmt::Display), showing a stable hash of it instead.
+    ///
+    /// For logging in privacy-sensitive deployments.
+    pub fn redact(mut self, redact: bool) -> Self {
+        self.config.redact = redact;
+        self
+    }
+
+    /// Hash paths in all logs, [reports](Report) and [reporter](Reporter) records.
+    ///
+    /// For tools running on customer machines under strict data handling agreements.
+    /// Paths are replaced by a stable hash of the form `<redacted:0123456789abcdef>`, so they can
+    /// still be correlated. Implies [redaction](Self::redact).
+    pub fn privacy(mut self, privacy: bool) -> Self {
+        self.config.privacy = privacy;
+        self
+    }
+
+    /// Configuration built so far.
+    pub fn config(&self) -> &GuardConfig {
+        &self.config
+    }
+
+    /// Create the guard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is invalid, as [`try_build`](Self::try_build) would fail.
+    pub fn build(self) -> Guard {
+        self.try_build()
+            .unwrap_or_else(|err| panic!("invalid guard configuration: {err}"))
+    }
+
+    /// Create the guard, failing with [`io::ErrorKind::InvalidInput`] if the configuration is
+    /// invalid, e.g. a [`TempNaming::Prefix`] that isn't part of a file name.
+    ///
+    /// For configuration that isn't fixed at compile time, such as a [`GuardConfig`] read from a
+    /// file.
+    pub fn try_build(self) -> io::Result<Guard> {
+        self.config.temp_naming.validate()?;
+        let GuardConfig {
+            retries,
+            delay,
+            backoff,
+            jitter,
+            mut strategies,
+            reporter,
+            target,
+            base_dir,
+            canonicalization,
+            source_policy,
+            setuid_policy,
+            multi_call_policy,
+            package_policy,
+            remove_nix_link,
+            debugger_policy,
+            verify_provenance,
+            temp_location,
+            temp_naming,
+            follow_renames,
+            hold_directory,
+            skip_if_replaced,
+            placeholder,
+            wrappers,
+            also_remove,
+            also_remove_dir_all,
+            hooks,
+            detect_wrapper,
+            redact,
+            privacy,
+            wipe,
+            overwrite_passes,
+            unbuffered_wipe,
+            wipe_rate,
+            mut terminate,
+            mut broadcast,
+            destructive,
+            resolution,
+            heartbeat,
+            drop_budget,
+            deadline,
+            fallback,
+            delete_on_panic,
+            max_runs,
+            expires_after,
+            dry_run,
+            disable_var,
+            inherit,
+            detached,
+            #[cfg(feature = "watch")]
+            confirm_deletion,
+            #[cfg(feature = "reexec-memfd")]
+            reexec_from_memory,
+        } = self.config;
+        let redact = redact || privacy;
+        let mut wrappers: Vec<_> = wrappers
+            .into_iter()
+            .map(|wrapper| resolve::resolve_relative(wrapper, base_dir.clone()))
+            .collect();
+        let mut extras: Vec<_> = (also_remove.into_iter().map(|path| (path, false)))
+            .chain(also_remove_dir_all.into_iter().map(|path| (path, true)))
+            .map(|(path, all)| (resolve::resolve_relative(path, base_dir.clone()), all))
+            .collect();
+        let mut target = target.map(|target| resolve::resolve_relative(target, base_dir));
+        let host = target.is_none();
+
+        // canonicalizing the host executable freezes its path
+        let canonicalization = match canonicalization {
+            Canonicalization::None => Canonicalization::None,
+            canonicalization => match original_path(&target) {
+                Ok(path) => {
+                    let (path, canonicalization) = resolve::canonicalize(path, canonicalization);
+                    target = Some(path);
+                    canonicalization
+                }
+                Err(_) => Canonicalization::None,
+            },
+        };
+        if strategies.is_empty() {
+            strategies.push(Strategy::Unlink);
+        }
+        #[cfg(feature = "tracing")]
+        debug!(
+            ?retries,
+            target = ?target.as_deref().map(|target| shown(target, privacy)),
+            ?canonicalization,
+            "creating mortem guard"
+        );
+
+        // guards are always constructed; features that can't be provided are recorded instead
+        let mut degraded = Vec::new();
+
+        let mut authorized = Vec::new();
+        for (configured, operation) in [
+            (terminate.is_some(), Destructive::Terminate),
+            (broadcast, Destructive::Broadcast),
+            (
+                extras.iter().any(|(_, all)| *all),
+                Destructive::RemoveDirAll,
+            ),
+        ] {
+            match (configured, destructive) {
+                (false, _) => {}
+                (true, true) => authorized.push(operation),
+                (true, false) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(
+                        ?operation,
+                        "destructive operation isn't unlocked; skipping it"
+                    );
+                    degraded.push(Degradation::Locked(operation));
+                }
+            }
+        }
+        if !destructive {
+            terminate = None;
+            broadcast = false;
+            extras.retain(|(_, all)| !all);
+        }
+
+        let disabled = disable_var
+            .as_ref()
+            .is_some_and(|var| env::var_os(var).is_some_and(|value| value == "1"));
+        #[cfg(feature = "tracing")]
+        if disabled {
+            debug!(
+                var = disable_var,
+                "disabled through the environment; deletion won't run"
+            );
+        }
+
+        // the copy deletes the executable it was made from as soon as its guard is built
+        #[cfg(feature = "reexec-memfd")]
+        let copy = match (reexec_from_memory && host).then(memfd::origin) {
+            Some(memfd::Origin::Disk) => {
+                let err = memfd::reexec();
+                #[cfg(feature = "tracing")]
+                warn!(%err, "failed to re-execute from memory");
+                degraded.push(Degradation::NotReexecuted(err.kind()));
+                false
+            }
+            Some(memfd::Origin::Copy(path)) => {
+                target = Some(path);
+                true
+            }
+            Some(memfd::Origin::Foreign) | None => false,
+        };
+
+        let handle = if let Some(handle) = self.handle {
+            Some(handle)
+        } else if follow_renames {
+            match original_path(&target).and_then(|path| Handle::open(&path)) {
+                Ok(handle) => Some(handle),
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(%err, "failed to open handle on target; renames won't be followed");
+                    degraded.push(Degradation::NoHandle(err.kind()));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let directory = if hold_directory {
+            match original_path(&target).and_then(|path| DirHandle::open(&path)) {
+                Ok(directory) => Some(directory),
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(%err, "failed to open handle on directory of target");
+                    degraded.push(Degradation::NoDirectoryHandle(err.kind()));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut refusal = None;
+        let mut wrapped = false;
+        if target.is_none() {
+            let sources = Sources::gather(handle.as_ref());
+            wrapped = sources.wrapper.is_some();
+            if let (true, Some(wrapper)) = (detect_wrapper, &sources.wrapper) {
+                #[cfg(feature = "tracing")]
+                debug!(wrapper = ?shown(wrapper, privacy), "launched through wrapper script");
+                wrappers.push(wrapper.clone());
+            }
+            if !sources.agree() {
+                #[cfg(feature = "tracing")]
+                if privacy {
+                    warn!(?source_policy, "sources of executable path disagree");
+                } else {
+                    warn!(
+                        ?sources,
+                        ?source_policy,
+                        "sources of executable path disagree"
+                    );
+                }
+                let preferred = match source_policy {
+                    SourcePolicy::Abort => None,
+                    SourcePolicy::PreferHandle => sources.handle,
+                    SourcePolicy::PreferProcfs => sources.procfs,
+                };
+                match preferred {
+                    Some(path) => target = Some(path),
+                    None => refusal = Some(Refusal::SourcesDisagree),
+                }
+            }
+        }
+
+        let nix_link = match original_path(&target) {
+            Ok(path) if resolve::is_nix_store(&path) => {
+                #[cfg(feature = "tracing")]
+                warn!("target lies in the nix store; refusing deletion");
+                refusal = refusal.or(Some(Refusal::NixStore));
+                (remove_nix_link && host)
+                    .then(env::args_os)
+                    .and_then(|mut args| args.next())
+                    .and_then(|invoked| resolve::nix_link(&invoked))
+                    .filter(|link| resolve::same_file(link, &path))
+            }
+            _ => None,
+        };
+
+        let setuid = original_path(&target)
+            .map(|path| resolve::is_setuid(&path))
+            .unwrap_or(false)
+            .then_some(setuid_policy);
+        if setuid == Some(SetuidPolicy::Refuse) {
+            refusal = refusal.or(Some(Refusal::Setuid));
+        }
+
+        if multi_call_policy != MultiCallPolicy::Proceed {
+            // only the host executable was run under a name, and a wrapper passes on its own
+            let invoked = (multi_call_policy == MultiCallPolicy::RefuseRenamed && host && !wrapped)
+                .then(env::args_os)
+                .and_then(|mut args| args.next());
+            let multi_call = original_path(&target).is_ok_and(|path| {
+                resolve::is_multi_call(&path)
+                    || invoked.is_some() && resolve::is_renamed(&path, invoked.as_deref())
+            });
+            if multi_call {
+                #[cfg(feature = "tracing")]
+                warn!(
+                    ?invoked,
+                    "target looks like a multi-call binary; refusing deletion"
+                );
+                refusal = refusal.or(Some(Refusal::MultiCall));
+            }
+        }
+
+        let package = match package_policy {
+            PackagePolicy::Ignore => None,
+            policy => original_path(&target)
+                .ok()
+                .and_then(|path| resolve::package_owner(&path))
+                .map(|package| (package, policy)),
+        };
+        let delegate = match package {
+            Some((_package, PackagePolicy::Refuse)) => {
+                #[cfg(feature = "tracing")]
+                warn!(package = _package, "target is packaged; refusing deletion");
+                refusal = refusal.or(Some(Refusal::Packaged));
+                None
+            }
+            Some((package, PackagePolicy::Delegate(command))) => Some((package, command)),
+            _ => None,
+        };
+
+        let inherited = match inherit {
+            true => inherit::take(),
+            false => Vec::new(),
+        };
+
+        let build_id = match verify_provenance.then(provenance::own) {
+            Some(Ok(build_id)) => Some(build_id),
+            Some(Err(_err)) => {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to identify own build; refusing deletion");
+                refusal = refusal.or(Some(Refusal::Provenance));
+                None
+            }
+            None => None,
+        };
+
+        let identity = if skip_if_replaced {
+            let identity = original_path(&target).and_then(|path| FileId::of(&path));
+            #[cfg(feature = "tracing")]
+            if let Err(err) = &identity {
+                warn!(%err, "failed to identify target; replacements won't be detected");
+            }
+            identity.ok()
+        } else {
+            None
+        };
+
+        let limits = Limits {
+            max_runs,
+            expires_after,
+        };
+        let (state, due) = if max_runs.is_some() || expires_after.is_some() {
+            let store = (self.state.map(|store| store.0))
+                .or_else(|| StateDir::user().map(|store| Arc::new(store) as Arc<dyn Store>))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory"));
+            let tracked = store.and_then(|store| {
+                let path = original_path(&target)?;
+                Ok(Tracked::new(store, crate::fnv1a(&path)))
+            });
+            match tracked.and_then(|tracked| Ok((tracked.run(limits)?, tracked))) {
+                Ok((due, tracked)) => {
+                    #[cfg(feature = "tracing")]
+                    if !due {
+                        debug!(?limits, "run limits not reached yet; disarming guard");
+                    }
+                    (Some(tracked), due)
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(%err, "failed to keep track of runs; disarming guard");
+                    degraded.push(Degradation::NoState(err.kind()));
+                    (None, false)
+                }
+            }
+        } else {
+            (None, true)
+        };
+
+        // prepared up front, so deletion by the original path neither allocates nor converts
+        let raw = original_path(&target)
+            .and_then(|path| RawPath::new(&path))
+            .ok();
+        report::reserve();
+
+        let inner = Arc::new(Inner {
+            pending: AtomicBool::new(true),
+            armed: AtomicBool::new(due),
+            capture: AtomicBool::new(false),
+            captured: Mutex::new(None),
+            hooked: AtomicBool::new(false),
+            retries,
+            delay,
+            backoff,
+            jitter,
+            strategies,
+            reporter: reporter.unwrap_or_else(Reporter::default_for_build),
+            target,
+            canonicalization,
+            handle,
+            directory: directory.map_or_else(OnceLock::new, OnceLock::from),
+            identity,
+            placeholder,
+            wrappers,
+            refusal,
+            setuid,
+            degraded: Mutex::new(degraded),
+            events: self.events,
+            dropped_events: AtomicU64::new(0),
+            condition: self.condition,
+            wipe_while: self.wipe_while,
+            callbacks: self.callbacks,
+            hooks,
+            state,
+            #[cfg(feature = "seal")]
+            seal_key: self.seal_key,
+            temp_location,
+            temp_naming,
+            moved: Mutex::new(None),
+            redact,
+            privacy,
+            wine: platform::is_wine(),
+            wipe,
+            overwrite_passes,
+            unbuffered_wipe,
+            wipe_rate,
+            wiped: AtomicBool::new(false),
+            teardown: Mutex::new(Vec::new()),
+            panic_reports: Mutex::new(Vec::new()),
+            extras: Mutex::new(
+                (inherited.iter().map(|path| (path.clone(), false)))
+                    .chain(extras)
+                    .collect(),
+            ),
+            detached,
+            completion: Mutex::new(None),
+            inherited,
+            terminate,
+            broadcast,
+            destructive,
+            resolution,
+            heartbeat,
+            drop_budget,
+            deadline,
+            fallback,
+            delete_on_panic,
+            dry_run,
+            disabled,
+            #[cfg(feature = "watch")]
+            confirm_deletion,
+            build_id,
+            delegate,
+            nix_link,
+            debugger_policy,
+            debugged: AtomicBool::new(false),
+            host,
+            raw,
+            watchdog: Mutex::new(None),
+        });
+        if host {
+            let mut host = HOST.lock().unwrap_or_else(PoisonError::into_inner);
+            host.guards.retain(|guard| guard.strong_count() > 0);
+            host.guards.push(Arc::downgrade(&inner));
+            #[cfg(feature = "tracing")]
+            if host.defers(&inner) {
+                debug!("executable is already guarded; this guard won't delete it");
+            }
+        }
+        inner.emit(Event::Armed);
+        for operation in authorized {
+            inner.authorize(operation);
+        }
+        #[cfg(panic = "abort")]
+        arm_panic_hook(&inner);
+        if debugger_policy == DebuggerPolicy::Delete && inner.debugged() {
+            let _ = inner.run();
+        }
+
+        #[cfg(feature = "reexec-memfd")]
+        if copy {
+            let _ = inner.run();
+        }
+
+        Ok(Guard { inner })
+    }
+}
+
+/// Condition for wiping to go on; see [`GuardBuilder::wipe_while`].
+#[derive(Clone)]
+pub(crate) struct Condition(pub(crate) Arc<dyn Fn() -> bool + Send + Sync>);
+
+impl fmt::Debug for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Condition")
+    }
+}
+
+/// Store shared between builders; see [`GuardBuilder::state`].
+#[derive(Clone)]
+struct SharedStore(Arc<dyn Store>);
+
+impl fmt::Debug for SharedStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Store")
+    }
+}
+
+/// Callbacks around deletion; see [`GuardBuilder::before_delete`], [`GuardBuilder::on_success`]
+/// and [`GuardBuilder::on_failure`].
+#[derive(Clone, Default)]
+pub(crate) struct Callbacks {
+    pub(crate) before_delete: Option<Callback<Path>>,
+    pub(crate) on_success: Option<Callback<Report>>,
+    pub(crate) on_failure: Option<Once<dyn FnOnce(Error) + Send>>,
+}
+
+/// Callback borrowing what it's called with.
+pub(crate) type Callback<T> = Once<dyn FnOnce(&T) + Send>;
+
+impl fmt::Debug for Callbacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Callbacks")
+            .field("before_delete", &self.before_delete.is_some())
+            .field("on_success", &self.on_success.is_some())
+            .field("on_failure", &self.on_failure.is_some())
+            .finish()
+    }
+}
+
+/// Function called at most once, shared between clones of the builder it was passed to.
+pub(crate) struct Once<F: ?Sized>(Arc<Mutex<Option<Box<F>>>>);
+
+impl<F: ?Sized> Once<F> {
+    pub(crate) fn new(function: Box<F>) -> Self {
+        Once(Arc::new(Mutex::new(Some(function))))
+    }
+
+    /// Take the function to call it, unless it was already.
+    pub(crate) fn take(&self) -> Option<Box<F>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).take()
+    }
+}
+
+impl<F: ?Sized> Clone for Once<F> {
+    fn clone(&self) -> Self {
+        Once(Arc::clone(&self.0))
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Once<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Once")
+    }
+}
@@ -0,0 +1,716 @@
+//! Declarative guard configuration.
+
+use crate::{Reporter, Strategy};
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Retry behaviour of a guard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Retries {
+    /// Try once.
+    #[default]
+    Never,
+    /// Retry up to the given number of times.
+    Limit(u32),
+    /// Retry till deleted.
+    Forever,
+}
+
+/// How the delay between attempts grows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Backoff {
+    /// Wait the same delay between all attempts.
+    #[default]
+    Fixed,
+    /// Double the delay after each attempt, up to `max`.
+    Exponential {
+        /// Longest delay between attempts.
+        max: Duration,
+    },
+}
+
+/// How a guard retries deletion.
+///
+/// See [`GuardBuilder::retry_policy`](crate::GuardBuilder::retry_policy).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+#[non_exhaustive]
+pub struct RetryPolicy {
+    /// How often to retry.
+    pub retries: Retries,
+    /// Delay before the first retry.
+    pub delay: Duration,
+    /// How the delay grows with further retries.
+    pub backoff: Backoff,
+    /// Randomize each delay between half and all of it, so processes failing together don't
+    /// retry in lockstep.
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Retry till deleted, starting at `delay` and doubling it up to `max`.
+    pub const fn exponential(delay: Duration, max: Duration) -> Self {
+        RetryPolicy {
+            retries: Retries::Forever,
+            delay,
+            backoff: Backoff::Exponential { max },
+            jitter: false,
+        }
+    }
+}
+
+/// Preset behaviour of a guard.
+///
+/// See [`Guard::with_mode`](crate::Guard::with_mode).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Mode {
+    /// Try once, giving up quietly on failure.
+    ///
+    /// See [`soft`](crate::soft).
+    Soft,
+    /// Retry up to `retries` times, waiting `delay` between attempts, then give up quietly.
+    ///
+    /// See [`bounded`](crate::bounded).
+    Bounded {
+        /// Number of retries.
+        retries: u32,
+        /// Delay between attempts.
+        delay: Duration,
+    },
+    /// Retry till deleted, escalating through the default strategies and backing off
+    /// exponentially between attempts.
+    ///
+    /// See [`hard`](crate::hard).
+    Hard,
+    /// Like [`Mode::Hard`], but overwrite the contents of the target before deleting it.
+    ///
+    /// The running host executable is deleted without being wiped on Linux and Windows; see
+    /// [`GuardBuilder::wipe`](crate::GuardBuilder::wipe).
+    Secure,
+    /// Retry till deleted, escalating through the given strategies.
+    Custom(Vec<Strategy>),
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Soft => "soft",
+            Mode::Bounded { .. } => "bounded",
+            Mode::Hard => "hard",
+            Mode::Secure => "secure",
+            Mode::Custom(_) => "custom",
+        })
+    }
+}
+
+/// How to stop other processes running the target before deleting it.
+///
+/// See [`GuardBuilder::terminate`](crate::GuardBuilder::terminate).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+#[non_exhaustive]
+pub struct Termination {
+    /// Time given to processes to exit after `SIGTERM`, before they're sent `SIGKILL`.
+    pub grace: Duration,
+    /// Time given to processes to exit after `SIGKILL`, before deleting regardless.
+    pub kill_timeout: Duration,
+}
+
+impl Default for Termination {
+    fn default() -> Self {
+        Termination {
+            grace: Duration::from_secs(5),
+            kill_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// When a [hook](Hook) runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum HookPoint {
+    /// Right before deleting the target, as [`GuardBuilder::before_delete`] callbacks.
+    ///
+    /// [`GuardBuilder::before_delete`]: crate::GuardBuilder::before_delete
+    BeforeDelete,
+    /// Once deletion succeeded, as [`GuardBuilder::on_success`] callbacks.
+    ///
+    /// [`GuardBuilder::on_success`]: crate::GuardBuilder::on_success
+    OnSuccess,
+    /// Once deletion failed, as [`GuardBuilder::on_failure`] callbacks.
+    ///
+    /// [`GuardBuilder::on_failure`]: crate::GuardBuilder::on_failure
+    OnFailure,
+}
+
+impl HookPoint {
+    /// Name of the point, as hooks are told in `MORTEM_HOOK`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            HookPoint::BeforeDelete => "before_delete",
+            HookPoint::OnSuccess => "on_success",
+            HookPoint::OnFailure => "on_failure",
+        }
+    }
+}
+
+/// Program run around deletion, described declaratively so [configurations](GuardConfig) can
+/// carry it where closures can't go.
+///
+/// The program runs with `MORTEM_HOOK` set to the name of its [point](HookPoint) in snake case,
+/// and `MORTEM_TARGET` to the path of the target as reports show it, if it was resolved. The guard
+/// waits for it to exit; failing to start it, or it exiting unsuccessfully, is reported as
+/// [`Degradation::HookFailed`](crate::Degradation::HookFailed).
+///
+/// See [`GuardBuilder::hook`](crate::GuardBuilder::hook).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Hook {
+    /// When the program runs.
+    pub when: HookPoint,
+    /// Program to run, looked up in `PATH` unless it contains a separator.
+    pub program: PathBuf,
+    /// Arguments passed to the program.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub args: Vec<String>,
+}
+
+impl Hook {
+    /// Hook running `program` at `when`, without arguments.
+    pub fn new(when: HookPoint, program: impl Into<PathBuf>) -> Self {
+        Hook {
+            when,
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Pass `arg` to the program as well.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+/// What to do when the path of the host executable can't be resolved at deletion.
+///
+/// Resolving fails where its sources aren't available, e.g. in a sandbox without `/proc`. That
+/// rarely changes by trying again, so it's handled apart from failing to delete, and bounded even
+/// for guards retrying deletion [forever](Retries::Forever).
+///
+/// See [`GuardBuilder::resolution_policy`](crate::GuardBuilder::resolution_policy).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+#[non_exhaustive]
+pub struct ResolutionPolicy {
+    /// Times to retry resolving before falling back.
+    pub retries: u32,
+    /// Delay between retries.
+    pub delay: Duration,
+    /// Whether to fall back to the path the executable had when the guard was built, rather than
+    /// giving up.
+    ///
+    /// That path may since have been replaced by another file, if the executable was moved.
+    pub fall_back_to_original: bool,
+}
+
+impl Default for ResolutionPolicy {
+    fn default() -> Self {
+        ResolutionPolicy {
+            retries: 0,
+            delay: Duration::from_millis(100),
+            fall_back_to_original: true,
+        }
+    }
+}
+
+/// How the target path is canonicalized when the guard is built.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Canonicalization {
+    /// Use the path as is.
+    #[default]
+    None,
+    /// Remove `.` and `..` components, without consulting the filesystem.
+    ///
+    /// Note that `..` following a symlink is resolved differently by the filesystem.
+    Lexical,
+    /// Resolve symlinks as well as `.` and `..` components through the filesystem.
+    ///
+    /// Falls back to [`Canonicalization::Lexical`] if the path can't be resolved, e.g. because
+    /// it doesn't exist yet.
+    Full,
+}
+
+/// What to do when the sources of the host executable's path disagree.
+///
+/// The path is cross-checked between [`std::env::current_exe`], `/proc/self/exe` and the handle
+/// held when [following renames](crate::GuardBuilder::follow_renames), where available.
+/// Disagreement points at unusual launchers or the executable being swapped, in which case no
+/// single source should be trusted silently. `argv[0]` isn't cross-checked, as launchers set it
+/// freely and it's only resolved against the current directory or `PATH` after the fact.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum SourcePolicy {
+    /// Refuse to delete anything.
+    #[default]
+    Abort,
+    /// Delete the file the held handle refers to, if there is one; otherwise abort.
+    PreferHandle,
+    /// Delete the file `/proc/self/exe` refers to, if available; otherwise abort.
+    PreferProcfs,
+}
+
+/// What to do when the target has the setuid or setgid bit set.
+///
+/// Deleting such a binary is rarely intended from an unprivileged process, e.g. a non-root user
+/// removing a root-owned setuid tool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum SetuidPolicy {
+    /// Refuse to delete the target.
+    #[default]
+    Refuse,
+    /// Delete the target regardless.
+    Proceed,
+}
+
+/// What to do when the target looks like a multi-call binary.
+///
+/// Multi-call binaries, such as busybox, behave according to the name they're run as, and are
+/// installed under many names through links. Deleting the file removes every one of them. Targets
+/// with several hard links count as such; host executables run under another name than their file
+/// name only with [`MultiCallPolicy::RefuseRenamed`], as symlinks, renamed copies and `exec -a`
+/// run them under other names as well.
+///
+/// See [`resolve::is_multi_call`](crate::resolve::is_multi_call) and
+/// [`resolve::is_renamed`](crate::resolve::is_renamed).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum MultiCallPolicy {
+    /// Refuse to delete the target if it has several hard links.
+    #[default]
+    Refuse,
+    /// Refuse to delete the target if it has several hard links, or is the host executable and
+    /// was run under another name than its file name.
+    RefuseRenamed,
+    /// Delete the target regardless.
+    Proceed,
+}
+
+/// What to do when the target is owned by a system package, as installed through dpkg or rpm.
+///
+/// Deleting a packaged file behind the back of the package manager leaves its database
+/// inconsistent. Ownership is looked up with `dpkg-query -S` or `rpm -qf` when the guard is built,
+/// on Linux only.
+///
+/// See [`resolve::package_owner`](crate::resolve::package_owner).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum PackagePolicy {
+    /// Don't look up ownership, and delete the target like any other.
+    #[default]
+    Ignore,
+    /// Refuse to delete packaged targets.
+    Refuse,
+    /// Have the package manager remove packaged targets, through a command.
+    ///
+    /// The first element is the program, and the others its arguments, in which `{package}` and
+    /// `{path}` are replaced by the owning package and the target path, e.g.
+    /// `["apt-get", "remove", "--yes", "{package}"]`. Unpackaged targets are deleted like any
+    /// other.
+    Delegate(Vec<String>),
+}
+
+/// What to do when the process runs under a debugger.
+///
+/// Detected through the tracer of the process on Linux, and `IsDebuggerPresent` on Windows; see
+/// [`platform::is_debugged`](crate::platform::is_debugged).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum DebuggerPolicy {
+    /// Don't check for a debugger.
+    #[default]
+    Ignore,
+    /// Refuse to delete the target while debugged, keeping it around for the developer.
+    ///
+    /// Checked when deletion runs.
+    Skip,
+    /// Delete the target right away when debugged, in response to tampering.
+    ///
+    /// Checked when the guard is built; otherwise, deletion runs when the guard drops as usual.
+    Delete,
+}
+
+/// Where temporary files, such as targets [renamed away](crate::Strategy::RenameAway), go.
+///
+/// Renaming only works within a filesystem, so locations on another filesystem than the target
+/// make renaming fail.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum TempLocation {
+    /// The directory of the target.
+    #[default]
+    TargetDir,
+    /// The user's runtime directory, `$XDG_RUNTIME_DIR`, falling back to the directory of the
+    /// target if unset.
+    RuntimeDir,
+    /// A custom directory.
+    Custom(PathBuf),
+}
+
+/// How temporary files, such as targets [renamed away](crate::Strategy::RenameAway), are named.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum TempNaming {
+    /// Hidden name derived from the target, `.<name>.mortem-<pid>`.
+    #[default]
+    Hidden,
+    /// Random name, `.mortem-<random>`, revealing nothing about the target.
+    Random,
+    /// Name of the target behind a fixed prefix, `<prefix><name>`.
+    ///
+    /// The prefix mustn't contain path separators, `..` or NUL, so the target stays in the
+    /// [location](TempLocation) it's renamed away to.
+    Prefix(String),
+}
+
+impl TempNaming {
+    /// Fail if the prefix could take the name out of its directory, or isn't a valid file name.
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        let TempNaming::Prefix(prefix) = self else {
+            return Ok(());
+        };
+        if prefix.contains(std::path::is_separator)
+            || prefix.contains("..")
+            || prefix.contains('\0')
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid prefix of temporary names `{}`",
+                    prefix.escape_debug()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Destructive operation a guard only performs once
+/// [unlocked](crate::GuardBuilder::i_understand_this_is_destructive).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Destructive {
+    /// Stopping other processes running the target.
+    ///
+    /// See [`GuardBuilder::terminate`](crate::GuardBuilder::terminate).
+    Terminate,
+    /// Asking sibling instances of the target to finish.
+    ///
+    /// See [`GuardBuilder::broadcast`](crate::GuardBuilder::broadcast).
+    Broadcast,
+    /// Removing directories with all their contents.
+    ///
+    /// See [`Guard::also_remove_dir_all`](crate::Guard::also_remove_dir_all).
+    RemoveDirAll,
+}
+
+/// Full configuration of a guard.
+///
+/// With the `serde` feature, configurations can be (de)serialized, so e.g. orchestration systems
+/// can ship a configuration for a tool to feed straight into [`Guard::from_config`].
+/// Missing fields take their default values.
+///
+/// See [`GuardBuilder`] for what each field does.
+///
+/// [`Guard::from_config`]: crate::Guard::from_config
+/// [`GuardBuilder`]: crate::GuardBuilder
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+#[non_exhaustive]
+pub struct GuardConfig {
+    /// How often to retry deletion.
+    pub retries: Retries,
+    /// Delay between attempts.
+    pub delay: Duration,
+    /// How the delay between attempts grows.
+    pub backoff: Backoff,
+    /// Randomize the delay between attempts.
+    pub jitter: bool,
+    /// Strategies hard guards escalate through, in order. The first is used by all guards.
+    pub strategies: Vec<Strategy>,
+    /// Final-resort reporter, or `None` for the default.
+    pub reporter: Option<Reporter>,
+    /// Path to delete instead of the host executable.
+    pub target: Option<PathBuf>,
+    /// Directory to resolve a relative target against.
+    pub base_dir: Option<PathBuf>,
+    /// How to canonicalize the target path.
+    pub canonicalization: Canonicalization,
+    /// What to do when the sources of the host executable's path disagree.
+    pub source_policy: SourcePolicy,
+    /// What to do when the target has the setuid or setgid bit set.
+    pub setuid_policy: SetuidPolicy,
+    /// What to do when the target looks like a multi-call binary.
+    pub multi_call_policy: MultiCallPolicy,
+    /// What to do when the target is owned by a system package.
+    pub package_policy: PackagePolicy,
+    /// Remove the symlink the process was run through when the target lies in the Nix store.
+    pub remove_nix_link: bool,
+    /// What to do when the process runs under a debugger.
+    pub debugger_policy: DebuggerPolicy,
+    /// Only delete the target if it's the same build as the host executable.
+    pub verify_provenance: bool,
+    /// Where temporary files go.
+    pub temp_location: TempLocation,
+    /// How temporary files are named.
+    pub temp_naming: TempNaming,
+    /// Follow the target across renames.
+    pub follow_renames: bool,
+    /// Delete the target relative to a handle held on its directory.
+    pub hold_directory: bool,
+    /// Skip deletion if the target was replaced.
+    pub skip_if_replaced: bool,
+    /// Leave an immutable, empty placeholder at the path of the target once it's deleted.
+    pub placeholder: bool,
+    /// Wrapper scripts launching the target, deleted along with it.
+    pub wrappers: Vec<PathBuf>,
+    /// Additional files to remove before the target, as [`Guard::also_remove`].
+    ///
+    /// [`Guard::also_remove`]: crate::Guard::also_remove
+    pub also_remove: Vec<PathBuf>,
+    /// Additional directories to remove with all their contents before the target, as
+    /// [`Guard::also_remove_dir_all`].
+    ///
+    /// [`Guard::also_remove_dir_all`]: crate::Guard::also_remove_dir_all
+    pub also_remove_dir_all: Vec<PathBuf>,
+    /// Programs run around deletion.
+    pub hooks: Vec<Hook>,
+    /// Detect a wrapper script the host executable was launched through.
+    pub detect_wrapper: bool,
+    /// Redact the target path when formatting the guard.
+    pub redact: bool,
+    /// Hash paths in all logs, reports and records.
+    pub privacy: bool,
+    /// Overwrite the contents of the target before deleting it.
+    pub wipe: bool,
+    /// Number of times to overwrite the contents of the target when wiping.
+    pub overwrite_passes: u32,
+    /// Bypass the page cache when wiping.
+    pub unbuffered_wipe: bool,
+    /// Bytes per second wiping is limited to, or `None` for no limit.
+    pub wipe_rate: Option<u64>,
+    /// Stop other processes running the target before deleting it.
+    pub terminate: Option<Termination>,
+    /// Ask sibling instances of the target to finish when deletion is triggered.
+    pub broadcast: bool,
+    /// Allow [destructive operations](Destructive).
+    pub destructive: bool,
+    /// What to do when the path of the host executable can't be resolved at deletion.
+    pub resolution: ResolutionPolicy,
+    /// Interval between reports while deletion keeps being retried, or `None` to stay quiet.
+    pub heartbeat: Option<Duration>,
+    /// Time the whole of deletion may take when the guard drops, or `None` for no bound.
+    pub drop_budget: Option<Duration>,
+    /// Time after which deletion is given up.
+    pub deadline: Option<Duration>,
+    /// Strategy tried once the deadline passed.
+    pub fallback: Option<Strategy>,
+    /// Delete the target when the guard drops while the thread is panicking.
+    pub delete_on_panic: bool,
+    /// Number of runs on which the target is deleted, or `None` for no limit.
+    pub max_runs: Option<u32>,
+    /// Time since the first run after which the target is deleted, or `None` for no limit.
+    pub expires_after: Option<Duration>,
+    /// Environment variable disabling the guard when set to `1`, or `None` to ignore the
+    /// environment.
+    pub disable_var: Option<String>,
+    /// Go through deletion without deleting anything.
+    pub dry_run: bool,
+    /// Also delete the paths handed over by the process that executed this one.
+    pub inherit: bool,
+    /// Delete on a background thread when dropped, rather than blocking.
+    pub detached: bool,
+    /// Confirm deletion through filesystem events.
+    #[cfg(feature = "watch")]
+    pub confirm_deletion: bool,
+    /// Re-execute the host executable from memory when the guard is built, deleting it right away.
+    #[cfg(feature = "reexec-memfd")]
+    pub reexec_from_memory: bool,
+}
+
+impl Default for GuardConfig {
+    fn default() -> Self {
+        GuardConfig {
+            retries: Retries::default(),
+            delay: Duration::ZERO,
+            backoff: Backoff::default(),
+            jitter: false,
+            strategies: Strategy::ESCALATION.to_vec(),
+            reporter: None,
+            target: None,
+            base_dir: None,
+            canonicalization: Canonicalization::default(),
+            source_policy: SourcePolicy::default(),
+            setuid_policy: SetuidPolicy::default(),
+            multi_call_policy: MultiCallPolicy::default(),
+            package_policy: PackagePolicy::default(),
+            remove_nix_link: false,
+            debugger_policy: DebuggerPolicy::default(),
+            verify_provenance: false,
+            temp_location: TempLocation::default(),
+            temp_naming: TempNaming::default(),
+            follow_renames: false,
+            hold_directory: false,
+            skip_if_replaced: false,
+            placeholder: false,
+            wrappers: Vec::new(),
+            also_remove: Vec::new(),
+            also_remove_dir_all: Vec::new(),
+            hooks: Vec::new(),
+            detect_wrapper: false,
+            redact: false,
+            privacy: false,
+            wipe: false,
+            overwrite_passes: 1,
+            unbuffered_wipe: false,
+            wipe_rate: None,
+            terminate: None,
+            broadcast: false,
+            destructive: false,
+            resolution: ResolutionPolicy::default(),
+            heartbeat: Some(Duration::from_secs(30)),
+            drop_budget: None,
+            deadline: None,
+            fallback: None,
+            delete_on_panic: true,
+            max_runs: None,
+            expires_after: None,
+            disable_var: None,
+            dry_run: false,
+            inherit: false,
+            detached: false,
+            #[cfg(feature = "watch")]
+            confirm_deletion: false,
+            #[cfg(feature = "reexec-memfd")]
+            reexec_from_memory: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GuardBuilder, TempNaming};
+
+    use std::io;
+
+    /// Builder of a guard targeting nothing, renaming away behind `prefix`.
+    fn builder(prefix: &str) -> GuardBuilder {
+        GuardBuilder::new()
+            .target(std::env::temp_dir().join("mortem-unit-test"))
+            .temp_naming(TempNaming::Prefix(prefix.into()))
+    }
+
+    #[test]
+    fn rejects_prefixes_leaving_the_directory() {
+        for prefix in ["../", "/tmp/", "sub/", "..", "a..b", "nul\0"] {
+            let err = builder(prefix).try_build().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{prefix:?}");
+        }
+        builder(".mortem-").try_build().unwrap().disarm();
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid guard configuration")]
+    fn build_panics_on_invalid_prefix() {
+        builder("../").build().disarm();
+    }
+}
@@ -0,0 +1,1720 @@
+//! Deletion pipeline shared by guards, from preparing the target through the attempts to reporting
+//! the outcome.
+
+use crate::builder::{Callbacks, Condition, Once};
+use crate::handle::{DirHandle, FileId, Handle};
+use crate::host::HOST;
+use crate::raw::RawPath;
+use crate::state::Tracked;
+use crate::{
+    original_path, platform, process, provenance, report, shown, strategy, watchdog, Backoff,
+    Canonicalization, Completion, DebuggerPolicy, Degradation, Destructive, Event, Hook, HookPoint,
+    Outcome, Refusal, Report, Reporter, ResolutionPolicy, Retries, SetuidPolicy, Strategy,
+    TempLocation, TempNaming, Termination,
+};
+
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::env::current_exe;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::ops::ControlFlow;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "watch")]
+use crate::watch;
+#[cfg(feature = "tracing")]
+use tracing::{debug, error, warn};
+
+/// Time the whole of deletion may take, shared out between its steps.
+struct Budget {
+    started: Instant,
+    total: Duration,
+}
+
+impl Budget {
+    fn new(total: Duration) -> Self {
+        Budget {
+            started: Instant::now(),
+            total,
+        }
+    }
+
+    fn spent(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn remaining(&self) -> Duration {
+        self.total.saturating_sub(self.spent())
+    }
+}
+
+/// Deletion in progress, between attempts.
+pub(crate) struct Deletion {
+    /// Number of attempts made so far.
+    pub(crate) attempts: u32,
+    /// Strategy of the last attempt.
+    strategy: Strategy,
+    started: Instant,
+    /// Elapsed time at which to report still retrying next.
+    next_beat: Option<Duration>,
+    /// Time the whole of deletion may take.
+    budget: Option<Budget>,
+    /// Number of times the path of the host executable couldn't be resolved.
+    unresolved: u32,
+    /// Additional paths left till after the target, under a deadline.
+    extras: Vec<(PathBuf, bool)>,
+    /// Watch confirming deletion.
+    #[cfg(feature = "watch")]
+    watch: Option<watch::Watch>,
+}
+
+/// What follows an attempt of deletion.
+pub(crate) enum Step<'a> {
+    /// Retry after the delay.
+    Retry(Duration),
+    /// Deletion ended on the path, with the result.
+    Done(Option<Cow<'a, Path>>, io::Result<Outcome>),
+}
+
+/// State of a guard, shared with the panic hook in `panic = "abort"` builds.
+pub(crate) struct Inner {
+    /// Whether deletion still has to run.
+    pub(crate) pending: AtomicBool,
+    /// Whether deletion runs at all; see [`Guard::disarm`].
+    pub(crate) armed: AtomicBool,
+    /// Whether to keep the report of deletion for [`Guard::execute`].
+    pub(crate) capture: AtomicBool,
+    /// Report kept for [`Guard::execute`].
+    pub(crate) captured: Mutex<Option<Report>>,
+    /// Whether deletion runs from the panic hook; see [`Guard::arm_panic_hook`].
+    pub(crate) hooked: AtomicBool,
+    /// Condition for deletion to run, evaluated once, when it would.
+    pub(crate) condition: Option<Once<dyn FnOnce() -> bool + Send>>,
+    /// Condition for wiping to go on, checked before every write.
+    pub(crate) wipe_while: Option<Condition>,
+    pub(crate) callbacks: Callbacks,
+    /// Programs run around deletion.
+    pub(crate) hooks: Vec<Hook>,
+    /// State kept across runs for run limits, forgotten once the target is deleted.
+    pub(crate) state: Option<Tracked>,
+    /// Key what's left on disk for later runs is sealed with.
+    #[cfg(feature = "seal")]
+    pub(crate) seal_key: Option<crate::seal::Key>,
+    /// How often to retry deletion.
+    pub(crate) retries: Retries,
+    /// Delay between attempts.
+    pub(crate) delay: Duration,
+    /// How the delay between attempts grows.
+    pub(crate) backoff: Backoff,
+    /// Randomize the delay between attempts.
+    pub(crate) jitter: bool,
+    /// Strategies to escalate through; never empty.
+    pub(crate) strategies: Vec<Strategy>,
+    /// Reporter used when deletion fails.
+    pub(crate) reporter: Reporter,
+    /// Path to delete instead of the host executable.
+    pub(crate) target: Option<PathBuf>,
+    /// Canonicalization applied to the target.
+    pub(crate) canonicalization: Canonicalization,
+    /// Handle used to follow the target across renames.
+    pub(crate) handle: Option<Handle>,
+    /// Handle on the directory of the target, to delete it relative to; opened at creation or by
+    /// [`Guard::prepare`].
+    pub(crate) directory: OnceLock<DirHandle>,
+    /// Identity of the target at creation, used to detect it being replaced.
+    pub(crate) identity: Option<FileId>,
+    /// Leave a placeholder at the path of the target once it's deleted.
+    pub(crate) placeholder: bool,
+    /// Wrapper scripts launching the target, deleted along with it.
+    pub(crate) wrappers: Vec<PathBuf>,
+    /// Reason deletion is refused, if it is.
+    pub(crate) refusal: Option<Refusal>,
+    /// Setuid policy applied, if the target is setuid or setgid.
+    pub(crate) setuid: Option<SetuidPolicy>,
+    /// Features the guard had to do without.
+    pub(crate) degraded: Mutex<Vec<Degradation>>,
+    /// Receiver of lifecycle events.
+    pub(crate) events: Option<SyncSender<Event>>,
+    /// Number of events dropped as the channel was full.
+    pub(crate) dropped_events: AtomicU64,
+    /// Where temporary files go.
+    pub(crate) temp_location: TempLocation,
+    /// How temporary files are named.
+    pub(crate) temp_naming: TempNaming,
+    /// Path the target was renamed away to.
+    pub(crate) moved: Mutex<Option<PathBuf>>,
+    /// Redact the target path when formatting.
+    pub(crate) redact: bool,
+    /// Hash paths in all logs and reports.
+    pub(crate) privacy: bool,
+    /// Whether the process runs under Wine.
+    pub(crate) wine: bool,
+    /// Overwrite the contents of the target before deleting it.
+    pub(crate) wipe: bool,
+    /// Number of times to overwrite the contents of the target.
+    pub(crate) overwrite_passes: u32,
+    /// Bypass the page cache when wiping.
+    pub(crate) unbuffered_wipe: bool,
+    /// Bytes per second wiping is limited to.
+    pub(crate) wipe_rate: Option<u64>,
+    /// Whether wiping was attempted.
+    pub(crate) wiped: AtomicBool,
+    /// How to stop other processes running the target.
+    pub(crate) terminate: Option<Termination>,
+    /// Ask sibling instances of the target to finish.
+    pub(crate) broadcast: bool,
+    /// Allow destructive operations.
+    pub(crate) destructive: bool,
+    /// What to do when the path of the host executable can't be resolved.
+    pub(crate) resolution: ResolutionPolicy,
+    /// Interval between reports while deletion keeps being retried.
+    pub(crate) heartbeat: Option<Duration>,
+    /// Time the whole of deletion may take.
+    pub(crate) drop_budget: Option<Duration>,
+    /// Time after which deletion is given up.
+    pub(crate) deadline: Option<Duration>,
+    /// Strategy tried once the deadline passed.
+    pub(crate) fallback: Option<Strategy>,
+    /// Delete while the thread is panicking.
+    pub(crate) delete_on_panic: bool,
+    /// Go through deletion without deleting anything.
+    pub(crate) dry_run: bool,
+    /// Disabled through the environment.
+    pub(crate) disabled: bool,
+    /// Confirm deletion through filesystem events.
+    #[cfg(feature = "watch")]
+    pub(crate) confirm_deletion: bool,
+    /// Steps to run right before deletion, in order.
+    pub(crate) teardown: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+    /// Crash reports to delete along with the target.
+    pub(crate) panic_reports: Mutex<Vec<PathBuf>>,
+    /// Additional paths to remove before the target, and whether they're directories.
+    pub(crate) extras: Mutex<Vec<(PathBuf, bool)>>,
+    /// Paths handed over by the process that executed this one, also among the additional paths.
+    pub(crate) inherited: Vec<PathBuf>,
+    /// Delete on a background thread when dropped.
+    pub(crate) detached: bool,
+    /// Completion shared with deletion handles, once one was asked for.
+    pub(crate) completion: Mutex<Option<Arc<Completion>>>,
+    /// Build identifier the target must carry.
+    pub(crate) build_id: Option<Vec<u8>>,
+    /// Package owning the target, and the command template to have it removed with.
+    pub(crate) delegate: Option<(String, Vec<String>)>,
+    /// Symlink into the Nix store to remove when deletion is refused for the target lying there.
+    pub(crate) nix_link: Option<PathBuf>,
+    /// What to do when the process runs under a debugger.
+    pub(crate) debugger_policy: DebuggerPolicy,
+    /// Whether a debugger was detected.
+    pub(crate) debugged: AtomicBool,
+    /// Whether the target is the host executable, which only one guard deletes.
+    pub(crate) host: bool,
+    /// Path of the target as resolved at creation, prepared for system calls.
+    pub(crate) raw: Option<RawPath>,
+    /// Process deleting the target once this one exited, if spawned.
+    pub(crate) watchdog: Mutex<Option<watchdog::Watchdog>>,
+}
+
+impl Inner {
+    /// Whether deletion is retried till it succeeds.
+    pub(crate) fn ensure(&self) -> bool {
+        self.retries == Retries::Forever
+    }
+
+    /// Delay to wait out before retrying after `attempts` attempts since `started`, if retrying.
+    ///
+    /// The delay is cut short by the deadline and the end of the `budget`, after which deletion
+    /// isn't retried anymore.
+    fn retry(&self, attempts: u32, started: Instant, budget: Option<&Budget>) -> Option<Duration> {
+        let retry = match self.retries {
+            Retries::Never => false,
+            Retries::Limit(retries) => attempts <= retries,
+            Retries::Forever => true,
+        };
+        let remaining = self
+            .deadline
+            .map(|deadline| deadline.saturating_sub(started.elapsed()));
+        if retry && remaining == Some(Duration::ZERO) {
+            #[cfg(feature = "tracing")]
+            warn!(attempts, "deadline passed; giving up deletion");
+            return None;
+        }
+        let delay = self.delay(attempts);
+        let delay = remaining.map_or(delay, |remaining| remaining.min(delay));
+        let delay = match budget.map(Budget::remaining) {
+            Some(Duration::ZERO) => return None,
+            Some(remaining) => delay.min(remaining),
+            None => delay,
+        };
+        retry.then_some(delay)
+    }
+
+    /// Delay after `attempts` attempts, with backoff and jitter applied.
+    pub(crate) fn delay(&self, attempts: u32) -> Duration {
+        let delay = match self.backoff {
+            Backoff::Fixed => self.delay,
+            Backoff::Exponential { max } => {
+                let doublings = attempts.saturating_sub(1).min(31);
+                self.delay.saturating_mul(1 << doublings).min(max)
+            }
+        };
+        if !self.jitter {
+            return delay;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        let fraction = (random >> 11) as f64 / (1u64 << 53) as f64;
+        delay / 2 + delay.mul_f64(fraction) / 2
+    }
+
+    /// Whether deletion is yet to run, and the guard is armed.
+    pub(crate) fn will_delete(&self) -> bool {
+        self.armed.load(Ordering::SeqCst) && self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Run deletion, unless it already ran or the guard is disarmed.
+    pub(crate) fn run(&self) -> Option<io::Result<()>> {
+        self.claim().then(|| self.delete(None))
+    }
+
+    /// Run deletion as [`run`](Self::run) does, giving it up after `budget` at the latest.
+    pub(crate) fn run_within(&self, budget: Duration) -> Option<io::Result<()>> {
+        self.claim().then(|| self.delete(Some(budget)))
+    }
+
+    /// Whether another guard deletes the host executable.
+    pub(crate) fn duplicate(&self) -> bool {
+        self.host
+            && HOST
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .defers(self)
+    }
+
+    /// Whether deletion is due, marking it as no longer pending if so.
+    pub(crate) fn claim(&self) -> bool {
+        if self.disabled {
+            return false;
+        }
+        if !self.armed.load(Ordering::SeqCst) {
+            #[cfg(feature = "tracing")]
+            debug!("guard is disarmed; skipping deletion");
+            return false;
+        }
+        if !self.delete_on_panic && thread::panicking() {
+            #[cfg(feature = "tracing")]
+            debug!("thread is panicking; keeping the target for post-mortem debugging");
+            return false;
+        }
+        // taken by a guard built from a clone of the same builder, the condition isn't known to hold
+        if self
+            .condition
+            .as_ref()
+            .is_some_and(|condition| !condition.take().is_some_and(|condition| condition()))
+        {
+            #[cfg(feature = "tracing")]
+            debug!("condition doesn't hold; skipping deletion");
+            return false;
+        }
+        if self.host {
+            let mut host = HOST.lock().unwrap_or_else(PoisonError::into_inner);
+            if host.defers(self) {
+                #[cfg(feature = "tracing")]
+                debug!("executable is guarded by another guard; skipping deletion");
+                return false;
+            }
+            let claimed = self.pending.swap(false, Ordering::SeqCst);
+            host.claimed |= claimed;
+            return claimed;
+        }
+        self.pending.swap(false, Ordering::SeqCst)
+    }
+
+    /// Path to delete, or `None` if the followed target has already been deleted.
+    ///
+    /// Borrows the path prepared at creation where possible, so the default path doesn't allocate.
+    pub(crate) fn path(&self) -> io::Result<Option<Cow<'_, Path>>> {
+        if let Some(moved) = self.moved.lock().unwrap().clone() {
+            return Ok(Some(Cow::Owned(moved)));
+        }
+
+        if let Some(handle) = &self.handle {
+            match handle.path() {
+                Ok(path) => return Ok(path.map(Cow::Owned)),
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(err = %_err, "failed to follow target; using original path");
+                }
+            }
+        }
+
+        match (&self.target, &self.raw) {
+            (Some(target), _) => Ok(Some(Cow::Borrowed(target))),
+            (None, Some(raw)) if raw.is_current_exe() => Ok(Some(Cow::Borrowed(raw.path()))),
+            (None, _) => current_exe().map(|path| Some(Cow::Owned(path))),
+        }
+    }
+
+    /// Owned copy of `path`, using the one prepared at creation if it's the original path.
+    fn owned(&self, path: Cow<'_, Path>) -> PathBuf {
+        match (path, &self.raw) {
+            (Cow::Borrowed(path), Some(raw)) if raw.matches(path) => raw.to_path_buf(),
+            (path, _) => path.into_owned(),
+        }
+    }
+
+    /// Whether the target isn't the build it's required to be.
+    fn foreign(&self) -> bool {
+        let Some(expected) = &self.build_id else {
+            return false;
+        };
+        let build_id = match self.path() {
+            Ok(Some(path)) => provenance::of(&path),
+            // nothing there to mistake for the target
+            Ok(None) => return false,
+            Err(err) => Err(err),
+        };
+        match build_id {
+            Ok(build_id) => build_id != *expected,
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to identify build of target");
+                true
+            }
+        }
+    }
+
+    /// Whether the process runs under a debugger, if the policy cares, remembering it if so.
+    pub(crate) fn debugged(&self) -> bool {
+        if self.debugger_policy == DebuggerPolicy::Ignore || !platform::is_debugged() {
+            return false;
+        }
+        #[cfg(feature = "tracing")]
+        warn!(policy = ?self.debugger_policy, "running under a debugger");
+        self.debugged.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// Original path of the target, if the file there is no longer the one the guard was created
+    /// for.
+    ///
+    /// Compared at the original path, where updaters put the new version; the path the target
+    /// resolves to by then is that of the old one, which is gone.
+    fn replaced(&self) -> Option<&Path> {
+        let identity = self.identity?;
+        let original = self
+            .raw
+            .as_ref()
+            .map(RawPath::path)
+            .or(self.target.as_deref())?;
+        FileId::of(original)
+            .is_ok_and(|current| current != identity)
+            .then_some(original)
+    }
+
+    /// Record that the destructive `operation` was authorized.
+    pub(crate) fn authorize(&self, operation: Destructive) {
+        #[cfg(feature = "tracing")]
+        warn!(?operation, target = %self.display(), "destructive operation authorized");
+        self.emit(Event::Authorized(operation));
+    }
+
+    /// Record a lifecycle event, and send it if anyone is listening.
+    pub(crate) fn emit(&self, event: Event) {
+        let Some(events) = &self.events else {
+            // moved rather than cloned, so recording doesn't allocate
+            report::record(event);
+            return;
+        };
+        report::record(event.clone());
+        // never blocks, as the receiver may only drain once the guard dropped; a receiver going
+        // away doesn't concern deletion
+        if let Err(TrySendError::Full(_)) = events.try_send(event) {
+            let _dropped = self.dropped_events.fetch_add(1, Ordering::SeqCst);
+            #[cfg(feature = "tracing")]
+            if _dropped == 0 {
+                warn!("event channel is full; dropping events");
+            }
+        }
+    }
+
+    /// Delete the target, within `budget` if given, on top of the drop budget.
+    ///
+    /// Skipping deletion, or finding the target already deleted, counts as success.
+    pub(crate) fn delete(&self, budget: Option<Duration>) -> io::Result<()> {
+        match self.begin(budget) {
+            ControlFlow::Continue(deletion) => self.proceed(deletion),
+            ControlFlow::Break(result) => result,
+        }
+    }
+
+    /// Attempt deletion till it's done, sleeping between attempts.
+    pub(crate) fn proceed(&self, mut deletion: Deletion) -> io::Result<()> {
+        loop {
+            match self.attempt(&mut deletion) {
+                Step::Retry(delay) => thread::sleep(delay),
+                Step::Done(path, result) => return self.end(deletion, path, result),
+            }
+        }
+    }
+
+    /// Prepare deletion, running everything preceding the attempts.
+    ///
+    /// Breaks with the result if deletion ends before any attempt, e.g. when refused. The drop
+    /// budget is cut down to `budget`, if given.
+    pub(crate) fn begin(&self, budget: Option<Duration>) -> ControlFlow<io::Result<()>, Deletion> {
+        self.emit(Event::DeletionStarted);
+        let owned_budget = self
+            .drop_budget
+            .into_iter()
+            .chain(budget)
+            .min()
+            .map(Budget::new);
+        let budget = owned_budget.as_ref();
+
+        let teardown = std::mem::take(&mut *self.teardown.lock().unwrap());
+        for step in teardown {
+            if budget.is_some_and(|budget| budget.spent() >= budget.total / 4) {
+                #[cfg(feature = "tracing")]
+                warn!("drop budget is running out; skipping steps before deletion");
+                break;
+            }
+            if catch_unwind(AssertUnwindSafe(step)).is_err() {
+                #[cfg(feature = "tracing")]
+                warn!("step before deletion panicked; deleting regardless");
+            }
+        }
+
+        let skip = self.debugger_policy == DebuggerPolicy::Skip && self.debugged();
+        let refusal = self
+            .refusal
+            .or_else(|| self.foreign().then_some(Refusal::Provenance))
+            .or_else(|| skip.then_some(Refusal::Debugged));
+        if let Some(refusal) = refusal {
+            #[cfg(feature = "tracing")]
+            warn!(?refusal, "refusing to delete executable");
+            let nix_link = self.nix_link.as_ref().filter(|_| !self.dry_run);
+            if let (Refusal::NixStore, Some(link)) = (refusal, nix_link) {
+                if let Err(err) = std::fs::remove_file(link) {
+                    #[cfg(feature = "tracing")]
+                    warn!(%err, "failed to remove symlink into the nix store");
+                    let degradation = Degradation::NixLinkNotRemoved(err.kind());
+                    self.degraded.lock().unwrap().push(degradation);
+                }
+            }
+            self.done(None, 0, self.strategies[0], Outcome::Refused(refusal));
+            return ControlFlow::Break(Ok(()));
+        }
+
+        if let (true, Ok(Some(path))) = (self.broadcast && !self.dry_run, self.path()) {
+            if let Err(err) = process::broadcast(&path) {
+                #[cfg(feature = "tracing")]
+                warn!(%err, "failed to ask sibling instances to finish");
+                let degradation = Degradation::NotBroadcast(err.kind());
+                self.degraded.lock().unwrap().push(degradation);
+            }
+        }
+
+        // under a deadline, the target comes first, and additional paths get what time is left
+        let mut extras = std::mem::take(&mut *self.extras.lock().unwrap());
+        if self.deadline.is_none() || self.delegate.is_some() {
+            self.remove_extras(std::mem::take(&mut extras), None);
+        }
+
+        if let Ok(Some(path)) = self.path() {
+            if let Some(callback) = self.callbacks.before_delete.as_ref().and_then(Once::take) {
+                self.call(|| callback(&path));
+            }
+            self.run_hooks(HookPoint::BeforeDelete, Some(&path));
+        }
+
+        if let Some((package, command)) = &self.delegate {
+            return ControlFlow::Break(self.delegate(package, command));
+        }
+
+        let terminate = self.terminate.as_ref().filter(|_| !self.dry_run);
+        if let (Some(termination), Ok(Some(path))) = (terminate, self.path()) {
+            let termination = match budget.map(|budget| budget.remaining() / 2) {
+                Some(share) => Termination {
+                    grace: termination.grace.min(share / 2),
+                    kill_timeout: termination.kill_timeout.min(share / 2),
+                },
+                None => *termination,
+            };
+            if let Err(err) = process::terminate(&path, &termination) {
+                #[cfg(feature = "tracing")]
+                warn!(%err, "failed to stop processes running the target; deleting regardless");
+                let degradation = Degradation::NotTerminated(err.kind());
+                self.degraded.lock().unwrap().push(degradation);
+            }
+        }
+
+        #[cfg(feature = "watch")]
+        let watch = match (self.confirm_deletion && !self.dry_run, self.path()) {
+            (true, Ok(Some(path))) => match watch::Watch::new(&path) {
+                Ok(watch) => Some(watch),
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(%err, "failed to watch target; deletion won't be confirmed");
+                    let degradation = Degradation::Unconfirmed(err.kind());
+                    self.degraded.lock().unwrap().push(degradation);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        ControlFlow::Continue(Deletion {
+            attempts: 0,
+            strategy: self.strategies[0],
+            started: Instant::now(),
+            next_beat: self.heartbeat,
+            budget: owned_budget,
+            unresolved: 0,
+            extras,
+            #[cfg(feature = "watch")]
+            watch,
+        })
+    }
+
+    /// Make the next attempt of `deletion`.
+    pub(crate) fn attempt(&self, deletion: &mut Deletion) -> Step<'_> {
+        let Deletion {
+            attempts,
+            strategy,
+            started,
+            next_beat,
+            budget,
+            unresolved,
+            ..
+        } = deletion;
+        if *attempts > 0 {
+            self.emit(Event::Retry(*attempts));
+        }
+        *attempts += 1;
+        let (attempts, started, budget) = (*attempts, *started, budget.as_ref());
+
+        if let Some(original) = self.replaced() {
+            #[cfg(feature = "tracing")]
+            debug!(path = ?self.shown(original), "target was replaced; skipping deletion");
+            return Step::Done(Some(Cow::Borrowed(original)), Ok(Outcome::Replaced));
+        }
+        let path = match (self.path(), self.directory.get()) {
+            // the path may be unreachable after changing roots; the directory handle isn't
+            (Err(_), Some(directory)) => Ok(Some(Cow::Borrowed(directory.path.as_path()))),
+            (path, _) => path,
+        };
+        let policy = self.resolution;
+        let path = match (path, &self.raw) {
+            (Err(_err), Some(raw))
+                if *unresolved >= policy.retries && policy.fall_back_to_original =>
+            {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to resolve executable; using original path");
+                Ok(Some(Cow::Borrowed(raw.path())))
+            }
+            (path, _) => path,
+        };
+        match path {
+            Err(err) => match (*unresolved < policy.retries)
+                .then_some(policy.delay)
+                .and_then(|delay| match budget.map(Budget::remaining) {
+                    Some(Duration::ZERO) => None,
+                    Some(remaining) => Some(delay.min(remaining)),
+                    None => Some(delay),
+                }) {
+                Some(delay) => {
+                    *unresolved += 1;
+                    self.beat(next_beat, started, attempts, None, &err);
+                    Step::Retry(delay)
+                }
+                None => {
+                    #[cfg(feature = "tracing")]
+                    error!(ensure = self.ensure(), "failed to delete executable");
+                    self.reporter.report(self.ensure(), None, &err);
+                    Step::Done(None, Err(err))
+                }
+            },
+            Ok(None) => {
+                #[cfg(feature = "tracing")]
+                debug!("target was already deleted");
+                Step::Done(None, Ok(Outcome::AlreadyDeleted))
+            }
+            Ok(Some(path)) => match self.apply(strategy, attempts, &path) {
+                // an earlier attempt succeeded, but others still hold handles on the target
+                Err(_) if platform::is_delete_pending(&path) => {
+                    #[cfg(feature = "tracing")]
+                    debug!("target is pending deletion");
+                    Step::Done(Some(path), Ok(Outcome::DeletePending))
+                }
+                // another guard, or another process, deleted the target first
+                Err(err)
+                    if err.kind() == io::ErrorKind::NotFound
+                        && path
+                            .symlink_metadata()
+                            .is_err_and(|err| err.kind() == io::ErrorKind::NotFound) =>
+                {
+                    #[cfg(feature = "tracing")]
+                    debug!("target was deleted concurrently");
+                    Step::Done(None, Ok(Outcome::AlreadyDeleted))
+                }
+                Err(err) => match self.retry(attempts, started, budget) {
+                    Some(delay) => {
+                        #[cfg(feature = "tracing")]
+                        error!(
+                            ensure = self.ensure(),
+                            "failed to delete executable; retrying"
+                        );
+                        self.beat(next_beat, started, attempts, Some(&path), &err);
+                        Step::Retry(delay)
+                    }
+                    None => match self.fall_back(strategy, started, &path) {
+                        Some(Ok(()))
+                            if matches!(*strategy, Strategy::Schedule | Strategy::Helper) =>
+                        {
+                            Step::Done(Some(path), Ok(Outcome::Scheduled))
+                        }
+                        Some(Ok(())) => Step::Done(Some(path), Ok(Outcome::Deleted)),
+                        _ if self.schedule_instead(*strategy, &path) => {
+                            *strategy = Strategy::Schedule;
+                            Step::Done(Some(path), Ok(Outcome::Scheduled))
+                        }
+                        _ => {
+                            let shown = self.shown(&path);
+                            self.reporter.report(self.ensure(), Some(&shown), &err);
+                            Step::Done(Some(path), Err(err))
+                        }
+                    },
+                },
+                Ok(()) if self.dry_run => Step::Done(Some(path), Ok(Outcome::DryRun)),
+                Ok(()) if matches!(*strategy, Strategy::Schedule | Strategy::Helper) => {
+                    Step::Done(Some(path), Ok(Outcome::Scheduled))
+                }
+                Ok(()) => Step::Done(Some(path), Ok(Outcome::Deleted)),
+            },
+        }
+    }
+
+    /// Finish `deletion`, which ended on `path` with `result`.
+    pub(crate) fn end(
+        &self,
+        deletion: Deletion,
+        path: Option<Cow<'_, Path>>,
+        result: io::Result<Outcome>,
+    ) -> io::Result<()> {
+        let panic_reports = std::mem::take(&mut *self.panic_reports.lock().unwrap());
+        for report in panic_reports.into_iter().filter(|_| !self.dry_run) {
+            if let Err(_err) = std::fs::remove_file(&report) {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, report = ?self.shown(&report), "failed to delete crash report");
+            }
+        }
+
+        let outcome = match &result {
+            Ok(outcome) => *outcome,
+            Err(err) => Outcome::Failed(err.kind()),
+        };
+        if let (true, Outcome::Deleted) = (self.placeholder, outcome) {
+            self.place_placeholder();
+        }
+        if matches!(outcome, Outcome::Deleted | Outcome::Scheduled) {
+            self.remove_wrappers();
+        }
+        #[cfg(feature = "watch")]
+        if let (Some(watch), Outcome::Deleted) = (deletion.watch, outcome) {
+            let timeout = deletion.budget.map_or(watch::TIMEOUT, |budget| {
+                watch::TIMEOUT.min(budget.remaining())
+            });
+            if let Err(err) = watch.confirm(timeout) {
+                #[cfg(feature = "tracing")]
+                warn!(%err, "deletion wasn't confirmed");
+                let degradation = Degradation::Unconfirmed(err.kind());
+                self.degraded.lock().unwrap().push(degradation);
+            }
+        }
+        if !deletion.extras.is_empty() {
+            let until = self.deadline.map(|deadline| deletion.started + deadline);
+            self.remove_extras(deletion.extras, until);
+        }
+        if outcome.is_success() {
+            self.cancel_watchdog();
+            self.forget_runs();
+        }
+        self.done(path, deletion.attempts, deletion.strategy, outcome);
+        result.map(drop)
+    }
+
+    /// Remove additional paths registered with the guard, giving up on those left once `until`.
+    fn remove_extras(&self, extras: Vec<(PathBuf, bool)>, until: Option<Instant>) {
+        let proceed = || self.wipe_allowed() && until.is_none_or(|until| Instant::now() < until);
+        let wiping = self.wiping(&proceed);
+        for (extra, directory) in extras {
+            if until.is_some_and(|until| Instant::now() >= until) {
+                #[cfg(feature = "tracing")]
+                warn!(path = ?self.shown(&extra), "deadline passed; not removing additional path");
+                let degradation = Degradation::NotRemoved(io::ErrorKind::TimedOut);
+                self.degraded.lock().unwrap().push(degradation);
+                continue;
+            }
+            if self.dry_run {
+                #[cfg(feature = "tracing")]
+                debug!(path = ?self.shown(&extra), "dry run; not removing additional path");
+                continue;
+            }
+            if self.wipe {
+                match strategy::wipe_tree(&extra, &wiping) {
+                    Ok(()) => continue,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => {
+                        #[cfg(feature = "tracing")]
+                        warn!(%err, path = ?self.shown(&extra), "failed to wipe additional path; removing regardless");
+                        let degradation = Degradation::NotWiped(err.kind());
+                        self.degraded.lock().unwrap().push(degradation);
+                    }
+                }
+            }
+            let result = match directory {
+                true => std::fs::remove_dir_all(&extra),
+                false => std::fs::remove_file(&extra),
+            };
+            match result {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    #[cfg(feature = "tracing")]
+                    warn!(%err, path = ?self.shown(&extra), "failed to remove additional path");
+                    let degradation = Degradation::NotRemoved(err.kind());
+                    self.degraded.lock().unwrap().push(degradation);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Forget the runs of the target kept for run limits, once it's gone.
+    fn forget_runs(&self) {
+        let Some(state) = self.state.as_ref().filter(|_| !self.dry_run) else {
+            return;
+        };
+        if let Err(err) = state.clear() {
+            #[cfg(feature = "tracing")]
+            warn!(%err, "failed to forget runs of target");
+            self.degraded
+                .lock()
+                .unwrap()
+                .push(Degradation::NoState(err.kind()));
+        }
+    }
+
+    /// Stop the watchdog, if one was spawned.
+    pub(crate) fn cancel_watchdog(&self) {
+        if let Some(watchdog) = self.watchdog.lock().unwrap().take() {
+            watchdog.cancel();
+        }
+    }
+
+    /// Remove the wrapper scripts launching the target.
+    fn remove_wrappers(&self) {
+        for wrapper in &self.wrappers {
+            match std::fs::remove_file(wrapper) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    #[cfg(feature = "tracing")]
+                    warn!(%err, wrapper = ?self.shown(wrapper), "failed to remove wrapper script");
+                    let degradation = Degradation::WrapperNotRemoved(err.kind());
+                    self.degraded.lock().unwrap().push(degradation);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Leave a placeholder at the original path of the target.
+    fn place_placeholder(&self) {
+        let result = match &self.raw {
+            Some(raw) => strategy::place_placeholder(raw.path()),
+            None => original_path(&self.target).and_then(|path| strategy::place_placeholder(&path)),
+        };
+        if let Err(err) = result {
+            #[cfg(feature = "tracing")]
+            warn!(%err, "failed to leave placeholder");
+            let degradation = Degradation::NoPlaceholder(err.kind());
+            self.degraded.lock().unwrap().push(degradation);
+        }
+    }
+
+    /// Have the package manager remove the target, owned by `package`, through `command`.
+    pub(crate) fn delegate(&self, package: &str, command: &[String]) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        debug!(package, "delegating deletion to the package manager");
+        let (path, result) = match self.path() {
+            Ok(Some(path)) => {
+                let command = strategy::delegation(command, package, &path);
+                let result = match self.dry_run {
+                    true => {
+                        #[cfg(feature = "tracing")]
+                        debug!(?command, "dry run; not delegating deletion");
+                        Ok(())
+                    }
+                    false => strategy::delegate(&command),
+                };
+                (Some(path), result)
+            }
+            Ok(None) => {
+                self.done(None, 0, self.strategies[0], Outcome::AlreadyDeleted);
+                return Ok(());
+            }
+            Err(err) => (None, Err(err)),
+        };
+        let outcome = match &result {
+            Ok(()) if self.dry_run => Outcome::DryRun,
+            Ok(()) => Outcome::Delegated,
+            Err(err) => {
+                let shown = path.as_deref().map(|path| self.shown(path));
+                self.reporter.report(self.ensure(), shown.as_deref(), err);
+                Outcome::Failed(err.kind())
+            }
+        };
+        self.done(path, 1, self.strategies[0], outcome);
+        result
+    }
+
+    /// Attempt deletion of `path` once with the fallback strategy, if the deadline passed since
+    /// `started`.
+    fn fall_back(
+        &self,
+        strategy: &mut Strategy,
+        started: Instant,
+        path: &Path,
+    ) -> Option<io::Result<()>> {
+        let (deadline, fallback) = (self.deadline?, self.fallback?);
+        if started.elapsed() < deadline {
+            return None;
+        }
+        #[cfg(feature = "tracing")]
+        warn!(?fallback, "deadline passed; falling back");
+        *strategy = fallback;
+        self.emit(Event::Fallback(fallback));
+        Some(self.apply_once(fallback, path))
+    }
+
+    /// Report that deletion is still being retried, if `next` is due since `started`.
+    fn beat(
+        &self,
+        next: &mut Option<Duration>,
+        started: Instant,
+        attempts: u32,
+        path: Option<&Path>,
+        err: &io::Error,
+    ) {
+        let (Some(due), Some(interval)) = (*next, self.heartbeat) else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        if elapsed < due {
+            return;
+        }
+        *next = Some(elapsed + interval);
+
+        #[cfg(feature = "tracing")]
+        warn!(
+            attempts,
+            ?elapsed,
+            kind = ?err.kind(),
+            %err,
+            "deletion is taking long; still retrying"
+        );
+        let shown = path.map(|path| self.shown(path));
+        self.reporter
+            .heartbeat(self.ensure(), shown.as_deref(), err, attempts, elapsed);
+    }
+
+    /// Attempt deletion of `path`, escalating `strategy` for hard guards.
+    fn apply(&self, strategy: &mut Strategy, attempt: u32, path: &Path) -> io::Result<()> {
+        if self.dry_run {
+            #[cfg(feature = "tracing")]
+            debug!(path = ?self.shown(path), ?strategy, "dry run; not deleting target");
+            return Ok(());
+        }
+
+        if self.wipe && !self.wiped.swap(true, Ordering::SeqCst) {
+            // on a full disk or over quota, e.g. on copy-on-write filesystems, the unlink still
+            // frees space
+            // under a deadline, wiping gets half of it; cut short, the target is truncated instead,
+            // leaving the rest for unlinking it
+            let until = self.deadline.map(|deadline| Instant::now() + deadline / 2);
+            let proceed =
+                || self.wipe_allowed() && until.is_none_or(|until| Instant::now() < until);
+            if let Err(err) = strategy::wipe(path, &self.wiping(&proceed)) {
+                #[cfg(feature = "tracing")]
+                warn!(
+                    %err,
+                    storage_full = strategy::is_storage_full(&err),
+                    "failed to wipe target; deleting regardless"
+                );
+                let degradation = Degradation::NotWiped(err.kind());
+                self.degraded.lock().unwrap().push(degradation);
+                // the target is deleted regardless, so there is nothing left to resume
+                strategy::forget_wipe(path);
+                if err.kind() == io::ErrorKind::Interrupted {
+                    if let Err(_err) = strategy::neuter(path) {
+                        #[cfg(feature = "tracing")]
+                        warn!(err = %_err, "failed to truncate target; deleting regardless");
+                    }
+                }
+            }
+        }
+
+        if self.ensure() {
+            // under Wine, repeating the first strategy is futile; escalate right away
+            let attempt = match self.wine {
+                true => attempt + Strategy::ESCALATE_AFTER - 1,
+                false => attempt,
+            };
+            let mut escalated = Strategy::escalated(&self.strategies, attempt);
+            // once renamed away, there is no need to rename again
+            if escalated == Strategy::RenameAway && self.moved.lock().unwrap().is_some() {
+                escalated = Strategy::ClearAttributes;
+            }
+            if escalated != *strategy {
+                #[cfg(feature = "tracing")]
+                warn!(?escalated, "escalating deletion strategy");
+                *strategy = escalated;
+                self.emit(Event::Fallback(escalated));
+            }
+        }
+
+        self.apply_once(*strategy, path)
+    }
+
+    /// How to [wipe](crate::GuardBuilder::wipe), going on while `proceed` holds.
+    fn wiping<'a>(&'a self, proceed: &'a dyn Fn() -> bool) -> strategy::Wiping<'a> {
+        strategy::Wiping {
+            unbuffered: self.unbuffered_wipe,
+            passes: self.overwrite_passes,
+            proceed,
+            throttle: self.wipe_rate.map(strategy::Throttle::new),
+            #[cfg(feature = "seal")]
+            key: self.seal_key.as_ref(),
+        }
+    }
+
+    /// Whether [wiping](crate::GuardBuilder::wipe) may go on.
+    fn wipe_allowed(&self) -> bool {
+        self.wipe_while
+            .as_ref()
+            .is_none_or(|condition| (condition.0)())
+    }
+
+    /// Attempt deletion of `path` using `strategy` as is.
+    fn apply_once(&self, strategy: Strategy, path: &Path) -> io::Result<()> {
+        if let (Strategy::Unlink, Some(directory)) = (strategy, self.directory.get()) {
+            // the directory handle is authoritative, unless the target is known to have moved
+            let followed = self.handle.is_some() && path != directory.path;
+            if !followed && self.moved.lock().unwrap().is_none() {
+                return directory.unlink();
+            }
+        }
+
+        if let (Strategy::Unlink, Some(raw)) = (strategy, &self.raw) {
+            if raw.matches(path) {
+                return raw.unlink();
+            }
+        }
+
+        let (moved, result) =
+            strategy::apply(strategy, path, &self.temp_location, &self.temp_naming);
+        if let Some(moved) = moved {
+            #[cfg(feature = "tracing")]
+            debug!(moved = ?self.shown(&moved), "renamed target away");
+            *self.moved.lock().unwrap() = Some(moved);
+        }
+        result
+    }
+
+    /// Whether deletion of `path` was scheduled in place of giving up after `strategy` failed.
+    ///
+    /// On Windows, where the running executable can't be deleted, guards giving up before
+    /// escalating to [`Strategy::Schedule`] still fall back to it, as long as it's among their
+    /// strategies.
+    fn schedule_instead(&self, strategy: Strategy, path: &Path) -> bool {
+        if !cfg!(windows)
+            || strategy == Strategy::Schedule
+            || !self.strategies.contains(&Strategy::Schedule)
+        {
+            return false;
+        }
+        let (_, result) = strategy::apply(
+            Strategy::Schedule,
+            path,
+            &self.temp_location,
+            &self.temp_naming,
+        );
+        match result {
+            Ok(()) => {
+                self.emit(Event::Fallback(Strategy::Schedule));
+                true
+            }
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to schedule deletion on reboot");
+                false
+            }
+        }
+    }
+
+    /// `path` as it may be logged or reported.
+    pub(crate) fn shown<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        shown(path, self.privacy)
+    }
+
+    pub(crate) fn done(
+        &self,
+        path: Option<Cow<'_, Path>>,
+        attempts: u32,
+        strategy: Strategy,
+        outcome: Outcome,
+    ) {
+        let when = match outcome.is_success() {
+            true => HookPoint::OnSuccess,
+            false => HookPoint::OnFailure,
+        };
+        self.run_hooks(when, path.as_deref());
+        let dropped = self.dropped_events.swap(0, Ordering::SeqCst);
+        if dropped > 0 {
+            let degradation = Degradation::EventsDropped(dropped);
+            self.degraded.lock().unwrap().push(degradation);
+        }
+        let report = Report {
+            path: path.map(|path| match self.privacy {
+                true => self.shown(&path).into_owned(),
+                false => self.owned(path),
+            }),
+            attempts,
+            strategy,
+            outcome,
+            setuid: self.setuid,
+            debugger: self
+                .debugged
+                .load(Ordering::SeqCst)
+                .then_some(self.debugger_policy),
+            degraded: std::mem::take(&mut *self.degraded.lock().unwrap()),
+        };
+        if self.capture.load(Ordering::SeqCst) {
+            *self.captured.lock().unwrap() = Some(report.clone());
+        }
+        let callbacks = &self.callbacks;
+        if outcome.is_success() {
+            if let Some(callback) = callbacks.on_success.as_ref().and_then(Once::take) {
+                self.call(|| callback(&report));
+            }
+        } else if let Some(callback) = callbacks.on_failure.as_ref().and_then(Once::take) {
+            if let Err(err) = report.clone().into_result() {
+                self.call(|| callback(err));
+            }
+        }
+        self.settle(Some(&report));
+        self.emit(Event::Done(report));
+    }
+
+    /// Record deletion as done with `report` for the guard's handles, unless it already is.
+    pub(crate) fn settle(&self, report: Option<&Report>) {
+        let Some(completion) = &*self.completion.lock().unwrap() else {
+            return;
+        };
+        let mut settled = completion.report.lock().unwrap();
+        if settled.is_none() {
+            *settled = Some(report.cloned());
+            completion.done.notify_all();
+        }
+    }
+
+    /// Run the [hooks](Hook) for `when`, recording those that fail.
+    fn run_hooks(&self, when: HookPoint, path: Option<&Path>) {
+        for hook in self.hooks.iter().filter(|hook| hook.when == when) {
+            let mut command = Command::new(&hook.program);
+            command
+                .args(&hook.args)
+                .env("MORTEM_HOOK", when.name())
+                .env_remove("MORTEM_TARGET")
+                .stdin(Stdio::null());
+            if let Some(path) = path {
+                command.env("MORTEM_TARGET", self.shown(path).as_os_str());
+            }
+            let result = command.status().and_then(|status| match status.success() {
+                true => Ok(()),
+                false => Err(io::Error::other(format!("hook exited with {status}"))),
+            });
+            if let Err(_err) = result {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, program = ?hook.program, "hook failed; carrying on");
+                let degradation = Degradation::HookFailed(_err.kind());
+                self.degraded.lock().unwrap().push(degradation);
+            }
+        }
+    }
+
+    /// Call a callback, carrying on with deletion if it panics.
+    fn call(&self, callback: impl FnOnce()) {
+        if catch_unwind(AssertUnwindSafe(callback)).is_err() {
+            #[cfg(feature = "tracing")]
+            warn!("deletion callback panicked; carrying on regardless");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Guard, GuardBuilder, GuardConfig, Mode, RetryPolicy};
+
+    /// Disarmed guard targeting nothing, configured by `builder`.
+    fn guard(builder: GuardBuilder) -> Guard {
+        let guard = builder
+            .target(std::env::temp_dir().join("mortem-unit-test"))
+            .build();
+        guard.disarm();
+        guard
+    }
+
+    #[test]
+    fn fixed_delay_stays_the_same() {
+        let guard = guard(GuardBuilder::new().bounded(5, Duration::from_millis(20)));
+        for attempts in 1..10 {
+            assert_eq!(guard.inner.delay(attempts), Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn exponential_delay_doubles_up_to_max() {
+        let guard = guard(GuardBuilder::new().mode(Mode::Hard));
+        let delays: Vec<_> = (1..10)
+            .map(|attempts| guard.inner.delay(attempts))
+            .collect();
+        let millis = |millis| Duration::from_millis(millis);
+        assert_eq!(
+            delays,
+            [10, 20, 40, 80, 160, 320, 640, 1000, 1000].map(millis)
+        );
+        assert_eq!(guard.inner.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn ensured_deletion_backs_off() {
+        let guard = guard(GuardBuilder::new().ensure(true));
+        assert_eq!(guard.inner.delay(1), Duration::from_millis(10));
+        assert!(guard.inner.delay(2) > guard.inner.delay(1));
+        assert_eq!(guard.inner.delay(100), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_half_and_all_of_the_delay() {
+        let mut policy = RetryPolicy::exponential(Duration::from_millis(100), Duration::MAX);
+        policy.jitter = true;
+        let guard = guard(GuardBuilder::new().retry_policy(policy));
+        for attempts in 1..5 {
+            let delay = Duration::from_millis(100) * (1 << (attempts - 1));
+            for _ in 0..100 {
+                let jittered = guard.inner.delay(attempts);
+                assert!(jittered >= delay / 2 && jittered <= delay, "{jittered:?}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reports_heartbeats_while_retrying() {
+        use std::os::unix::io::AsRawFd;
+
+        let dir =
+            std::env::temp_dir().join(format!("mortem-unit-{}-heartbeat", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // a directory can't be unlinked as a file, so deletion keeps failing
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        let records = std::fs::File::create(dir.join("records")).unwrap();
+
+        let guard = GuardBuilder::new()
+            .target(dir.join("target"))
+            .bounded(10, Duration::from_millis(20))
+            .heartbeat(Some(Duration::from_millis(50)))
+            .reporter(crate::Reporter::Fd(records.as_raw_fd()))
+            .build();
+        assert!(guard.execute().is_err());
+
+        let records = std::fs::read_to_string(dir.join("records")).unwrap();
+        let stalled: Vec<_> = records
+            .lines()
+            .filter(|record| record.starts_with("mortem: event=deletion-stalled"))
+            .collect();
+        assert!(!stalled.is_empty(), "{records}");
+        assert!(stalled.iter().all(|record| record.contains(" attempts=")));
+        assert!(records
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("mortem: event=deletion-failed"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hard_deletion_falls_back_and_gives_up_at_the_deadline() {
+        let dir = std::env::temp_dir().join(format!("mortem-unit-{}-fallback", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // a directory can't be unlinked as a file, so deletion keeps failing
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel(256);
+        let guard = GuardBuilder::new()
+            .mode(Mode::Hard)
+            .target(dir.join("target"))
+            .deadline(Duration::from_millis(100))
+            .fallback(Strategy::Unlink)
+            .reporter(crate::Reporter::Silent)
+            .events(sender)
+            .build();
+        let started = Instant::now();
+        assert!(guard.execute().is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let events: Vec<_> = receiver.try_iter().collect();
+        let [.., Event::Fallback(Strategy::Unlink), Event::Done(report)] = &events[..] else {
+            panic!("deletion didn't end on the fallback: {events:?}");
+        };
+        assert!(matches!(report.outcome, Outcome::Failed(_)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn emits_lifecycle_events() {
+        let target =
+            std::env::temp_dir().join(format!("mortem-unit-{}-events", std::process::id()));
+        std::fs::write(&target, "").unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel(16);
+        let guard = GuardBuilder::new().target(&target).events(sender).build();
+        guard.disarm();
+        guard.rearm();
+        drop(guard);
+
+        let events: Vec<_> = receiver.iter().collect();
+        let [Event::Armed, Event::Disarmed, Event::Armed, Event::DeletionStarted, Event::Done(report)] =
+            &events[..]
+        else {
+            panic!("unexpected events: {events:?}");
+        };
+        assert_eq!(report.outcome, Outcome::Deleted);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn racing_guards_delete_exactly_once() {
+        let target = std::env::temp_dir().join(format!("mortem-unit-{}-race", std::process::id()));
+        std::fs::write(&target, "").unwrap();
+
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let racers: Vec<_> = (0..8)
+            .map(|_| {
+                let guard = GuardBuilder::new().target(&target).build();
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    guard.execute().unwrap().unwrap().outcome
+                })
+            })
+            .collect();
+        let outcomes: Vec<_> = racers
+            .into_iter()
+            .map(|racer| racer.join().unwrap())
+            .collect();
+        let deleted = outcomes
+            .iter()
+            .filter(|outcome| **outcome == Outcome::Deleted);
+        assert_eq!(deleted.count(), 1, "{outcomes:?}");
+        assert!(outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, Outcome::Deleted | Outcome::AlreadyDeleted)));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn full_event_channels_never_block_deletion() {
+        let target =
+            std::env::temp_dir().join(format!("mortem-unit-{}-full-events", std::process::id()));
+        std::fs::write(&target, "").unwrap();
+
+        // drained only once the guard dropped, as callers commonly do
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        let (done, finished) = std::sync::mpsc::channel();
+        let deleting = target.clone();
+        thread::spawn(move || {
+            let guard = GuardBuilder::new().target(&deleting).events(sender).build();
+            guard.disarm();
+            guard.rearm();
+            done.send(guard.execute()).unwrap();
+        });
+        let report = finished
+            .recv_timeout(Duration::from_secs(5))
+            .expect("deletion blocked on the full channel")
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.outcome, Outcome::Deleted);
+        // disarming, rearming and starting deletion didn't fit
+        assert_eq!(report.degraded, [Degradation::EventsDropped(3)]);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [Event::Armed]);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn deadline_puts_the_target_before_additional_paths() {
+        let dir = std::env::temp_dir().join(format!("mortem-unit-{}-deadline", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (target, extra) = (dir.join("target"), dir.join("extra"));
+        std::fs::write(&target, "").unwrap();
+        std::fs::write(&extra, "").unwrap();
+
+        // with no time left after the target, additional paths are given up on
+        let guard = GuardBuilder::new()
+            .target(&target)
+            .deadline(Duration::ZERO)
+            .build();
+        guard.also_remove(&extra);
+        let report = guard.execute().unwrap().unwrap();
+        assert_eq!(report.outcome, Outcome::Deleted);
+        assert_eq!(
+            report.degraded,
+            [Degradation::NotRemoved(io::ErrorKind::TimedOut)]
+        );
+        assert!(!target.exists() && extra.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn configurations_carry_additional_paths_and_hooks() {
+        let dir = std::env::temp_dir().join(format!("mortem-unit-{}-config", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("tree/nested")).unwrap();
+        let (target, extra, log) = (dir.join("target"), dir.join("extra"), dir.join("log"));
+        std::fs::write(&target, "").unwrap();
+        std::fs::write(&extra, "").unwrap();
+        std::fs::write(dir.join("tree/nested/file"), "").unwrap();
+
+        let append = |when| {
+            Hook::new(when, "/bin/sh").arg("-c").arg(format!(
+                "echo \"$MORTEM_HOOK $MORTEM_TARGET\" >> {}",
+                log.display()
+            ))
+        };
+        let config = GuardConfig {
+            target: Some(target.clone()),
+            also_remove: vec![extra.clone()],
+            also_remove_dir_all: vec![dir.join("tree")],
+            destructive: true,
+            hooks: vec![
+                append(HookPoint::BeforeDelete),
+                append(HookPoint::OnSuccess),
+                append(HookPoint::OnFailure),
+                Hook::new(HookPoint::OnSuccess, "/bin/false"),
+            ],
+            ..GuardConfig::default()
+        };
+        let report = GuardBuilder::from(config)
+            .build()
+            .execute()
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.outcome, Outcome::Deleted);
+        assert!(!target.exists() && !extra.exists() && !dir.join("tree").exists());
+
+        // failing hooks don't hold up deletion, but are reported
+        assert_eq!(
+            report.degraded,
+            [Degradation::HookFailed(io::ErrorKind::Other)]
+        );
+        let target = target.display();
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            format!("before_delete {target}\non_success {target}\n")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removing_directories_from_configurations_needs_unlocking() {
+        let dir = std::env::temp_dir().join(format!("mortem-unit-{}-locked", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("tree")).unwrap();
+        let target = dir.join("target");
+        std::fs::write(&target, "").unwrap();
+
+        let config = GuardConfig {
+            target: Some(target.clone()),
+            also_remove_dir_all: vec![dir.join("tree")],
+            ..GuardConfig::default()
+        };
+        let report = GuardBuilder::from(config)
+            .build()
+            .execute()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            report.degraded,
+            [Degradation::Locked(Destructive::RemoveDirAll)]
+        );
+        assert!(!target.exists() && dir.join("tree").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_report_and_plan_without_deleting() {
+        let dir = std::env::temp_dir().join(format!("mortem-unit-{}-dry-run", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (target, extra) = (dir.join("target"), dir.join("extra"));
+        std::fs::write(&target, "").unwrap();
+        std::fs::write(&extra, "").unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel(16);
+        let guard = GuardBuilder::new()
+            .target(&target)
+            .dry_run(true)
+            .events(sender)
+            .build();
+        guard.also_remove(&extra);
+        assert_eq!(
+            guard.plan(),
+            [
+                crate::Operation::RemoveFile(extra.clone()),
+                crate::Operation::Delete {
+                    path: target.clone(),
+                    strategies: vec![Strategy::Unlink],
+                },
+            ]
+        );
+        drop(guard);
+
+        let done = receiver.try_iter().find_map(|event| match event {
+            Event::Done(report) => Some(report),
+            _ => None,
+        });
+        let report = done.expect("dry run didn't finish");
+        assert_eq!(report.outcome, Outcome::DryRun);
+        assert_eq!(report.path.as_deref(), Some(&*target));
+        assert!(target.exists() && extra.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn false_conditions_skip_deletion() {
+        let target =
+            std::env::temp_dir().join(format!("mortem-unit-{}-condition", std::process::id()));
+        std::fs::write(&target, "").unwrap();
+
+        let builder = GuardBuilder::new().target(&target).only_if(|| false);
+        assert!(builder.clone().build().execute().unwrap().is_none());
+        assert!(target.exists());
+        // the condition was taken by the first guard, so isn't known to hold for the second
+        assert!(builder.build().execute().unwrap().is_none());
+        assert!(target.exists());
+
+        std::fs::remove_file(&target).unwrap();
+    }
+
+    #[test]
+    fn callbacks_run_exactly_once() {
+        use std::sync::atomic::AtomicUsize;
+
+        let dir =
+            std::env::temp_dir().join(format!("mortem-unit-{}-callbacks", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // a directory can't be unlinked as a file, so deleting `failing` fails
+        std::fs::create_dir_all(dir.join("failing")).unwrap();
+        let target = dir.join("target");
+
+        let calls: Arc<[AtomicUsize; 3]> = Arc::default();
+        let counting = |index: usize| {
+            let calls = Arc::clone(&calls);
+            move || {
+                calls[index].fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        let (before, success, failure) = (counting(0), counting(1), counting(2));
+        let builder = GuardBuilder::new()
+            .bounded(1, Duration::ZERO)
+            .reporter(crate::Reporter::Silent)
+            .before_delete(move |_| before())
+            .on_success(move |_| success())
+            .on_failure(move |_| failure());
+
+        // guards built from clones share the callbacks, so only the first to delete calls them
+        for _ in 0..2 {
+            std::fs::write(&target, "").unwrap();
+            let guard = builder.clone().target(&target).build();
+            assert_eq!(guard.execute().unwrap().unwrap().outcome, Outcome::Deleted);
+            let guard = builder.clone().target(dir.join("failing")).build();
+            assert!(guard.execute().is_err());
+        }
+        let calls = calls.each_ref().map(|calls| calls.load(Ordering::SeqCst));
+        assert_eq!(calls, [1, 1, 1]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plans_the_strategies_deletion_runs() {
+        let dir = std::env::temp_dir().join(format!("mortem-unit-{}-plan", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // a directory can't be unlinked as a file, so deletion goes through every strategy
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel(256);
+        let guard = GuardBuilder::new()
+            .mode(Mode::Hard)
+            .strategies([Strategy::Unlink, Strategy::ClearAttributes])
+            .target(dir.join("target"))
+            .deadline(Duration::from_millis(300))
+            .fallback(Strategy::Unlink)
+            .reporter(crate::Reporter::Silent)
+            .events(sender)
+            .build();
+        let [crate::Operation::Delete { path, strategies }] = &guard.plan()[..] else {
+            panic!("unexpected plan: {:?}", guard.plan());
+        };
+        assert_eq!(*path, dir.join("target"));
+        assert_eq!(
+            strategies,
+            &[
+                Strategy::Unlink,
+                Strategy::ClearAttributes,
+                Strategy::Unlink
+            ]
+        );
+        let _ = guard.execute();
+
+        let ran: Vec<_> = std::iter::once(Strategy::Unlink)
+            .chain(receiver.try_iter().filter_map(|event| match event {
+                Event::Fallback(strategy) => Some(strategy),
+                _ => None,
+            }))
+            .collect();
+        assert_eq!(&ran, strategies);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn preparing_leaves_the_target_till_committed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let target =
+            std::env::temp_dir().join(format!("mortem-unit-{}-prepare", std::process::id()));
+        let readonly = || {
+            std::fs::write(&target, "").unwrap();
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o444)).unwrap();
+        };
+        let builder = GuardBuilder::new()
+            .target(&target)
+            .strategies([Strategy::ClearAttributes]);
+
+        // prepared, attributes are cleared, but only committing deletes
+        readonly();
+        let guard = builder.clone().build();
+        guard.prepare().unwrap();
+        assert!(target.exists());
+        guard.disarm();
+        drop(guard);
+        assert!(target.exists());
+
+        let guard = builder.build();
+        guard.prepare().unwrap();
+        assert!(target.exists());
+        let report = guard.commit().unwrap().unwrap();
+        assert_eq!(report.outcome, Outcome::Deleted);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn refuses_setuid_targets_by_default() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let target =
+            std::env::temp_dir().join(format!("mortem-unit-{}-setuid", std::process::id()));
+        for mode in [0o4755, 0o2755] {
+            std::fs::write(&target, "").unwrap();
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode)).unwrap();
+            let report = GuardBuilder::new()
+                .target(&target)
+                .build()
+                .execute()
+                .unwrap()
+                .unwrap();
+            assert_eq!(report.outcome, Outcome::Refused(Refusal::Setuid));
+            assert_eq!(report.setuid, Some(SetuidPolicy::Refuse));
+            assert!(target.exists());
+        }
+
+        let report = GuardBuilder::new()
+            .target(&target)
+            .setuid_policy(SetuidPolicy::Proceed)
+            .build()
+            .execute()
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.outcome, Outcome::Deleted);
+        assert_eq!(report.setuid, Some(SetuidPolicy::Proceed));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn redacts_paths_from_formatting() {
+        let path = std::env::temp_dir().join("mortem-unit-test");
+        let path = path.to_string_lossy();
+        let redacted = guard(GuardBuilder::new().redact(true));
+        for shown in [format!("{redacted:?}"), redacted.to_string()] {
+            assert!(!shown.contains(&*path), "{shown}");
+            assert!(shown.contains("<redacted:"), "{shown}");
+        }
+        let shown = guard(GuardBuilder::new());
+        assert!(format!("{shown:?}").contains(&*path));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn keeps_paths_private() {
+        use std::os::unix::io::AsRawFd;
+
+        let dir = std::env::temp_dir().join(format!("mortem-unit-{}-private", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // a directory can't be unlinked as a file, so deletion fails and is reported
+        std::fs::create_dir_all(dir.join("secret-target")).unwrap();
+        let records = std::fs::File::create(dir.join("records")).unwrap();
+
+        let guard = GuardBuilder::new()
+            .target(dir.join("secret-target"))
+            .bounded(1, Duration::ZERO)
+            .privacy(true)
+            .reporter(crate::Reporter::Fd(records.as_raw_fd()))
+            .build();
+        let shown = [format!("{guard:?}"), guard.to_string()];
+        let report = guard.execute().unwrap_err().into_report();
+        let records = std::fs::read_to_string(dir.join("records")).unwrap();
+        for shown in shown.into_iter().chain([format!("{report:?}"), records]) {
+            assert!(!shown.contains("secret-target"), "{shown}");
+            assert!(shown.contains("<redacted:"), "{shown}");
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
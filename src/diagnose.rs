@@ -0,0 +1,131 @@
+//! Non-destructive self-test of what deletion depends on.
+
+use crate::handle::Handle;
+use crate::sources::Sources;
+use crate::{platform, resolve, strategy};
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Findings of [`diagnose`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Diagnosis {
+    /// Path of the host executable according to [`std::env::current_exe`].
+    pub current_exe: Option<PathBuf>,
+    /// Path of the host executable according to `/proc/self/exe`, where available.
+    pub procfs: Option<PathBuf>,
+    /// Path of the host executable according to `argv[0]`, resolved the way a shell would have.
+    pub argv0: Option<PathBuf>,
+    /// Path of the host executable according to a handle held on it, where supported.
+    pub handle: Option<PathBuf>,
+    /// Whether all available sources other than `argv[0]`, which is only advisory, point at the
+    /// same file.
+    ///
+    /// See [`SourcePolicy`](crate::SourcePolicy).
+    pub sources_agree: bool,
+    /// Whether the directory of the host executable is writable, which deleting it requires.
+    pub writable: Result<bool, io::ErrorKind>,
+    /// Whether the host executable has attributes preventing plain deletion, such as the read-only
+    /// attribute or the immutable flag.
+    ///
+    /// See [`Strategy::ClearAttributes`](crate::Strategy::ClearAttributes).
+    pub protected: Result<bool, io::ErrorKind>,
+    /// Whether the host executable has the setuid or setgid bit set.
+    ///
+    /// See [`SetuidPolicy`](crate::SetuidPolicy).
+    pub setuid: bool,
+    /// Whether the host executable looks like a multi-call binary.
+    ///
+    /// See [`MultiCallPolicy`](crate::MultiCallPolicy).
+    pub multi_call: bool,
+    /// Whether the process runs under Wine.
+    pub wine: bool,
+    /// Whether deletion can be scheduled, found by running `systemd-run --version` on Linux, and
+    /// assumed on Windows.
+    ///
+    /// See [`Strategy::Schedule`](crate::Strategy::Schedule).
+    pub schedule: Result<(), io::ErrorKind>,
+}
+
+/// Run a non-destructive battery of checks on what deleting the host executable depends on.
+///
+/// Nothing is deleted, renamed or written to. Meant for a diagnostic flag of the host's command
+/// line interface, and for bug reports.
+///
+/// ### Usage
+/// ```rust
+/// if std::env::args().any(|arg| arg == "--mortem-diagnose") {
+///     eprintln!("{}", mortem::diagnose());
+/// }
+/// ```
+pub fn diagnose() -> Diagnosis {
+    let path = std::env::current_exe();
+    let handle = path.as_ref().ok().and_then(|path| Handle::open(path).ok());
+    let sources = Sources::gather(handle.as_ref());
+    let path = path.map_err(|err| err.kind());
+    let parent = path.clone().and_then(|path| match path.parent() {
+        Some(parent) => Ok(parent.to_owned()),
+        None => Err(io::ErrorKind::InvalidInput),
+    });
+
+    Diagnosis {
+        sources_agree: sources.agree(),
+        current_exe: sources.current_exe,
+        procfs: sources.procfs,
+        argv0: sources.argv0,
+        handle: sources.handle,
+        writable: parent.and_then(|parent| writable(&parent).map_err(|err| err.kind())),
+        protected: path
+            .as_ref()
+            .map_err(|&kind| kind)
+            .and_then(|path| strategy::protected(path).map_err(|err| err.kind())),
+        setuid: path.as_ref().is_ok_and(|path| resolve::is_setuid(path)),
+        multi_call: path.as_ref().is_ok_and(|path| resolve::is_multi_call(path)),
+        wine: platform::is_wine(),
+        schedule: strategy::probe_schedule().map_err(|err| err.kind()),
+    }
+}
+
+/// Whether the process may create and remove entries in `dir`.
+///
+/// Directories with the sticky bit additionally require owning the entry.
+#[cfg(unix)]
+fn writable(dir: &Path) -> io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = CString::new(dir.as_os_str().as_bytes())?;
+    // SAFETY: `dir` is a valid nul-terminated string
+    if unsafe { libc::access(dir.as_ptr(), libc::W_OK | libc::X_OK) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EACCES | libc::EROFS) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(unix))]
+fn writable(dir: &Path) -> io::Result<bool> {
+    Ok(!std::fs::metadata(dir)?.permissions().readonly())
+}
+
+/// Formats as one `key: value` line per finding.
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "current_exe: {:?}", self.current_exe)?;
+        writeln!(f, "procfs: {:?}", self.procfs)?;
+        writeln!(f, "argv0: {:?}", self.argv0)?;
+        writeln!(f, "handle: {:?}", self.handle)?;
+        writeln!(f, "sources_agree: {}", self.sources_agree)?;
+        writeln!(f, "writable: {:?}", self.writable)?;
+        writeln!(f, "protected: {:?}", self.protected)?;
+        writeln!(f, "setuid: {}", self.setuid)?;
+        writeln!(f, "multi_call: {}", self.multi_call)?;
+        writeln!(f, "wine: {}", self.wine)?;
+        write!(f, "schedule: {:?}", self.schedule)
+    }
+}
@@ -0,0 +1,150 @@
+//! Typed errors.
+
+use crate::report::{Outcome, Report};
+
+use std::fmt;
+use std::io;
+
+/// Failed deletion, carrying the report of how it went.
+///
+/// See [`Report::into_result`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    report: Report,
+}
+
+impl Error {
+    /// Kind of the error the last attempt failed with.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self.report.outcome {
+            Outcome::Failed(kind) => kind,
+            _ => unreachable!("errors are only made from failed reports"),
+        }
+    }
+
+    /// Broad cause of the failure.
+    pub fn failure(&self) -> Failure {
+        match (&self.report.path, self.kind()) {
+            (None, _) => Failure::ExePathUnavailable,
+            (_, io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem) => {
+                Failure::PermissionDenied
+            }
+            (_, io::ErrorKind::ResourceBusy | io::ErrorKind::ExecutableFileBusy) => {
+                Failure::FileLocked
+            }
+            (_, io::ErrorKind::NotFound) => Failure::NotFound,
+            _ => Failure::Other,
+        }
+    }
+
+    /// Report of the failed deletion.
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    /// Take the report of the failed deletion.
+    pub fn into_report(self) -> Report {
+        self.report
+    }
+}
+
+/// Broad cause of a failed deletion.
+///
+/// See [`Error::failure`], and [`Error::kind`] for the exact kind of error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Failure {
+    /// The path of the target couldn't be resolved.
+    ExePathUnavailable,
+    /// Not permitted to delete the target.
+    PermissionDenied,
+    /// The target is in use and locked against deletion.
+    FileLocked,
+    /// The target or its directory disappeared midway.
+    NotFound,
+    /// Anything else.
+    Other,
+}
+
+/// Formats as e.g. `failed to delete /usr/local/bin/tool after 3 attempts: permission denied`.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.report.path {
+            Some(path) => write!(f, "failed to delete {}", path.display())?,
+            None => write!(f, "failed to resolve target")?,
+        }
+        write!(
+            f,
+            " after {} attempts: {}",
+            self.report.attempts,
+            self.kind()
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Report {
+    /// The report, or an error if deletion failed.
+    ///
+    /// As with [`Outcome::is_success`], refusals aren't errors.
+    pub fn into_result(self) -> Result<Report, Error> {
+        match self.outcome {
+            Outcome::Failed(_) => Err(Error { report: self }),
+            _ => Ok(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Strategy;
+
+    fn failed(path: Option<&str>, kind: io::ErrorKind) -> Error {
+        let report = Report {
+            path: path.map(Into::into),
+            attempts: 3,
+            strategy: Strategy::Unlink,
+            outcome: Outcome::Failed(kind),
+            setuid: None,
+            debugger: None,
+            degraded: Vec::new(),
+        };
+        report.into_result().unwrap_err()
+    }
+
+    #[test]
+    fn classifies_failures() {
+        let path = Some("/usr/local/bin/tool");
+        for (kind, failure) in [
+            (io::ErrorKind::PermissionDenied, Failure::PermissionDenied),
+            (io::ErrorKind::ReadOnlyFilesystem, Failure::PermissionDenied),
+            (io::ErrorKind::ResourceBusy, Failure::FileLocked),
+            (io::ErrorKind::ExecutableFileBusy, Failure::FileLocked),
+            (io::ErrorKind::NotFound, Failure::NotFound),
+            (io::ErrorKind::Interrupted, Failure::Other),
+        ] {
+            let err = failed(path, kind);
+            assert_eq!(err.kind(), kind);
+            assert_eq!(err.failure(), failure, "{kind:?}");
+        }
+        // whatever the last attempt failed with, without a path the target wasn't resolved
+        assert_eq!(
+            failed(None, io::ErrorKind::PermissionDenied).failure(),
+            Failure::ExePathUnavailable
+        );
+    }
+
+    #[test]
+    fn only_failed_reports_are_errors() {
+        let err = failed(Some("/usr/local/bin/tool"), io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            err.to_string(),
+            "failed to delete /usr/local/bin/tool after 3 attempts: permission denied"
+        );
+        let mut report = err.into_report();
+        report.outcome = Outcome::Deleted;
+        assert!(report.into_result().is_ok());
+    }
+}
@@ -0,0 +1,186 @@
+//! C API, for hosts embedding mortem as a static library.
+//!
+//! Build the library with
+//! ```text
+//! cargo rustc --release --features ffi --crate-type staticlib
+//! ```
+//! and include `include/mortem.h`.
+//!
+//! Deletion outcomes are reported through an optional callback, invoked with
+//! [`MORTEM_SUCCESS`] or [`MORTEM_FAILURE`] and the userdata pointer it was registered with.
+
+use crate::Guard;
+
+use std::ffi::{c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Mutex;
+
+/// Deletion succeeded.
+pub const MORTEM_SUCCESS: c_int = 0;
+/// Deletion failed.
+pub const MORTEM_FAILURE: c_int = -1;
+
+/// Callback notified of the deletion outcome.
+pub type MortemCallback = Option<unsafe extern "C" fn(status: c_int, userdata: *mut c_void)>;
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> c_int;
+}
+
+/// Guard handed out to C, along with its outcome callback.
+pub struct MortemGuard {
+    guard: Guard,
+    notify: Notify,
+}
+
+struct Notify {
+    callback: MortemCallback,
+    userdata: *mut c_void,
+}
+
+// SAFETY: the userdata pointer is never dereferenced, only passed back to the host's callback,
+// which the host registered knowing it may be invoked at process exit.
+unsafe impl Send for Notify {}
+
+impl Notify {
+    /// Run `guard` and notify the host of the outcome.
+    fn run(self, guard: Guard) {
+        let result = catch_unwind(AssertUnwindSafe(|| guard.inner.run()));
+        let status = match result {
+            Ok(None | Some(Ok(()))) => MORTEM_SUCCESS,
+            Ok(Some(Err(_))) | Err(_) => MORTEM_FAILURE,
+        };
+        drop(guard);
+
+        if let Some(callback) = self.callback {
+            // SAFETY: the host guarantees the callback is valid to call with its userdata.
+            unsafe { callback(status, self.userdata) };
+        }
+    }
+}
+
+/// Create a guard deleting the host executable.
+///
+/// Deletion happens on [`mortem_guard_free`]. When `hard` is non-zero, deletion is retried till it
+/// succeeds.
+///
+/// # Safety
+/// `callback`, if not null, must be safe to call with `userdata` when the guard is freed.
+#[no_mangle]
+pub unsafe extern "C" fn mortem_guard_new(
+    hard: c_int,
+    callback: MortemCallback,
+    userdata: *mut c_void,
+) -> *mut MortemGuard {
+    let guard = Guard::builder().ensure(hard != 0).build();
+    Box::into_raw(Box::new(MortemGuard {
+        guard,
+        notify: Notify { callback, userdata },
+    }))
+}
+
+/// Free a guard, deleting the host executable.
+///
+/// # Safety
+/// `guard` must have been returned by [`mortem_guard_new`] and not yet been freed.
+/// Null pointers are ignored.
+#[no_mangle]
+pub unsafe extern "C" fn mortem_guard_free(guard: *mut MortemGuard) {
+    if guard.is_null() {
+        return;
+    }
+    let MortemGuard { guard, notify } = *Box::from_raw(guard);
+    notify.run(guard);
+}
+
+static REGISTRATION: Mutex<Option<Registration>> = Mutex::new(None);
+
+struct Registration {
+    hard: bool,
+    notify: Notify,
+}
+
+extern "C" fn run_registration() {
+    let registration = match REGISTRATION.lock() {
+        Ok(mut registration) => registration.take(),
+        Err(_) => return,
+    };
+    if let Some(Registration { hard, notify }) = registration {
+        notify.run(Guard::builder().ensure(hard).build());
+    }
+}
+
+/// Delete the host executable when the process exits, as registered with `atexit`.
+///
+/// Registering again replaces the previous registration; the exit handler is only installed once.
+/// Returns [`MORTEM_SUCCESS`], or [`MORTEM_FAILURE`] if the exit handler couldn't be installed.
+///
+/// # Safety
+/// `callback`, if not null, must be safe to call with `userdata` at process exit.
+#[no_mangle]
+pub unsafe extern "C" fn mortem_register_atexit(
+    hard: c_int,
+    callback: MortemCallback,
+    userdata: *mut c_void,
+) -> c_int {
+    let mut registration = match REGISTRATION.lock() {
+        Ok(registration) => registration,
+        Err(_) => return MORTEM_FAILURE,
+    };
+    let installed = registration.is_some();
+    *registration = Some(Registration {
+        hard: hard != 0,
+        notify: Notify { callback, userdata },
+    });
+
+    if !installed && atexit(run_registration) != 0 {
+        *registration = None;
+        return MORTEM_FAILURE;
+    }
+    MORTEM_SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GuardBuilder, Reporter};
+
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    unsafe extern "C" fn record(status: c_int, userdata: *mut c_void) {
+        (*userdata.cast::<AtomicI32>()).store(status, Ordering::SeqCst);
+    }
+
+    /// Free a guard of `target` through the C API, returning the status it was notified of.
+    fn free(target: &std::path::Path) -> c_int {
+        let status = AtomicI32::new(1);
+        let guard = GuardBuilder::new()
+            .target(target)
+            .reporter(Reporter::Silent)
+            .build();
+        let notify = Notify {
+            callback: Some(record),
+            userdata: (&status as *const AtomicI32).cast_mut().cast(),
+        };
+        // SAFETY: the guard was just boxed, and `status` outlives freeing it
+        unsafe { mortem_guard_free(Box::into_raw(Box::new(MortemGuard { guard, notify }))) };
+        status.into_inner()
+    }
+
+    #[test]
+    fn notifies_the_outcome_when_freed() {
+        let dir = std::env::temp_dir().join(format!("mortem-ffi-unit-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("directory")).unwrap();
+        let target = dir.join("target");
+        std::fs::write(&target, "").unwrap();
+
+        assert_eq!(free(&target), MORTEM_SUCCESS);
+        assert!(!target.exists());
+        // a directory can't be unlinked as a file
+        assert_eq!(free(&dir.join("directory")), MORTEM_FAILURE);
+        // SAFETY: null is documented to be ignored
+        unsafe { mortem_guard_free(std::ptr::null_mut()) };
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Handles held on, and identities of, the target between guard creation and drop.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Open handle on the target, used to find it again after it was renamed.
+///
+/// Only supported on Linux and Android, where the current name of an open file can be read back
+/// through procfs; opening a handle fails with [`io::ErrorKind::Unsupported`] elsewhere.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Handle {
+    file: std::sync::Arc<std::fs::File>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Handle {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        use std::fs::OpenOptions;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        // O_PATH doesn't require read permission on the file, nor does it keep it busy
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH)
+            .open(path)?;
+        Ok(Handle::from(file))
+    }
+
+    /// Current path of the file, or `None` if it has no name left.
+    pub(crate) fn path(&self) -> io::Result<Option<PathBuf>> {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::io::AsRawFd;
+
+        if self.file.metadata()?.nlink() == 0 {
+            return Ok(None);
+        }
+        std::fs::read_link(format!("/proc/self/fd/{}", self.file.as_raw_fd())).map(Some)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl From<std::fs::File> for Handle {
+    fn from(file: std::fs::File) -> Self {
+        Handle {
+            file: std::sync::Arc::new(file),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[derive(Clone, Debug)]
+pub(crate) enum Handle {}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+impl Handle {
+    pub(crate) fn open(_path: &Path) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub(crate) fn path(&self) -> io::Result<Option<PathBuf>> {
+        match *self {}
+    }
+}
+
+/// Current path of the open `file`.
+///
+/// Read back through procfs on Linux and Android, `F_GETPATH` on macOS and
+/// `GetFinalPathNameByHandleW` on Windows; fails with [`io::ErrorKind::Unsupported`] elsewhere, and
+/// [`io::ErrorKind::NotFound`] if the file has no name left.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn path_of(file: std::fs::File) -> io::Result<PathBuf> {
+    Handle::from(file)
+        .path()?
+        .ok_or(io::ErrorKind::NotFound.into())
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn path_of(file: std::fs::File) -> io::Result<PathBuf> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let mut buffer = [0 as libc::c_char; libc::PATH_MAX as usize];
+    // SAFETY: the buffer has room for the longest path F_GETPATH writes
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETPATH, buffer.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: F_GETPATH wrote a nul-terminated path
+    let path = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+    Ok(PathBuf::from(OsStr::from_bytes(path.to_bytes())))
+}
+
+#[cfg(windows)]
+pub(crate) fn path_of(file: std::fs::File) -> io::Result<PathBuf> {
+    use std::ffi::{c_void, OsString};
+    use std::os::windows::ffi::OsStringExt;
+    use std::os::windows::io::AsRawHandle;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetFinalPathNameByHandleW(
+            handle: *mut c_void,
+            path: *mut u16,
+            length: u32,
+            flags: u32,
+        ) -> u32;
+    }
+
+    let mut buffer = vec![0u16; 512];
+    loop {
+        // SAFETY: the handle is open, and the length is that of the buffer
+        let length = unsafe {
+            GetFinalPathNameByHandleW(
+                file.as_raw_handle(),
+                buffer.as_mut_ptr(),
+                buffer.len() as u32,
+                0,
+            )
+        } as usize;
+        match length {
+            0 => return Err(io::Error::last_os_error()),
+            // too small; the length needed includes the nul terminator
+            length if length > buffer.len() => buffer.resize(length, 0),
+            length => return Ok(PathBuf::from(OsString::from_wide(&buffer[..length]))),
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    windows
+)))]
+pub(crate) fn path_of(_file: std::fs::File) -> io::Result<PathBuf> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Identity of a file, independent of its name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    /// Identity of the file currently at `path`.
+    ///
+    /// Only supported on Unix, failing with [`io::ErrorKind::Unsupported`] elsewhere.
+    #[cfg(unix)]
+    pub(crate) fn of(path: &Path) -> io::Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::metadata(path)?;
+        Ok(FileId {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn of(_path: &Path) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Handle on the directory of the target, used to delete it relative to that directory.
+///
+/// Keeps working after the process changed its root (`chroot`, `pivot_root`) or the path to the
+/// directory changed. Only supported on Unix, failing with [`io::ErrorKind::Unsupported`]
+/// elsewhere.
+#[derive(Debug)]
+pub(crate) struct DirHandle {
+    /// Path of the target when the handle was opened.
+    pub(crate) path: PathBuf,
+    #[cfg(unix)]
+    dir: std::fs::File,
+    #[cfg(unix)]
+    name: std::ffi::CString,
+}
+
+impl DirHandle {
+    #[cfg(unix)]
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        use std::ffi::CString;
+        use std::fs::OpenOptions;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let invalid = || io::Error::from(io::ErrorKind::InvalidInput);
+        let parent = match path.parent().ok_or_else(invalid)? {
+            parent if parent.as_os_str().is_empty() => Path::new("."),
+            parent => parent,
+        };
+        let name = CString::new(path.file_name().ok_or_else(invalid)?.as_bytes())?;
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let flags = libc::O_DIRECTORY | libc::O_PATH;
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let flags = libc::O_DIRECTORY;
+        let dir = OpenOptions::new()
+            .read(true)
+            .custom_flags(flags)
+            .open(parent)?;
+
+        Ok(DirHandle {
+            path: path.to_owned(),
+            dir,
+            name,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn open(_path: &Path) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Unlink the target relative to the held directory.
+    #[cfg(unix)]
+    pub(crate) fn unlink(&self) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        // SAFETY: the descriptor is open and the name is nul-terminated
+        match unsafe { libc::unlinkat(self.dir.as_raw_fd(), self.name.as_ptr(), 0) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn unlink(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,40 @@
+//! Registry of the guards of the host executable, only one of which deletes it.
+
+use crate::Inner;
+
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, Weak};
+
+/// Guards of the host executable, in order of creation.
+pub(crate) static HOST: Mutex<Host> = Mutex::new(Host {
+    guards: Vec::new(),
+    claimed: false,
+});
+
+/// Guards of the host executable, of which only the primary one deletes it.
+pub(crate) struct Host {
+    pub(crate) guards: Vec<Weak<Inner>>,
+    /// Whether a guard claimed deletion, which no other guard may then run.
+    pub(crate) claimed: bool,
+}
+
+impl Host {
+    /// First guard still alive and armed.
+    pub(crate) fn primary(&self) -> Option<Arc<Inner>> {
+        self.guards
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|inner| inner.armed.load(Ordering::SeqCst))
+    }
+
+    /// Whether `inner` defers to another guard, being neither the primary nor the one to have
+    /// claimed deletion.
+    pub(crate) fn defers(&self, inner: &Inner) -> bool {
+        match self.claimed {
+            true => inner.pending.load(Ordering::SeqCst),
+            false => !self
+                .primary()
+                .is_some_and(|primary| std::ptr::eq(Arc::as_ptr(&primary), inner)),
+        }
+    }
+}
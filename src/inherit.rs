@@ -0,0 +1,58 @@
+//! Handing over deletion across `execve`, through the environment.
+//!
+//! A process executing another program sets [`VAR`] to the paths it leaves for it to delete,
+//! joined as with [`std::env::join_paths`]. A guard of the new process
+//! [inheriting](crate::GuardBuilder::inherit) them takes the variable out of the environment, so
+//! they're picked up exactly once, and deletes them along with its own target.
+
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+
+/// Environment variable carrying the paths handed over.
+pub(crate) const VAR: &str = "MORTEM_INHERIT";
+
+/// Take the paths handed over by the process that executed this one out of the environment.
+///
+/// Ignored under secure execution, such as for setuid executables, where the environment is
+/// controlled by a less privileged user.
+pub(crate) fn take() -> Vec<PathBuf> {
+    let Some(paths) = env::var_os(VAR) else {
+        return Vec::new();
+    };
+    env::remove_var(VAR);
+    if secure_execution() {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("ignoring inherited paths under secure execution");
+        return Vec::new();
+    }
+    env::split_paths(&paths)
+        .filter(|path| path.is_absolute())
+        .collect()
+}
+
+/// Value of [`VAR`] handing over `paths`.
+pub(crate) fn encode(paths: &[PathBuf]) -> io::Result<OsString> {
+    env::join_paths(paths).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+/// Whether the process runs with privileges the user executing it doesn't have.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn secure_execution() -> bool {
+    // SAFETY: getauxval has no preconditions
+    unsafe { libc::getauxval(libc::AT_SECURE) != 0 }
+}
+
+/// Whether the process runs with privileges the user executing it doesn't have.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+pub(crate) fn secure_execution() -> bool {
+    // SAFETY: these have no preconditions and can't fail
+    unsafe { libc::getuid() != libc::geteuid() || libc::getgid() != libc::getegid() }
+}
+
+/// Whether the process runs with privileges the user executing it doesn't have.
+#[cfg(not(unix))]
+pub(crate) fn secure_execution() -> bool {
+    false
+}
@@ -0,0 +1,930 @@
+//! Janitor, deleting files on behalf of other processes.
+//!
+//! For fleets of short-lived tools, spawning a helper per process to delete its executable is
+//! wasteful. Instead, a single long-lived janitor can be [served](serve) on a unix socket, and the
+//! tools [hand off](hand_off) their deletions to it. By default the janitor deletes the handed off
+//! paths once the connection of the process that handed them off closes, i.e. once it exited.
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! // in the short-lived tool
+//! mortem::janitor::hand_off("/run/mortem.sock", [std::env::current_exe()?])?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Orchestrators needing to verify the deletions [submit] them instead, and query the
+//! status of the acknowledged [`Submission`]. With the `seal` feature, janitors served sealed
+//! (`serve_sealed`) seal the status records they reply with, so they reveal nothing and can't be
+//! forged without the key.
+//!
+//! Child processes forked without executing another program share the connection, so the janitor
+//! waits for them as well.
+//!
+//! The janitor only deletes files the connecting process may delete on its own, unless it runs as
+//! root or as the janitor's user: those in directories its user may write to and search, and, in
+//! directories with the sticky bit such as `/tmp`, only those its user owns or in directories it
+//! owns. The user and group are taken from the credentials of the connection; supplementary groups
+//! aren't, so group permissions only count for the primary group. Submissions with any other path
+//! are refused as a whole.
+//!
+//! Paths are fully resolved when submitted, and their parent directory is opened right away.
+//! Permission is checked through that directory both then and again right before deleting, and
+//! the file is unlinked relative to it, so swapping a directory along the path for another in
+//! between doesn't redirect the deletion.
+//!
+//! At most [`MAX_CONNECTIONS`] connections are served at once, further ones are closed right away.
+//! Each is given [`TIMEOUT`] per record until it commits a submission waiting for it to close, and
+//! may hand off at most [`MAX_PATHS`] paths. Statuses of submissions are kept while their
+//! submitter stays connected, and of the latest [`MAX_RETIRED`] submissions after that.
+//!
+//! Every connection opens with a handshake naming the [version](PROTOCOL) of the protocol. A
+//! janitor speaking another version replies so and hangs up before reading anything else, and
+//! one predating the handshake drops the connection, so mismatched janitors and tools fail with
+//! an error rather than misreading each other's records.
+
+#[cfg(feature = "seal")]
+use crate::seal::{self, Key};
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+/// Version of the protocol between janitors and the processes connecting to them, bumped on
+/// incompatible changes.
+pub const PROTOCOL: u32 = 1;
+
+/// Most connections served at once, so clients can't pin threads of the janitor without bound.
+pub const MAX_CONNECTIONS: usize = 128;
+
+/// Most paths, and submissions, a single connection may hand off.
+pub const MAX_PATHS: usize = 16;
+
+/// Most statuses kept of submissions whose submitter disconnected.
+pub const MAX_RETIRED: usize = 1024;
+
+/// Time a connection is given to send each record, and to take each reply.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest record accepted, so clients can't make the janitor buffer without bound.
+const MAX_RECORD: u64 = 8192;
+
+/// Opcode of the record opening every connection, `mortem-janitor <version>`.
+const HELLO: u8 = b'H';
+/// Magic opening hello records, and replies to those accepted.
+const MAGIC: &str = "mortem-janitor";
+/// Reply to a hello of an unsupported version, followed by the supported version.
+const UNSUPPORTED: &str = "unsupported";
+/// Opcode of a record handing off a path.
+const DELETE: u8 = b'D';
+/// Opcode of a record setting the options of the paths handed off next.
+const OPTIONS: u8 = b'O';
+/// Opcode of a record committing the paths handed off so far as a submission.
+const COMMIT: u8 = b'C';
+/// Opcode of a record querying the status of a submission.
+const STATUS: u8 = b'S';
+/// Reply to a commit with paths the submitting process may not delete.
+const REFUSED: &str = "refused";
+
+/// Connections kept open till the process exits.
+static HANDED_OFF: Mutex<Vec<UnixStream>> = Mutex::new(Vec::new());
+
+/// Options of a submission.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SubmitOptions {
+    /// Number of times the janitor retries deleting each path.
+    pub retries: u32,
+    /// Delay between the janitor's attempts.
+    pub delay: Duration,
+    /// Whether to wait for the submitting process to exit before deleting.
+    ///
+    /// Otherwise deletion starts as soon as the submission is acknowledged.
+    pub on_exit: bool,
+    /// Key to open the status records of a janitor [served sealed](serve_sealed) with.
+    #[cfg(feature = "seal")]
+    pub key: Option<Key>,
+}
+
+impl Default for SubmitOptions {
+    fn default() -> Self {
+        Self {
+            retries: 10,
+            delay: Duration::from_millis(100),
+            on_exit: true,
+            #[cfg(feature = "seal")]
+            key: None,
+        }
+    }
+}
+
+/// Status of a submission.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Status {
+    /// The paths are yet to be deleted.
+    Pending,
+    /// Deletion finished.
+    Done {
+        /// Number of paths that are gone.
+        deleted: usize,
+        /// Number of paths that couldn't be deleted.
+        failed: usize,
+    },
+    /// The janitor doesn't know of the submission, e.g. because it was restarted.
+    Unknown,
+}
+
+/// Submission acknowledged by a janitor.
+#[derive(Clone, Debug)]
+pub struct Submission {
+    socket: PathBuf,
+    id: u64,
+    #[cfg(feature = "seal")]
+    key: Option<Key>,
+}
+
+impl Submission {
+    /// Identifier of the submission, unique to the janitor.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Query the janitor for the status of the submission.
+    ///
+    /// With a [key](SubmitOptions::key), the status record is opened with it.
+    pub fn status(&self) -> io::Result<Status> {
+        #[cfg(feature = "seal")]
+        if let Some(key) = &self.key {
+            return status_sealed(&self.socket, self.id, key);
+        }
+        status(&self.socket, self.id)
+    }
+}
+
+/// State shared between the connections of a janitor.
+#[derive(Default)]
+struct Janitor {
+    next: AtomicU64,
+    jobs: Mutex<HashMap<u64, Status>>,
+    /// Submissions whose submitter disconnected, oldest first.
+    retired: Mutex<VecDeque<u64>>,
+    /// Number of connections being served.
+    served: Arc<AtomicUsize>,
+    /// Key status records are sealed with.
+    #[cfg(feature = "seal")]
+    key: Option<Key>,
+}
+
+/// Paths committed for deletion.
+struct Job {
+    id: u64,
+    /// Credentials of the submitting process.
+    peer: Peer,
+    entries: Vec<Entry>,
+    options: SubmitOptions,
+}
+
+/// Serve a janitor on the unix socket at `socket`.
+///
+/// Each connection is served on its own thread, up to [`MAX_CONNECTIONS`] at once. Only returns
+/// if binding the socket fails.
+pub fn serve(socket: impl AsRef<Path>) -> io::Result<Infallible> {
+    serve_with(socket.as_ref(), Janitor::default())
+}
+
+/// Serve a janitor on the unix socket at `socket`, sealing the status records it replies with
+/// with `key`.
+///
+/// Submitters query them with the same [key](SubmitOptions::key). See [`seal`].
+#[cfg(feature = "seal")]
+pub fn serve_sealed(socket: impl AsRef<Path>, key: Key) -> io::Result<Infallible> {
+    let janitor = Janitor {
+        key: Some(key),
+        ..Janitor::default()
+    };
+    serve_with(socket.as_ref(), janitor)
+}
+
+fn serve_with(socket: &Path, janitor: Janitor) -> io::Result<Infallible> {
+    let listener = UnixListener::bind(socket)?;
+    let janitor = Arc::new(janitor);
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let Some(slot) = Slot::take(&janitor.served) else {
+                    #[cfg(feature = "tracing")]
+                    warn!("too many janitor connections; closing");
+                    continue;
+                };
+                let janitor = Arc::clone(&janitor);
+                thread::spawn(move || {
+                    janitor.serve(stream);
+                    drop(slot);
+                });
+            }
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to accept janitor connection");
+            }
+        }
+    }
+}
+
+/// Slot of a connection being served, given back once dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    /// Take a slot, unless all [`MAX_CONNECTIONS`] are taken.
+    fn take(served: &Arc<AtomicUsize>) -> Option<Self> {
+        served
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |served| {
+                (served < MAX_CONNECTIONS).then_some(served + 1)
+            })
+            .ok()
+            .map(|_| Slot(Arc::clone(served)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Janitor {
+    /// Seal `status` with the janitor's key, if it has one.
+    fn seal(&self, status: String) -> io::Result<String> {
+        #[cfg(feature = "seal")]
+        if let Some(key) = &self.key {
+            return seal::seal(key, status.as_bytes());
+        }
+        Ok(status)
+    }
+
+    /// Serve records received on `stream`, deleting submissions waiting for exit once it closes.
+    fn serve(&self, stream: UnixStream) {
+        let peer = match peer_credentials(&stream) {
+            Ok(peer) => peer,
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to get credentials of janitor connection");
+                return;
+            }
+        };
+        let timeouts = stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)));
+        let mut writer = match timeouts.and_then(|()| stream.try_clone()) {
+            Ok(writer) => writer,
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to set up janitor connection");
+                return;
+            }
+        };
+        let mut reader = BufReader::new(stream);
+        // paths and submissions handed off so far
+        let mut handed_off = 0;
+        let mut ids = Vec::new();
+        let mut entries = Vec::new();
+        let mut refused = false;
+        let mut options = SubmitOptions::default();
+        let mut on_exit = Vec::new();
+        let mut immediate = None;
+        let mut record = Vec::new();
+        let mut greeted = false;
+        loop {
+            record.clear();
+            match (&mut reader).take(MAX_RECORD).read_until(0, &mut record) {
+                // the submitting process exited
+                Ok(0) => break,
+                Ok(len) if len as u64 == MAX_RECORD && record.last() != Some(&0) => {
+                    #[cfg(feature = "tracing")]
+                    warn!("janitor record too long; dropping connection");
+                    break;
+                }
+                Ok(_) => {
+                    let record = record.strip_suffix(&[0]).unwrap_or(&record);
+                    let mut hang_up = false;
+                    let reply = match record.split_first() {
+                        Some((&HELLO, hello)) if !greeted => match decode_hello(hello) {
+                            Some(PROTOCOL) => {
+                                greeted = true;
+                                Some(format!("{MAGIC} {PROTOCOL}"))
+                            }
+                            Some(_version) => {
+                                #[cfg(feature = "tracing")]
+                                warn!(
+                                    version = _version,
+                                    "unsupported janitor protocol; hanging up"
+                                );
+                                hang_up = true;
+                                Some(format!("{UNSUPPORTED} {PROTOCOL}"))
+                            }
+                            None => break,
+                        },
+                        _ if !greeted => {
+                            #[cfg(feature = "tracing")]
+                            warn!("janitor connection didn't open with a handshake; dropping it");
+                            break;
+                        }
+                        Some((&DELETE | &COMMIT, _)) if handed_off == MAX_PATHS => {
+                            #[cfg(feature = "tracing")]
+                            warn!("janitor connection handed off too much; dropping it");
+                            break;
+                        }
+                        Some((&DELETE, path)) => {
+                            handed_off += 1;
+                            let path = Path::new(OsStr::from_bytes(path));
+                            match Entry::permitted(peer, path) {
+                                Some(entry) => entries.push(entry),
+                                None => {
+                                    #[cfg(feature = "tracing")]
+                                    warn!(?path, ?peer, "refusing path submitter may not delete");
+                                    refused = true;
+                                }
+                            }
+                            None
+                        }
+                        Some((&OPTIONS, encoded)) => match decode_options(encoded) {
+                            Some(decoded) => {
+                                options = decoded;
+                                None
+                            }
+                            None => break,
+                        },
+                        Some((&COMMIT, [])) if std::mem::take(&mut refused) => {
+                            handed_off += 1;
+                            entries.clear();
+                            Some(REFUSED.to_owned())
+                        }
+                        Some((&COMMIT, [])) => {
+                            let job = Job {
+                                id: self.next.fetch_add(1, Ordering::Relaxed),
+                                peer,
+                                entries: std::mem::take(&mut entries),
+                                options: options.clone(),
+                            };
+                            handed_off += 1;
+                            self.jobs.lock().unwrap().insert(job.id, Status::Pending);
+                            ids.push(job.id);
+                            let ack = format!("{}", job.id);
+                            match job.options.on_exit {
+                                true => {
+                                    // wait for the submitter however long it runs
+                                    if let Err(_err) = reader.get_ref().set_read_timeout(None) {
+                                        #[cfg(feature = "tracing")]
+                                        warn!(err = %_err, "failed to clear janitor connection timeout");
+                                    }
+                                    on_exit.push(job)
+                                }
+                                false => immediate = Some(job),
+                            }
+                            Some(ack)
+                        }
+                        Some((&STATUS, id)) => {
+                            let status = std::str::from_utf8(id)
+                                .ok()
+                                .and_then(|id| id.parse().ok())
+                                .and_then(|id| self.jobs.lock().unwrap().get(&id).copied())
+                                .unwrap_or(Status::Unknown);
+                            match self.seal(encode_status(status)) {
+                                Ok(status) => Some(status),
+                                Err(_err) => {
+                                    #[cfg(feature = "tracing")]
+                                    warn!(
+                                        "failed to seal status record: {_err}; dropping connection"
+                                    );
+                                    break;
+                                }
+                            }
+                        }
+                        _ => {
+                            #[cfg(feature = "tracing")]
+                            warn!("malformed janitor record; dropping connection");
+                            break;
+                        }
+                    };
+                    if let Some(reply) = reply {
+                        let mut reply = reply.into_bytes();
+                        reply.push(0);
+                        if let Err(_err) = writer.write_all(&reply) {
+                            #[cfg(feature = "tracing")]
+                            warn!(err = %_err, "failed to reply on janitor connection");
+                        }
+                    }
+                    if hang_up {
+                        break;
+                    }
+                    // only once acknowledged
+                    if let Some(job) = immediate.take() {
+                        self.clean_up(job);
+                    }
+                }
+                // the connection broke, but the process likely exited all the same
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(err = %_err, "janitor connection failed");
+                    break;
+                }
+            }
+        }
+
+        for job in on_exit {
+            self.clean_up(job);
+        }
+        self.retire(ids);
+    }
+
+    /// Retire the submissions `ids` of a submitter that disconnected, forgetting the oldest
+    /// retired ones beyond [`MAX_RETIRED`].
+    fn retire(&self, ids: Vec<u64>) {
+        let mut retired = self.retired.lock().unwrap();
+        retired.extend(ids);
+        while retired.len() > MAX_RETIRED {
+            if let Some(id) = retired.pop_front() {
+                self.jobs.lock().unwrap().remove(&id);
+            }
+        }
+    }
+
+    /// Delete the paths of `job`, recording its status.
+    fn clean_up(&self, job: Job) {
+        let (mut deleted, mut failed) = (0, 0);
+        for entry in job.entries {
+            // the file or its directory may have changed since it was submitted
+            if !entry.still_permitted(job.peer) {
+                #[cfg(feature = "tracing")]
+                warn!(
+                    name = ?entry.name,
+                    id = job.id,
+                    "submitter may no longer delete submitted path; not deleting it"
+                );
+                failed += 1;
+                continue;
+            }
+            #[cfg(feature = "tracing")]
+            debug!(name = ?entry.name, id = job.id, "janitor deleting submitted path");
+            match entry.unlink(job.options.retries, job.options.delay) {
+                Ok(()) => deleted += 1,
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(name = ?entry.name, id = job.id, err = %_err, "failed to delete submitted path");
+                    failed += 1;
+                }
+            }
+        }
+        let status = Status::Done { deleted, failed };
+        self.jobs.lock().unwrap().insert(job.id, status);
+    }
+}
+
+/// Credentials of the process at the other end of a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Peer {
+    uid: libc::uid_t,
+    /// Primary group.
+    gid: libc::gid_t,
+}
+
+/// Submitted path, held by its parent directory.
+#[derive(Debug)]
+struct Entry {
+    /// Parent directory, opened when the path was submitted.
+    parent: File,
+    /// Name of the file in `parent`.
+    name: CString,
+}
+
+impl Entry {
+    /// Entry of the fully resolved `path`, if `peer` may have the janitor delete it.
+    fn permitted(peer: Peer, path: &Path) -> Option<Entry> {
+        let path = fs::canonicalize(path).ok()?;
+        let name = CString::new(path.file_name()?.as_bytes()).ok()?;
+        // resolved, so no component is a symbolic link anymore
+        let parent = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECTORY | libc::O_NOFOLLOW)
+            .open(path.parent()?)
+            .ok()?;
+        let entry = Entry { parent, name };
+        entry.still_permitted(peer).then_some(entry)
+    }
+
+    /// Whether `peer` may still have the janitor delete the file.
+    fn still_permitted(&self, peer: Peer) -> bool {
+        // SAFETY: geteuid has no preconditions
+        let janitor = unsafe { libc::geteuid() };
+        let Ok(parent) = self.parent.metadata() else {
+            return false;
+        };
+        let parent = Directory {
+            uid: parent.uid(),
+            gid: parent.gid(),
+            mode: parent.mode(),
+        };
+        // SAFETY: an all-zero stat is valid, and fstatat only writes to it
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        // SAFETY: `parent` is an open directory and `name` is nul-terminated
+        let result = unsafe {
+            libc::fstatat(
+                self.parent.as_raw_fd(),
+                self.name.as_ptr(),
+                &mut stat,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        result == 0 && authorized(peer, janitor, parent, stat.st_uid)
+    }
+
+    /// Unlink the file from its parent directory, retrying `retries` times `delay` apart.
+    ///
+    /// Succeeds if the file is already gone.
+    fn unlink(&self, retries: u32, delay: Duration) -> io::Result<()> {
+        let mut attempts = 0;
+        loop {
+            // SAFETY: `parent` is an open directory and `name` is nul-terminated
+            let result = unsafe { libc::unlinkat(self.parent.as_raw_fd(), self.name.as_ptr(), 0) };
+            let err = match result {
+                0 => return Ok(()),
+                _ => io::Error::last_os_error(),
+            };
+            if err.kind() == io::ErrorKind::NotFound {
+                return Ok(());
+            }
+            if attempts >= retries {
+                return Err(err);
+            }
+            attempts += 1;
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Ownership and mode of a directory.
+#[derive(Clone, Copy, Debug)]
+struct Directory {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    mode: u32,
+}
+
+/// Whether `peer` may have the janitor, running as `janitor`, delete a file owned by `owner` in
+/// `parent`, as it could unlink the file on its own.
+fn authorized(peer: Peer, janitor: libc::uid_t, parent: Directory, owner: libc::uid_t) -> bool {
+    if peer.uid == 0 || peer.uid == janitor {
+        return true;
+    }
+    let permissions = match () {
+        _ if parent.uid == peer.uid => parent.mode >> 6,
+        _ if parent.gid == peer.gid => parent.mode >> 3,
+        _ => parent.mode,
+    };
+    // both write and search permission are needed to unlink
+    let writable = permissions & 0o3 == 0o3;
+    // S_ISVTX, whose type differs across platforms
+    let sticky = parent.mode & 0o1000 != 0;
+    writable && (!sticky || owner == peer.uid || parent.uid == peer.uid)
+}
+
+/// Credentials of the process at the other end of `stream`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_credentials(stream: &UnixStream) -> io::Result<Peer> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: SO_PEERCRED writes a ucred of at most `len` bytes
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    match result {
+        0 => Ok(Peer {
+            uid: credentials.uid,
+            gid: credentials.gid,
+        }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Credentials of the process at the other end of `stream`.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_credentials(stream: &UnixStream) -> io::Result<Peer> {
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: getpeereid writes the two ids
+    match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Ok(Peer { uid, gid }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+fn encode_options(options: &SubmitOptions) -> String {
+    let delay = options.delay.as_millis();
+    format!("{},{},{}", options.retries, delay, options.on_exit as u8)
+}
+
+fn decode_options(encoded: &[u8]) -> Option<SubmitOptions> {
+    let encoded = std::str::from_utf8(encoded).ok()?;
+    let mut fields = encoded.split(',');
+    let options = SubmitOptions {
+        retries: fields.next()?.parse().ok()?,
+        delay: Duration::from_millis(fields.next()?.parse().ok()?),
+        on_exit: fields.next()? == "1",
+        // the key stays with the submitter
+        #[cfg(feature = "seal")]
+        key: None,
+    };
+    fields.next().is_none().then_some(options)
+}
+
+fn encode_status(status: Status) -> String {
+    match status {
+        Status::Pending => "pending".into(),
+        Status::Done { deleted, failed } => format!("done,{deleted},{failed}"),
+        Status::Unknown => "unknown".into(),
+    }
+}
+
+fn decode_status(encoded: &str) -> Option<Status> {
+    match encoded.split(',').collect::<Vec<_>>()[..] {
+        ["pending"] => Some(Status::Pending),
+        ["done", deleted, failed] => Some(Status::Done {
+            deleted: deleted.parse().ok()?,
+            failed: failed.parse().ok()?,
+        }),
+        ["unknown"] => Some(Status::Unknown),
+        _ => None,
+    }
+}
+
+/// Version in the payload of a hello record, if it's one.
+fn decode_hello(encoded: &[u8]) -> Option<u32> {
+    let (magic, version) = std::str::from_utf8(encoded).ok()?.split_once(' ')?;
+    if magic != MAGIC {
+        return None;
+    }
+    version.parse().ok()
+}
+
+/// Hello record opening a connection.
+fn hello() -> Vec<u8> {
+    let mut record = vec![HELLO];
+    record.extend_from_slice(format!("{MAGIC} {PROTOCOL}").as_bytes());
+    record.push(0);
+    record
+}
+
+/// Read the reply to the [hello](hello) from `reader`.
+///
+/// Fails with [`io::ErrorKind::Unsupported`] if the janitor speaks another version of the
+/// protocol.
+fn greeted(reader: &mut impl BufRead) -> io::Result<()> {
+    let reply = reply(reader)?;
+    match reply.split_once(' ') {
+        Some((MAGIC, version)) if version == PROTOCOL.to_string() => Ok(()),
+        Some((UNSUPPORTED, version)) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("janitor speaks protocol version {version}, not {PROTOCOL}"),
+        )),
+        _ => Err(malformed()),
+    }
+}
+
+/// Read a reply from `reader`.
+fn reply(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut reply = Vec::new();
+    reader.take(MAX_RECORD).read_until(0, &mut reply)?;
+    match reply.pop() {
+        Some(0) => String::from_utf8(reply).map_err(|_| malformed()),
+        _ => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed janitor reply")
+}
+
+/// Submit deletion of `paths` to the janitor served at `socket`, waiting for acknowledgment.
+///
+/// Relative paths are made absolute first, as the janitor runs elsewhere. With
+/// [`SubmitOptions::on_exit`], the connection is kept open till the process exits.
+///
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the janitor refuses any of the paths, as
+/// this process's user may not delete them.
+pub fn submit<P: AsRef<Path>>(
+    socket: impl AsRef<Path>,
+    paths: impl IntoIterator<Item = P>,
+    options: &SubmitOptions,
+) -> io::Result<Submission> {
+    let mut records = hello();
+    records.push(OPTIONS);
+    records.extend_from_slice(encode_options(options).as_bytes());
+    records.push(0);
+    for path in paths {
+        records.push(DELETE);
+        records.extend_from_slice(std::path::absolute(path)?.as_os_str().as_bytes());
+        records.push(0);
+    }
+    records.extend_from_slice(&[COMMIT, 0]);
+
+    let socket = socket.as_ref();
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(&records)?;
+    let mut reader = BufReader::new(&stream);
+    greeted(&mut reader)?;
+    let id = match reply(&mut reader)?.as_str() {
+        REFUSED => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "janitor refused paths this process's user may not delete",
+            ))
+        }
+        id => id.parse().map_err(|_| malformed())?,
+    };
+    if options.on_exit {
+        // the connection closes as the process exits, which signals the janitor
+        HANDED_OFF.lock().unwrap().push(stream);
+    }
+    Ok(Submission {
+        socket: socket.to_owned(),
+        id,
+        #[cfg(feature = "seal")]
+        key: options.key.clone(),
+    })
+}
+
+/// Hand off deletion of `paths` to the janitor served at `socket`.
+///
+/// The paths are deleted once the process exits. See [`submit`] for verifying deletion.
+pub fn hand_off<P: AsRef<Path>>(
+    socket: impl AsRef<Path>,
+    paths: impl IntoIterator<Item = P>,
+) -> io::Result<()> {
+    submit(socket, paths, &SubmitOptions::default()).map(drop)
+}
+
+/// Query the janitor served at `socket` for the status of submission `id`.
+pub fn status(socket: impl AsRef<Path>, id: u64) -> io::Result<Status> {
+    decode_status(&query_status(socket.as_ref(), id)?).ok_or_else(malformed)
+}
+
+/// Query the janitor [served sealed](serve_sealed) at `socket` for the status of submission `id`,
+/// opening the status record with `key`.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the record wasn't sealed with `key`.
+#[cfg(feature = "seal")]
+pub fn status_sealed(socket: impl AsRef<Path>, id: u64, key: &Key) -> io::Result<Status> {
+    let status = seal::open(key, &query_status(socket.as_ref(), id)?)?;
+    let status = String::from_utf8(status).map_err(|_| malformed())?;
+    decode_status(&status).ok_or_else(malformed)
+}
+
+/// Query the janitor at `socket` for the status record of submission `id`.
+fn query_status(socket: &Path, id: u64) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket)?;
+    let mut records = hello();
+    records.push(STATUS);
+    records.extend_from_slice(id.to_string().as_bytes());
+    records.push(0);
+    stream.write_all(&records)?;
+    let mut reader = BufReader::new(&stream);
+    greeted(&mut reader)?;
+    reply(&mut reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_options_and_statuses_reversibly() {
+        let options = SubmitOptions {
+            retries: 3,
+            delay: Duration::from_millis(250),
+            on_exit: false,
+            #[cfg(feature = "seal")]
+            key: None,
+        };
+        let encoded = encode_options(&options);
+        assert_eq!(decode_options(encoded.as_bytes()), Some(options));
+        assert_eq!(decode_options(b"3,250"), None);
+        assert_eq!(decode_options(b"3,250,0,1"), None);
+
+        for status in [
+            Status::Pending,
+            Status::Done {
+                deleted: 2,
+                failed: 1,
+            },
+            Status::Unknown,
+        ] {
+            assert_eq!(decode_status(&encode_status(status)), Some(status));
+        }
+        assert_eq!(decode_status("done,1"), None);
+    }
+
+    #[test]
+    fn decodes_hellos_of_any_version() {
+        assert_eq!(decode_hello(&hello()[1..hello().len() - 1]), Some(PROTOCOL));
+        assert_eq!(decode_hello(b"mortem-janitor 2"), Some(2));
+        assert_eq!(decode_hello(b"mortem-janitor"), None);
+        assert_eq!(decode_hello(b"mortem-remote 1"), None);
+    }
+
+    #[test]
+    fn authorizes_as_unlinking_would() {
+        let peer = Peer {
+            uid: 1000,
+            gid: 1000,
+        };
+        let parent = |uid, gid, mode| Directory { uid, gid, mode };
+        // root and the janitor's own user may delete anything
+        assert!(authorized(
+            Peer { uid: 0, gid: 0 },
+            0,
+            parent(0, 0, 0o755),
+            1001
+        ));
+        assert!(authorized(peer, 1000, parent(0, 0, 0o755), 0));
+        // owning the file doesn't matter, but writing to its directory does
+        assert!(!authorized(peer, 0, parent(0, 0, 0o755), 1000));
+        assert!(authorized(peer, 0, parent(1000, 0, 0o700), 0));
+        assert!(authorized(peer, 0, parent(0, 1000, 0o770), 0));
+        assert!(authorized(peer, 0, parent(0, 0, 0o777), 0));
+        assert!(!authorized(peer, 0, parent(1000, 0, 0o577), 0));
+        // search permission is needed too
+        assert!(!authorized(peer, 0, parent(1000, 0, 0o600), 1000));
+        // in sticky directories, only owners of the file or the directory may
+        assert!(!authorized(peer, 0, parent(0, 0, 0o1777), 0));
+        assert!(authorized(peer, 0, parent(0, 0, 0o1777), 1000));
+        assert!(authorized(peer, 0, parent(1000, 0, 0o1700), 0));
+    }
+
+    #[test]
+    fn permits_resolved_paths_in_writable_directories_only() {
+        let dir = std::env::temp_dir().join(format!("mortem-janitor-unit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let target = dir.join("target");
+        std::fs::write(&target, "").unwrap();
+        let link = dir.join("link");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // SAFETY: geteuid and getegid have no preconditions
+        let user = unsafe {
+            Peer {
+                uid: libc::geteuid(),
+                gid: libc::getegid(),
+            }
+        };
+        let entry = Entry::permitted(user, &link).unwrap();
+        assert_eq!(entry.name.as_bytes(), b"target");
+        let other = Peer {
+            uid: user.uid.wrapping_add(4321),
+            gid: user.gid.wrapping_add(4321),
+        };
+        assert!(Entry::permitted(other, &link).is_none());
+        assert!(Entry::permitted(user, &dir.join("missing")).is_none());
+
+        // deleted through the directory it was submitted in, even once that moved
+        let moved = dir.with_extension("moved");
+        std::fs::rename(&dir, &moved).unwrap();
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(&target, "").unwrap();
+        assert!(entry.still_permitted(user));
+        entry.unlink(0, Duration::ZERO).unwrap();
+        assert!(!moved.join("target").exists());
+        assert!(target.exists());
+        // and gone is gone
+        entry.unlink(0, Duration::ZERO).unwrap();
+        assert!(!entry.still_permitted(user));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&moved).unwrap();
+    }
+}
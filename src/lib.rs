@@ -6,6 +6,7 @@
 //! When a [`Guard`] is created, it does nothing.
 //! When it gets dropped, however, it begins the process of deleting the host executable.
 //! It does this with the best of it's ability, either trying once and exiting successfully upon failure (provided by [`Guard::soft()`]) or trying continually and blocking till it succeeds (provided by [`Guard::hard()`]).
+//! In between, [`Guard::bounded()`] retries a limited number of times before giving up quietly.
 //!
 //! This means, for Mortem to do it's work, all that it needs is to be dropped at the end of the
 //! main function.
@@ -23,6 +24,28 @@
 //! }
 //! ```
 //!
+//! #### Attribute macro
+//! With the `macros` feature, `#[mortem::main]` guards `main` instead, so the guard can't be
+//! dropped early by mistake. `#[mortem::main(hard)]` uses a hard guard. Runtime attributes go
+//! below it.
+//! ```rust
+//! # #[cfg(feature = "macros")]
+//! #[mortem::main(hard)]
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     // some code
+//!     std::fs::metadata("/")?;
+//!
+//!     // the guard drops, even on early returns and errors, and the executable is deleted
+//!     Ok(())
+//! }
+//! # #[cfg(not(feature = "macros"))]
+//! # fn main() {}
+//! ```
+//!
+//! Types are organized in modules such as [`strategy`] and [`report`]; the most common ones are
+//! also available at the crate root, and through the [`prelude`].
+//!
 //! # Async
 //! Using Mortem in an async runtime is functionally the same and no user action is required beyond
 //! the usual.
@@ -56,13 +79,138 @@
 //!     // _mortem drops and executable is deleted
 //! }
 //! ```
+//!
+//! #### Non-blocking deletion
+//! A hard guard blocks the thread it's dropped on till deletion succeeds, which on a runtime's
+//! worker thread stalls the runtime. [`AsyncGuard`] instead deletes the executable on a separate
+//! thread, which can be awaited. With the `tokio` or `async-std` feature, deletion is driven by
+//! that runtime instead, awaiting its timer between attempts.
+//!
+//! # Aborting panics
+//! When built with `panic = "abort"`, destructors don't run when panicking.
+//! Guards detect this at compile time and additionally run from a panic hook, after the
+//! previously installed hook, so the executable is deleted just the same. Other paths aborting
+//! the process, such as panics while panicking, are covered by arming the hook explicitly with
+//! [`Guard::arm_panic_hook`]. Crash reporters installing a hook of their own should be installed
+//! through [`Guard::set_panic_reporter`] instead, so neither replaces the other.
+//!
+//! # Re-executing
+//! Bootstrappers executing later stages with `execve` can hand over deletion with
+//! [`Guard::hand_over`], and the guard of the stage executed [inherits](GuardBuilder::inherit) it,
+//! deleting the earlier stages along with its own executable. Paths are handed over in the
+//! `MORTEM_INHERIT` environment variable, joined as with [`std::env::join_paths`], and picked up
+//! exactly once.
+//!
+//! # Deleting exactly once
+//! Deletion is idempotent rather than leased. Of any number of guards racing to delete the same
+//! target, in one process or several, exactly one unlinks it; the others find it gone and report
+//! [`Outcome::AlreadyDeleted`]. An instance respawned after a crash does the same. That leaves two
+//! failure modes:
+//! - The target is replaced at the same path in between, e.g. by a reinstall, and a later guard
+//!   deletes the new file. Guards built with [`GuardBuilder::skip_if_replaced`] compare the
+//!   identity of the file first, and report [`Outcome::Replaced`] instead.
+//! - The process dies before its guard runs, which no bookkeeping on disk makes up for by itself.
+//!   With the `janitor` feature, handing the target off to a janitor covers this, as the janitor
+//!   deletes it once the connection closes, however the process ended.
+//!
+//! # Changing roots
+//! After entering a `chroot` or `pivot_root`, the executable may be unreachable by its path.
+//! Guards built with [`GuardBuilder::hold_directory`] before changing roots hold on to the
+//! directory of the executable instead, and delete it relative to that. This works from within
+//! user namespaces too, as with rootless containers: build the guard, then unshare and change
+//! roots, e.g.
+//!
+//! ```no_run
+//! # use mortem::Guard;
+//! let _guard = Guard::builder().hold_directory(true).build();
+//! // unshare(CLONE_NEWUSER), write the id maps, then chroot("/new/root") and chdir("/")
+//! ```
+//!
+//! # Memory exhaustion
+//! On Linux, dropping a soft or hard guard for the host executable with the default settings
+//! performs no heap allocations, so the executable is deleted even when the process is dying of
+//! memory exhaustion. Everything deletion needs is prepared when the guard is built; options
+//! such as [`GuardBuilder::events`], following renames or [wiping](GuardBuilder::wipe) may
+//! allocate.
 
+// examples deliberately show the guard living in `main`
+#![allow(clippy::needless_doctest_main)]
+
+pub mod async_;
+mod builder;
+mod config;
+mod deletion;
+mod diagnose;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod handle;
+mod host;
+mod inherit;
+#[cfg(all(unix, feature = "janitor"))]
+pub mod janitor;
+#[cfg(feature = "reexec-memfd")]
+mod memfd;
+pub mod platform;
+pub mod prelude;
+mod process;
+mod provenance;
+mod raw;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod report;
+pub mod resolve;
+#[cfg(feature = "serde")]
+pub mod schema;
+#[cfg(feature = "seal")]
+pub mod seal;
+#[cfg(feature = "signals")]
+mod signals;
+#[cfg(feature = "test-util")]
+pub mod simulate;
+mod sources;
+pub mod state;
+pub mod strategy;
+pub mod supervise;
+#[cfg(feature = "watch")]
+mod watch;
+mod watchdog;
+
+pub use async_::{AsyncGuard, DeletionFuture};
+pub use builder::GuardBuilder;
+pub use config::{
+    Backoff, Canonicalization, DebuggerPolicy, Destructive, GuardConfig, Hook, HookPoint, Mode,
+    MultiCallPolicy, PackagePolicy, ResolutionPolicy, Retries, RetryPolicy, SetuidPolicy,
+    SourcePolicy, TempLocation, TempNaming, Termination,
+};
+pub use diagnose::{diagnose, Diagnosis};
+pub use error::{Error, Failure};
+#[cfg(feature = "macros")]
+pub use mortem_macros::main;
+pub use report::{last_events, Degradation, Event, Operation, Outcome, Refusal, Report};
+pub use strategy::Strategy;
+
+use deletion::{Deletion, Inner, Step};
+use handle::DirHandle;
+use host::HOST;
+
+use std::borrow::Cow;
 use std::env::current_exe;
-use std::fs::remove_file;
+use std::fmt;
+use std::io::{self, Write};
 use std::ops::Drop;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex, PoisonError, Weak};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
 
 #[cfg(feature = "tracing")]
-use tracing::{debug, error};
+use tracing::{debug, warn};
 
 /// Create a guard that when dropped tries to delete the host executable.
 ///
@@ -86,6 +234,10 @@ pub fn soft() -> Guard {
 
 /// Create a guard that when dropped blocks till the host executable is successfully deleted.
 ///
+/// After a few failed attempts, the guard escalates through [deletion strategies](Strategy) rather
+/// than repeating the same failing call. Between attempts it waits, starting at 10 milliseconds and
+/// doubling up to a second; see [`GuardBuilder::retry_policy`] for other delays.
+///
 /// ### Usage
 /// ```rust
 /// fn main() {
@@ -102,56 +254,1127 @@ pub fn hard() -> Guard {
     Guard::hard()
 }
 
+/// Create a guard that when dropped makes a bounded effort to delete the host executable.
+///
+/// Deletion is retried up to `retries` times, waiting `delay` between attempts, and then given up
+/// quietly.
+///
+/// ### Usage
+/// ```rust
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let _mortem = mortem::bounded(10, Duration::from_millis(200)); // register guard
+///
+///     // some code
+///     println!("Hello!")
+///
+///     // functions ends, _mortem drops and executable is deleted
+/// }
+/// ```
+#[inline(always)]
+pub fn bounded(retries: u32, delay: Duration) -> Guard {
+    Guard::bounded(retries, delay)
+}
+
+/// Create a guard that when dropped blocks till the host executable is successfully deleted, but
+/// at most `timeout`.
+///
+/// See [`GuardBuilder::deadline`].
+///
+/// ### Usage
+/// ```rust
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let _mortem = mortem::hard_with_timeout(Duration::from_secs(5)); // register guard
+///
+///     // some code
+///     println!("Hello!")
+///
+///     // _mortem drops, and the executable is deleted within 5 seconds or left behind
+/// }
+/// ```
+pub fn hard_with_timeout(timeout: Duration) -> Guard {
+    Guard::hard_with_timeout(timeout)
+}
+
+/// Create a guard that when dropped overwrites the host executable with zeroes, then blocks till
+/// it is successfully deleted.
+///
+/// See [`GuardBuilder::wipe`] for the caveats, notably that the executable can't be overwritten
+/// while it runs on Linux and Windows, and [`GuardBuilder::overwrite`] for more passes.
+///
+/// ### Usage
+/// ```rust
+/// fn main() {
+///     let _mortem = mortem::secure(); // register guard
+///
+///     // some code
+///     println!("Hello!")
+///
+///     // _mortem drops, and the executable is overwritten and deleted
+/// }
+/// ```
+pub fn secure() -> Guard {
+    Guard::secure()
+}
+
+/// Register deletion of the host executable to run at process exit, as a [`hard`] guard would,
+/// without a guard to hold.
+///
+/// Unlike guards, this also deletes the executable when the guard would have been leaked or moved
+/// into a detached task, or when the process exits through [`std::process::exit`], which skips
+/// destructors. See [`Guard::arm_at_exit`].
+///
+/// ### Usage
+/// ```rust,no_run
+/// fn main() -> std::io::Result<()> {
+///     mortem::arm()?;
+///
+///     // some code
+///     std::process::exit(0); // the executable is deleted all the same
+/// }
+/// ```
+pub fn arm() -> io::Result<()> {
+    let guard = Guard::hard();
+    guard.arm_at_exit().inspect_err(|_| guard.disarm())?;
+    // leaked rather than dropped, so it is still alive at exit
+    std::mem::forget(guard);
+    Ok(())
+}
+
+/// Whether a guard of the host executable exists, and will delete it.
+///
+/// Only one guard of the host executable deletes it: the first one created that's still alive and
+/// armed. The others do nothing, so dropping them doesn't fail on the executable being gone
+/// already, and take over in order of creation when it's disarmed, or dropped without deleting.
+///
+/// ### Usage
+/// ```rust
+/// assert!(!mortem::is_armed());
+/// let mortem = mortem::hard();
+/// assert!(mortem::is_armed());
+/// # mortem.disarm();
+/// ```
+pub fn is_armed() -> bool {
+    let host = HOST.lock().unwrap_or_else(PoisonError::into_inner);
+    !host.claimed && host.primary().is_some_and(|inner| inner.will_delete())
+}
+
+/// Final-resort reporter for deletion failures.
+///
+/// When deletion fails, the reporter writes a single line record to its destination, so failures are
+/// never completely silent in binaries without tracing.
+/// Records are `key=value` pairs prefixed by `mortem:`, with values quoted and escaped so the record
+/// always stays on one line, e.g.
+/// ```text
+/// mortem: event=deletion-failed ensure=false path="/usr/local/bin/tool" error="Permission denied (os error 13)"
+/// ```
+///
+/// Unless configured otherwise, guards report to [`Reporter::Stderr`], or stay [`Reporter::Silent`]
+/// when the `tracing` feature is enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Reporter {
+    /// Never report failures.
+    Silent,
+    /// Write failure records to stderr.
+    Stderr,
+    /// Write failure records to a file descriptor.
+    ///
+    /// The descriptor is borrowed and never closed by the guard.
+    ///
+    /// Not (de)serialized, as descriptor numbers mean nothing to other processes; configurations
+    /// with it fail to serialize.
+    #[cfg(unix)]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Fd(RawFd),
+}
+
+impl Reporter {
+    fn default_for_build() -> Self {
+        if cfg!(feature = "tracing") {
+            Reporter::Silent
+        } else {
+            Reporter::Stderr
+        }
+    }
+
+    fn report(self, ensure: bool, path: Option<&Path>, error: &io::Error) {
+        self.write(Record {
+            ensure,
+            path,
+            error,
+            stalled: None,
+        });
+    }
+
+    /// Report that deletion is still being retried after `elapsed`, failing with `error`.
+    fn heartbeat(
+        self,
+        ensure: bool,
+        path: Option<&Path>,
+        error: &io::Error,
+        attempts: u32,
+        elapsed: Duration,
+    ) {
+        self.write(Record {
+            ensure,
+            path,
+            error,
+            stalled: Some((attempts, elapsed)),
+        });
+    }
+
+    fn write(self, record: Record) {
+        // reporting is best effort; there is nowhere left to report a failure to report
+        let _ = match self {
+            Reporter::Silent => Ok(()),
+            Reporter::Stderr => writeln!(io::stderr().lock(), "{record}"),
+            #[cfg(unix)]
+            Reporter::Fd(fd) => {
+                use std::fs::File;
+                use std::mem::ManuallyDrop;
+                use std::os::unix::io::FromRawFd;
+
+                // SAFETY: the descriptor is only borrowed; `ManuallyDrop` ensures it isn't closed.
+                let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+                writeln!(file, "{record}")
+            }
+        };
+    }
+}
+
+/// Single line failure record written by a [`Reporter`].
+struct Record<'a> {
+    ensure: bool,
+    path: Option<&'a Path>,
+    error: &'a io::Error,
+    /// Attempts and time spent so far, for deletion that is still being retried.
+    stalled: Option<(u32, Duration)>,
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stalled {
+            None => write!(f, "mortem: event=deletion-failed ensure={}", self.ensure)?,
+            Some((attempts, elapsed)) => write!(
+                f,
+                "mortem: event=deletion-stalled ensure={} attempts={attempts} elapsed_ms={}",
+                self.ensure,
+                elapsed.as_millis()
+            )?,
+        }
+        if let Some(path) = self.path {
+            write!(f, " path={:?}", path.display().to_string())?;
+        }
+        write!(f, " kind={:?}", self.error.kind())?;
+        write!(f, " error={:?}", self.error.to_string())
+    }
+}
+
+/// Handle on the deletion of a guard, for waiting till it's done.
+///
+/// See [`Guard::handle`].
+#[derive(Clone)]
+pub struct DeletionHandle {
+    completion: Arc<Completion>,
+}
+
+/// Completion of deletion, shared between a guard and its handles.
+#[derive(Default)]
+struct Completion {
+    /// Report of the deletion once it's done, or `None` if it didn't run.
+    report: Mutex<Option<Option<Report>>>,
+    done: Condvar,
+}
+
+impl DeletionHandle {
+    /// Whether the guard was dropped and deletion is done, or didn't run.
+    pub fn is_finished(&self) -> bool {
+        self.completion.report.lock().unwrap().is_some()
+    }
+
+    /// Block till deletion is done, returning its report.
+    ///
+    /// `None` if deletion didn't run, e.g. because the guard was [disarmed](Guard::disarm).
+    pub fn join(&self) -> Option<Report> {
+        let report = self.completion.report.lock().unwrap();
+        let report = self
+            .completion
+            .done
+            .wait_while(report, |report| report.is_none())
+            .unwrap();
+        report.clone().flatten()
+    }
+
+    /// Block till deletion is done like [`join`](Self::join), but at most `timeout`.
+    ///
+    /// `None` if deletion isn't done by then, or didn't run.
+    pub fn join_timeout(&self, timeout: Duration) -> Option<Report> {
+        let report = self.completion.report.lock().unwrap();
+        let (report, _) = self
+            .completion
+            .done
+            .wait_timeout_while(report, timeout, |report| report.is_none())
+            .unwrap();
+        report.clone().flatten()
+    }
+}
+
+impl fmt::Debug for DeletionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeletionHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// Path of the target as configured, or the host executable.
+fn original_path(target: &Option<PathBuf>) -> io::Result<PathBuf> {
+    match target {
+        Some(target) => Ok(target.clone()),
+        None => current_exe(),
+    }
+}
+
+/// Time deletion may take when run on termination or at process exit, where retrying forever would
+/// keep the process from ending.
+pub const SHUTDOWN_BUDGET: Duration = Duration::from_secs(3);
+
 /// Executable guard.
 pub struct Guard {
-    /// Ensure deletion of the file, retrying till executable is deleted.
-    ensure: bool,
+    inner: Arc<Inner>,
+}
+
+/// Handle deleting the target of a guard from elsewhere, such as a thread monitoring the
+/// integrity of the executable.
+///
+/// The handle doesn't keep the guard alive. See [`Guard::trigger_handle`].
+#[derive(Clone)]
+pub struct TriggerHandle {
+    inner: Weak<Inner>,
+}
+
+impl TriggerHandle {
+    /// Delete the target now, as the guard would when dropped.
+    ///
+    /// Returns `None` if deletion doesn't run, as when the guard was dropped or
+    /// [disarmed](Guard::disarm), or deletion already ran. The outcome is reported like any other,
+    /// e.g. through [events](GuardBuilder::events).
+    pub fn trigger(&self) -> Option<io::Result<()>> {
+        self.inner.upgrade().and_then(|inner| inner.run())
+    }
+}
+
+impl fmt::Debug for TriggerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TriggerHandle")
+            .field("alive", &(self.inner.strong_count() > 0))
+            .finish()
+    }
 }
 
 impl Guard {
-    fn new(ensure: bool) -> Self {
-        #[cfg(feature = "tracing")]
-        debug!(?ensure, "creating mortem guard");
-        Guard { ensure }
+    /// Create a guard with the preset `mode`.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// fn main() {
+    ///     let _mortem = mortem::Guard::with_mode(mortem::Mode::Secure);
+    ///
+    ///     // some code
+    ///     println!("Hello!")
+    ///
+    ///     // _mortem drops, and the executable is overwritten and deleted
+    /// }
+    /// ```
+    pub fn with_mode(mode: Mode) -> Self {
+        GuardBuilder::new().mode(mode).build()
+    }
+
+    /// Mode the guard was configured with.
+    ///
+    /// Guards with a configuration beyond the presets report the closest one, or
+    /// [`Mode::Custom`] with their strategies.
+    pub fn mode(&self) -> Mode {
+        self.inner.mode()
+    }
+
+    /// Create a builder for configuring a guard.
+    ///
+    /// See [`GuardBuilder`].
+    pub fn builder() -> GuardBuilder {
+        GuardBuilder::new()
+    }
+
+    /// Create a guard deleting the file at `path` instead of the host executable, trying once.
+    ///
+    /// For e.g. an extracted payload or a downloaded stub, that should go along with the process.
+    /// Relative paths are resolved right away. Other modes and settings are configured through
+    /// [`GuardBuilder::target`].
+    ///
+    /// ### Usage
+    /// ```rust
+    /// fn main() {
+    ///     let payload = std::env::temp_dir().join("mortem-payload-example");
+    ///     std::fs::write(&payload, "payload").unwrap();
+    ///     let _payload = mortem::Guard::for_path(&payload);
+    ///
+    ///     // some code
+    ///     println!("Hello!")
+    ///
+    ///     // _payload drops, and the payload is deleted
+    /// }
+    /// ```
+    pub fn for_path(path: impl Into<PathBuf>) -> Self {
+        GuardBuilder::new().target(path).build()
+    }
+
+    /// Create a guard deleting the file open as `fd` instead of the host executable, trying once.
+    ///
+    /// For embedders already holding the file open, e.g. having spawned it. The guard takes
+    /// ownership of the descriptor. See [`GuardBuilder::target_file`], also for configuring the
+    /// guard further.
+    ///
+    /// # Safety
+    /// `fd` must be an open file descriptor, and not be owned by anything else.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use std::os::unix::io::IntoRawFd;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let path = std::env::temp_dir().join("mortem-fd-example");
+    /// let file = std::fs::File::create(&path)?;
+    ///
+    /// // SAFETY: the descriptor was just opened, and is handed over
+    /// let guard = unsafe { mortem::Guard::from_raw_fd(file.into_raw_fd())? };
+    /// drop(guard);
+    /// assert!(!path.exists());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        // SAFETY: upheld by the caller
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        Ok(GuardBuilder::new().target_file(file)?.build())
+    }
+
+    /// Create a guard deleting the file open as `handle` instead of the host executable, trying
+    /// once.
+    ///
+    /// For embedders already holding the file open, e.g. having spawned it. The guard takes
+    /// ownership of the handle. See [`GuardBuilder::target_file`], also for configuring the guard
+    /// further.
+    ///
+    /// # Safety
+    /// `handle` must be an open file handle, and not be owned by anything else.
+    #[cfg(windows)]
+    pub unsafe fn from_raw_handle(handle: std::os::windows::io::RawHandle) -> io::Result<Self> {
+        use std::os::windows::io::FromRawHandle;
+
+        // SAFETY: upheld by the caller
+        let file = unsafe { std::fs::File::from_raw_handle(handle) };
+        Ok(GuardBuilder::new().target_file(file)?.build())
+    }
+
+    /// Path the guard will delete, as currently resolved.
+    ///
+    /// For the host executable this is resolved on each call, unless it was canonicalized.
+    /// Note that a guard [following renames](GuardBuilder::follow_renames) may end up deleting the
+    /// target under a different name.
+    pub fn target_path(&self) -> io::Result<PathBuf> {
+        original_path(&self.inner.target)
+    }
+
+    /// Canonicalization that was applied to the target path.
+    ///
+    /// May be weaker than configured, if the path couldn't be resolved through the filesystem.
+    pub fn canonicalization(&self) -> Canonicalization {
+        self.inner.canonicalization
+    }
+
+    /// Run deletion from the panic hook, on a panic in any thread.
+    ///
+    /// Drops don't run where panics abort the process: in `panic = "abort"` builds, where this is
+    /// done automatically, but also on panics while panicking or escaping `extern "C"` functions.
+    /// With the hook armed, the target is deleted on the first panic of any thread instead, after
+    /// the previously installed hook reported it. Note that this includes panics the process
+    /// recovers from, such as those of threads whose result is checked.
+    pub fn arm_panic_hook(&self) {
+        arm_panic_hook(&self.inner);
+    }
+
+    /// Run deletion at process exit, through `atexit`, unless the guard dropped before.
+    ///
+    /// Exits through [`std::process::exit`] and returning from `main` run deletion, even if the
+    /// guard was leaked or is still held by a detached task. As on termination, deletion is given up
+    /// after [`SHUTDOWN_BUDGET`], so the process still exits when it keeps failing. Registering the
+    /// same guard again does nothing. Fails if the handler can't be registered with the C runtime.
+    pub fn arm_at_exit(&self) -> io::Result<()> {
+        arm_at_exit(&self.inner)
+    }
+
+    /// Spawn a watchdog process deleting the target once this process exited, however it exits.
+    ///
+    /// Unlike drops and [exit handlers](Self::arm_at_exit), the watchdog still deletes the target
+    /// when the process is killed, e.g. by `SIGKILL`, and only once the process is fully gone, so
+    /// on Windows, the running executable can be deleted as well. On Unix, the watchdog is forked,
+    /// and notices the exit by being orphaned; on Windows, PowerShell waits on a handle on this
+    /// process. Either way, a reused process identifier can't be mistaken for this process.
+    /// Deletion is retried for up to 10 seconds, and on Unix skipped if the target was replaced.
+    ///
+    /// The watchdog is stopped for good when the guard is [disarmed](Self::disarm) or deletes the
+    /// target itself. Spawning it again does nothing, and nothing is spawned for dry runs,
+    /// [disabled](GuardBuilder::respect_env) guards or duplicate guards of the host executable.
+    pub fn watchdog(&self) -> io::Result<()> {
+        let inner = &self.inner;
+        if inner.dry_run || inner.disabled || inner.duplicate() {
+            return Ok(());
+        }
+        let mut watchdog = inner.watchdog.lock().unwrap();
+        if watchdog.is_none() {
+            let path = inner.path()?.ok_or(io::ErrorKind::NotFound)?;
+            *watchdog = Some(watchdog::spawn(&path)?);
+        }
+        Ok(())
+    }
+
+    /// Keep the guard from deleting anything, e.g. after detecting a development environment.
+    ///
+    /// Unlike forgetting the guard, its resources are still released when it drops. See
+    /// [`Guard::rearm`] for undoing this.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// fn main() {
+    ///     let _mortem = mortem::hard();
+    ///     if std::env::var_os("CARGO").is_some() {
+    ///         _mortem.disarm(); // running through cargo; keep the executable
+    ///     }
+    /// }
+    /// ```
+    pub fn disarm(&self) {
+        if self.inner.armed.swap(false, Ordering::SeqCst) {
+            self.inner.emit(Event::Disarmed);
+        }
+        self.inner.cancel_watchdog();
+    }
+
+    /// Have a [disarmed](Guard::disarm) guard delete the target after all.
+    pub fn rearm(&self) {
+        if !self.inner.armed.swap(true, Ordering::SeqCst) {
+            self.inner.emit(Event::Armed);
+        }
+    }
+
+    /// Whether the guard deletes the target when dropped, i.e. it isn't
+    /// [disarmed](Guard::disarm).
+    pub fn is_armed(&self) -> bool {
+        self.inner.armed.load(Ordering::SeqCst)
+    }
+
+    /// Handle deleting the target mid-run, e.g. as soon as it's found to be tampered with, rather
+    /// than once the guard drops.
+    ///
+    /// Once triggered, deletion doesn't run again when the guard drops.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::{Hash, Hasher};
+    /// use std::time::Duration;
+    ///
+    /// fn digest() -> Option<u64> {
+    ///     let contents = std::fs::read(std::env::current_exe().ok()?).ok()?;
+    ///     let mut hasher = DefaultHasher::new();
+    ///     contents.hash(&mut hasher);
+    ///     Some(hasher.finish())
+    /// }
+    ///
+    /// let mortem = mortem::hard();
+    /// let trigger = mortem.trigger_handle();
+    /// let original = digest();
+    /// std::thread::spawn(move || loop {
+    ///     std::thread::sleep(Duration::from_secs(1));
+    ///     if digest() != original {
+    ///         trigger.trigger();
+    ///         break;
+    ///     }
+    /// });
+    ///
+    /// // some code
+    /// println!("Hello!");
+    /// # mortem.disarm();
+    /// ```
+    pub fn trigger_handle(&self) -> TriggerHandle {
+        TriggerHandle {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Delete the target now, consuming the guard, and return how it went.
+    ///
+    /// Unlike dropping the guard, this lets failures be acted on, e.g. by exiting with an error.
+    /// As with [`Report::into_result`], refusals aren't errors. Returns `Ok(None)` if deletion
+    /// doesn't run, as for [disarmed](Guard::disarm) guards.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// fn main() -> Result<(), mortem::Error> {
+    ///     let target = std::env::temp_dir().join("mortem-execute-example");
+    /// #   std::fs::write(&target, "").unwrap();
+    ///     let mortem = mortem::Guard::builder().target(target).build();
+    ///
+    ///     // some code
+    ///     println!("Hello!");
+    ///
+    ///     if let Some(report) = mortem.execute()? {
+    ///         println!("{:?}", report.outcome);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn execute(self) -> Result<Option<Report>, Error> {
+        self.inner.capture.store(true, Ordering::SeqCst);
+        let _ = self.inner.run();
+        let report = self.inner.captured.lock().unwrap().take();
+        report.map(Report::into_result).transpose()
+    }
+
+    /// Do the groundwork of deletion now, while the program is healthy, leaving less to go wrong
+    /// once the guard drops.
+    ///
+    /// Resolves the path of the target, opens a handle on its directory to delete it relative to,
+    /// and with [`Strategy::ClearAttributes`] among the strategies, clears the attributes
+    /// protecting the target. Fails if any of these does, in which case deletion still goes
+    /// through all of them when the guard drops. Cleared attributes aren't restored when the guard
+    /// is [disarmed](Guard::disarm) afterwards. Does nothing for guards that won't delete
+    /// anything, such as refusing or [disabled](GuardBuilder::respect_env) ones, or in dry runs.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// fn main() -> std::io::Result<()> {
+    ///     let target = std::env::temp_dir().join("mortem-prepare-example");
+    /// #   std::fs::write(&target, "").unwrap();
+    ///     let mortem = mortem::Guard::builder().target(target).build();
+    ///     mortem.prepare()?;
+    ///
+    ///     // some code
+    ///     println!("Hello!");
+    ///
+    ///     mortem.commit().map_err(std::io::Error::other)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn prepare(&self) -> io::Result<()> {
+        let inner = &self.inner;
+        if inner.dry_run || inner.disabled || inner.duplicate() || inner.refusal.is_some() {
+            return Ok(());
+        }
+
+        let path = inner.path()?.ok_or(io::ErrorKind::NotFound)?.into_owned();
+        if inner.directory.get().is_none() {
+            let _ = inner.directory.set(DirHandle::open(&path)?);
+        }
+        if inner.strategies.contains(&Strategy::ClearAttributes) && strategy::protected(&path)? {
+            strategy::clear_attributes(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the target now, once [prepared](Guard::prepare); the same as [`Guard::execute`].
+    pub fn commit(self) -> Result<Option<Report>, Error> {
+        self.execute()
+    }
+
+    /// Also remove the file at `path`, before the target.
+    ///
+    /// Meant for files the executable created, such as configuration or extracted resources.
+    /// Additional paths are removed in the order they were registered, once deletion of the
+    /// target isn't refused, and the target comes last, unless under a
+    /// [deadline](GuardBuilder::deadline). Relative paths are resolved against the
+    /// current working directory right away. Paths already gone are skipped; others that can't be
+    /// removed are reported as [`Degradation::NotRemoved`].
+    ///
+    /// Guards [wiping](GuardBuilder::wipe) the target wipe additional paths as well, directories
+    /// file by file, and rename every entry to a random name before unlinking it. Paths that
+    /// can't be wiped are removed regardless, reporting [`Degradation::NotWiped`].
+    pub fn also_remove(&self, path: impl Into<PathBuf>) {
+        let path = resolve::resolve_relative(path.into(), None);
+        self.inner.extras.lock().unwrap().push((path, false));
+    }
+
+    /// Also remove the directory at `path` with all its contents, before the target.
+    ///
+    /// Symlinks inside aren't followed. See [`Guard::also_remove`]. Directories are only removed
+    /// once [unlocked](GuardBuilder::i_understand_this_is_destructive); otherwise this is reported
+    /// as [`Degradation::Locked`].
+    pub fn also_remove_dir_all(&self, path: impl Into<PathBuf>) {
+        if !self.inner.destructive {
+            #[cfg(feature = "tracing")]
+            warn!("removing directories isn't unlocked; skipping it");
+            let degradation = Degradation::Locked(Destructive::RemoveDirAll);
+            self.inner.degraded.lock().unwrap().push(degradation);
+            return;
+        }
+        let path = resolve::resolve_relative(path.into(), None);
+        self.inner.authorize(Destructive::RemoveDirAll);
+        self.inner.extras.lock().unwrap().push((path, true));
     }
 
+    /// Hand over deletion of the target to the program `command` executes, and disarm the guard.
+    ///
+    /// Meant for executing the program in place of this one with `execve`, e.g. through
+    /// [`CommandExt::exec`](std::os::unix::process::CommandExt::exec), as the guard doesn't run
+    /// then. The target, and paths this guard [inherited](GuardBuilder::inherit) itself, are
+    /// deleted by the first guard inheriting them in the program; other additional paths aren't
+    /// handed over. Should executing fail, [rearm](Guard::rearm) the guard to delete the target
+    /// after all.
+    ///
+    /// ### Usage
+    /// ```no_run
+    /// # #[cfg(unix)]
+    /// # fn main() {
+    /// use std::os::unix::process::CommandExt;
+    ///
+    /// let mortem = mortem::Guard::builder().inherit(true).build();
+    ///
+    /// // bootstrap, then
+    /// let mut next = std::process::Command::new("./stage-2");
+    /// mortem.hand_over(&mut next).unwrap();
+    /// let err = next.exec();
+    /// mortem.rearm();
+    /// # }
+    /// # #[cfg(not(unix))]
+    /// # fn main() {}
+    /// ```
+    pub fn hand_over(&self, command: &mut Command) -> io::Result<()> {
+        let mut paths = self.inner.inherited.clone();
+        if let Some(path) = self.inner.path()? {
+            paths.push(std::path::absolute(path)?);
+        }
+        command.env(inherit::VAR, inherit::encode(&paths)?);
+        self.disarm();
+        Ok(())
+    }
+
+    /// Operations the guard would perform if dropped now, in order, without performing them.
+    ///
+    /// Meant for showing what will be removed, and for comparing it against expectations in
+    /// tests. Empty if the guard is [disarmed](Guard::disarm) or refuses deletion; the
+    /// [condition](GuardBuilder::only_if) isn't evaluated. The target is left out if its path
+    /// can't be resolved.
+    pub fn plan(&self) -> Vec<Operation> {
+        let inner = &self.inner;
+        if inner.disabled
+            || inner.duplicate()
+            || !inner.armed.load(Ordering::SeqCst)
+            || !inner.pending.load(Ordering::SeqCst)
+            || inner.refusal.is_some()
+        {
+            return Vec::new();
+        }
+
+        let extras = inner.extras.lock().unwrap();
+        let mut plan: Vec<_> = extras
+            .iter()
+            .map(|(path, directory)| match directory {
+                true => Operation::RemoveDirAll(path.clone()),
+                false => Operation::RemoveFile(path.clone()),
+            })
+            .collect();
+        if let (Some((package, command)), Ok(Some(path))) = (&inner.delegate, inner.path()) {
+            plan.push(Operation::Delegate {
+                command: strategy::delegation(command, package, &path),
+                path: path.into_owned(),
+                package: package.clone(),
+            });
+        } else if let Ok(Some(path)) = inner.path() {
+            let mut strategies = match inner.ensure() {
+                true => inner.strategies.clone(),
+                false => inner.strategies[..1].to_vec(),
+            };
+            if let (Some(_), Some(fallback)) = (inner.deadline, inner.fallback) {
+                strategies.push(fallback);
+            }
+            plan.push(Operation::Delete {
+                path: path.into_owned(),
+                strategies,
+            });
+        }
+        plan
+    }
+
+    /// Delete the target when the process is asked to terminate, by `SIGINT`, `SIGTERM` or
+    /// `SIGHUP` on unix, or a console control event such as Ctrl-C on Windows.
+    ///
+    /// These terminate the process without unwinding, so the guard would never drop. The handlers
+    /// are installed once per process, replacing earlier handlers of these signals, though those
+    /// ignored at the time stay ignored. Once deletion ran, the process terminates the way it
+    /// would have. Deletion is given up after [`SHUTDOWN_BUDGET`], even by hard guards, so the
+    /// process still terminates when it keeps failing. If the handlers can't be installed,
+    /// [`Degradation::NoSignalHandling`] is reported.
+    ///
+    /// Only with the `signals` feature.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// fn main() {
+    ///     let _mortem = mortem::hard().with_signal_handling();
+    ///
+    ///     // some code
+    ///     println!("Hello!")
+    ///
+    ///     // _mortem drops, or Ctrl-C is hit, and the executable is deleted
+    /// }
+    /// ```
+    #[cfg(feature = "signals")]
+    pub fn with_signal_handling(self) -> Self {
+        if let Err(err) = signals::register(&self.inner) {
+            #[cfg(feature = "tracing")]
+            warn!(%err, "failed to install signal handlers");
+            let degradation = Degradation::NoSignalHandling(err.kind());
+            self.inner.degraded.lock().unwrap().push(degradation);
+        }
+        self
+    }
+
+    /// Run `step` right before deletion, after steps registered earlier.
+    ///
+    /// Steps run whether or not deletion ends up happening, and a panicking step doesn't prevent
+    /// deletion. See [`Guard::drop_before_delete`] for tearing down values.
+    pub fn run_before_delete(&self, step: impl FnOnce() + Send + 'static) {
+        self.inner.teardown.lock().unwrap().push(Box::new(step));
+    }
+
+    /// Drop `value` right before deletion, after steps registered earlier.
+    ///
+    /// Meant for flushing and shutting down background log writers, such as the `WorkerGuard` of
+    /// `tracing-appender`, so they aren't still appending while the files around them are deleted.
+    /// Note that the guard's own logs after this point go wherever the writer went.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// # struct WorkerGuard;
+    /// # fn appender() -> WorkerGuard { WorkerGuard }
+    /// fn main() {
+    ///     let _mortem = mortem::hard();
+    ///     let writer = appender(); // e.g. tracing_appender::non_blocking(..).1
+    ///     _mortem.drop_before_delete(writer);
+    ///
+    ///     // _mortem drops, flushing the writer before the executable is deleted
+    /// }
+    /// ```
+    pub fn drop_before_delete<T: Send + 'static>(&self, value: T) {
+        self.run_before_delete(move || drop(value));
+    }
+
+    /// Install `reporter` as the panic hook, writing its crash report before deletion runs.
+    ///
+    /// Crash reporters such as `human-panic` write their report from the panic hook, and in
+    /// `panic = "abort"` builds, guards delete from the panic hook as well; installed separately,
+    /// the two replace each other's hook. Through the guard, the reporter always runs first, and
+    /// deletion right after it in abort builds, or once the guard drops otherwise. Like the hooks
+    /// of crash reporters, this replaces the previously installed hook.
+    ///
+    /// `reporter` returns the path of the report it wrote, if any. With `clean_up`, the report is
+    /// deleted along with the target, e.g. once it has been shown or uploaded.
+    ///
+    /// ### Usage
+    /// ```rust
+    /// # use std::path::PathBuf;
+    /// # fn write_report(_: &std::panic::PanicHookInfo<'_>) -> Option<PathBuf> { None }
+    /// fn main() {
+    ///     let _mortem = mortem::hard();
+    ///     // e.g. human_panic::handle_dump(&metadata, info)
+    ///     _mortem.set_panic_reporter(write_report, false);
+    ///
+    ///     // on panic, the report is written before the executable is deleted
+    /// }
+    /// ```
+    pub fn set_panic_reporter<F>(&self, reporter: F, clean_up: bool)
+    where
+        F: Fn(&std::panic::PanicHookInfo<'_>) -> Option<PathBuf> + Send + Sync + 'static,
+    {
+        let inner = Arc::downgrade(&self.inner);
+        std::panic::set_hook(Box::new(move |info| {
+            let report = reporter(info);
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            if let (true, Some(report)) = (clean_up, report) {
+                inner.panic_reports.lock().unwrap().push(report);
+            }
+            #[cfg(panic = "abort")]
+            {
+                #[cfg(feature = "tracing")]
+                debug!("panicking in abort build; running mortem guard after crash reporter");
+                let _ = inner.run();
+            }
+        }));
+    }
+
+    /// Create a guard from a full configuration.
+    ///
+    /// See [`GuardConfig`]. Panics if the configuration is invalid; build it with
+    /// [`GuardBuilder::try_build`] instead if it isn't fixed at compile time.
+    pub fn from_config(config: GuardConfig) -> Self {
+        GuardBuilder::from(config).build()
+    }
+
+    /// Create a guard that tries to delete the executable once.
+    ///
+    /// Failures, including failing to resolve the path of the executable, are reported as
+    /// [`Outcome::Failed`] rather than panicking in drop; see [`Guard::execute`] for handling them
+    /// as an [`Error`].
     pub fn soft() -> Self {
-        Self::new(false)
+        Self::with_mode(Mode::Soft)
     }
 
     /// Create a guard that blocks till the executable is successfully deleted
     ///
     /// See [`hard`].
     pub fn hard() -> Self {
-        Self::new(true)
+        Self::with_mode(Mode::Hard)
+    }
+
+    /// Create a guard that makes a bounded effort to delete the executable
+    ///
+    /// See [`bounded`].
+    pub fn bounded(retries: u32, delay: Duration) -> Self {
+        GuardBuilder::new().bounded(retries, delay).build()
+    }
+
+    /// Create a guard that blocks till the executable is successfully deleted, but at most
+    /// `timeout`
+    ///
+    /// See [`hard_with_timeout`].
+    pub fn hard_with_timeout(timeout: Duration) -> Self {
+        GuardBuilder::new()
+            .mode(Mode::Hard)
+            .deadline(timeout)
+            .build()
+    }
+
+    /// Create a guard that overwrites the executable before blocking till it's deleted
+    ///
+    /// See [`secure`].
+    pub fn secure() -> Self {
+        Self::with_mode(Mode::Secure)
+    }
+
+    /// Create a guard that keeps trying to delete the executable on a background thread once
+    /// dropped, without blocking
+    ///
+    /// See [`GuardBuilder::detached`].
+    ///
+    /// ### Usage
+    /// ```rust
+    /// let mortem = mortem::Guard::detached();
+    /// let deletion = mortem.handle();
+    ///
+    /// // some code
+    /// drop(mortem);
+    ///
+    /// // print the results, then make sure the executable is gone before exiting
+    /// deletion.join();
+    /// ```
+    pub fn detached() -> Self {
+        GuardBuilder::new().mode(Mode::Hard).detached(true).build()
+    }
+
+    /// Handle on the deletion of the guard, for waiting till it's done once the guard dropped.
+    ///
+    /// Meant for [detached](GuardBuilder::detached) guards, but works for any.
+    pub fn handle(&self) -> DeletionHandle {
+        let mut completion = self.inner.completion.lock().unwrap();
+        DeletionHandle {
+            completion: Arc::clone(completion.get_or_insert_default()),
+        }
     }
 }
 
+impl fmt::Debug for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = &self.inner;
+        f.debug_struct("Guard")
+            .field("mode", &format_args!("{}", inner.mode()))
+            .field("target", &format_args!("{}", inner.display()))
+            .field("canonicalization", &inner.canonicalization)
+            .field("retries", &inner.retries)
+            .field("delay", &inner.delay)
+            .field("reporter", &inner.reporter)
+            .field("follow_renames", &inner.handle.is_some())
+            .field("hold_directory", &inner.directory.get().is_some())
+            .field("skip_if_replaced", &inner.identity.is_some())
+            .field("events", &inner.events.is_some())
+            .field("wine", &inner.wine)
+            .field("only_if", &inner.condition.is_some())
+            .field("callbacks", &inner.callbacks)
+            .field("detached", &inner.detached)
+            .field("armed", &inner.armed.load(Ordering::SeqCst))
+            .field("pending", &inner.pending.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+/// Formats as e.g. `hard guard for /usr/local/bin/tool`.
+impl fmt::Display for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} guard for {}",
+            self.inner.mode(),
+            self.inner.display()
+        )
+    }
+}
+
+impl Inner {
+    fn mode(&self) -> Mode {
+        match self.retries {
+            Retries::Never => Mode::Soft,
+            Retries::Limit(retries) => Mode::Bounded {
+                retries,
+                delay: self.delay,
+            },
+            Retries::Forever if self.strategies != Strategy::ESCALATION => {
+                Mode::Custom(self.strategies.clone())
+            }
+            Retries::Forever if self.wipe => Mode::Secure,
+            Retries::Forever => Mode::Hard,
+        }
+    }
+
+    /// Displayable target path, redacted if configured.
+    fn display(&self) -> TargetDisplay {
+        TargetDisplay {
+            path: original_path(&self.target).ok(),
+            redact: self.redact,
+        }
+    }
+}
+
+/// Target path formatted for display, optionally redacted.
+struct TargetDisplay {
+    path: Option<PathBuf>,
+    redact: bool,
+}
+
+impl fmt::Display for TargetDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            None => f.write_str("<unresolved>"),
+            Some(path) => shown(path, self.redact).display().fmt(f),
+        }
+    }
+}
+
+/// `path` as it may be logged or reported, hashed if `redact`.
+fn shown(path: &Path, redact: bool) -> Cow<'_, Path> {
+    if redact {
+        Cow::Owned(format!("<redacted:{:016x}>", fnv1a(path)).into())
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// 64-bit FNV-1a hash of `path`, which unlike `std`'s hashers is stable across builds, so
+/// redacted paths can still be correlated between logs.
+fn fnv1a(path: &Path) -> u64 {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
 impl Drop for Guard {
     fn drop(&mut self) {
         #[cfg(feature = "tracing")]
-        debug!(ensure = self.ensure, "dropping mortem guard");
+        debug!(retries = ?self.inner.retries, "dropping mortem guard");
 
-        loop {
-            match current_exe() {
-                Err(_) if self.ensure => continue,
-                Err(_) => {
+        if self.inner.detached {
+            let inner = Arc::clone(&self.inner);
+            let spawned = thread::Builder::new().name("mortem".into()).spawn(move || {
+                let _ = inner.run();
+                inner.settle(None);
+            });
+            match spawned {
+                Ok(_) => return,
+                Err(_err) => {
                     #[cfg(feature = "tracing")]
-                    error!(ensure = self.ensure, "failed to delete executable");
-                    panic!("failed to delete executable")
-                }
-                Ok(path) => {
-                    if remove_file(path).is_err() && self.ensure {
-                        #[cfg(feature = "tracing")]
-                        error!(
-                            ensure = self.ensure,
-                            "failed to delete executable; retrying"
-                        );
-                        continue;
-                    }
+                    warn!(err = %_err, "failed to spawn deletion thread; deleting in place");
                 }
             }
-            break;
         }
+
+        let _ = self.inner.run();
+        self.inner.settle(None);
+    }
+}
+
+/// Guards to run deletion of at process exit.
+static AT_EXIT: Mutex<Vec<Weak<Inner>>> = Mutex::new(Vec::new());
+
+/// Register `inner` to run deletion at process exit.
+fn arm_at_exit(inner: &Arc<Inner>) -> io::Result<()> {
+    extern "C" {
+        fn atexit(callback: extern "C" fn()) -> std::ffi::c_int;
+    }
+
+    extern "C" fn at_exit() {
+        let guards = std::mem::take(&mut *AT_EXIT.lock().unwrap_or_else(PoisonError::into_inner));
+        for inner in guards.iter().filter_map(Weak::upgrade) {
+            #[cfg(feature = "tracing")]
+            debug!("exiting; running mortem guard at exit");
+            let _ = inner.run_within(SHUTDOWN_BUDGET);
+        }
+    }
+
+    static REGISTERED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    // SAFETY: the callback is a plain function living as long as the process
+    if !*REGISTERED.get_or_init(|| unsafe { atexit(at_exit) } == 0) {
+        return Err(io::Error::other("failed to register handler with atexit"));
+    }
+    let mut guards = AT_EXIT.lock().unwrap_or_else(PoisonError::into_inner);
+    if !guards
+        .iter()
+        .any(|guard| guard.as_ptr() == Arc::as_ptr(inner))
+    {
+        guards.retain(|guard| guard.strong_count() > 0);
+        guards.push(Arc::downgrade(inner));
     }
+    Ok(())
+}
+
+/// Run deletion from the panic hook, as drops don't run in `panic = "abort"` builds.
+///
+/// The previous hook runs first, so the panic message is still reported. Only installed once per
+/// guard.
+fn arm_panic_hook(inner: &Arc<Inner>) {
+    if inner.hooked.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let inner = Arc::downgrade(inner);
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        if let Some(inner) = inner.upgrade() {
+            #[cfg(feature = "tracing")]
+            debug!("panicking; running mortem guard from the panic hook");
+            let _ = inner.run();
+        }
+    }));
 }
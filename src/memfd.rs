@@ -0,0 +1,133 @@
+//! Re-executing the host executable from memory, so it can be deleted right away.
+//!
+//! The executable is copied into an anonymous file created with `memfd_create`, and executed
+//! again from `/proc/self/fd`. [`VAR`] tells the copy which file it was copied from, by path and
+//! identity, so the copy deletes that file and no other.
+
+use std::io;
+use std::path::PathBuf;
+
+/// Environment variable carrying the executable a copy was made from.
+#[cfg(target_os = "linux")]
+const VAR: &str = "MORTEM_MEMFD";
+
+/// Where the host executable runs from.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) enum Origin {
+    /// A file on disk, to be re-executed from memory.
+    Disk,
+    /// A copy in memory made from the executable at the path.
+    Copy(PathBuf),
+    /// Memory, without having been copied by a guard, so there's nothing to re-execute.
+    Foreign,
+}
+
+/// Where the host executable runs from.
+///
+/// Takes [`VAR`] out of the environment, so processes executed by the copy don't mistake
+/// themselves for one.
+#[cfg(target_os = "linux")]
+pub(crate) fn origin() -> Origin {
+    use std::env;
+    use std::os::unix::ffi::OsStrExt;
+
+    let copied = env::var_os(VAR);
+    if copied.is_some() {
+        env::remove_var(VAR);
+    }
+    let in_memory = std::fs::read_link("/proc/self/exe")
+        .is_ok_and(|path| path.as_os_str().as_bytes().starts_with(b"/memfd:"));
+    if !in_memory {
+        return Origin::Disk;
+    }
+    match copied.filter(|_| !crate::inherit::secure_execution()) {
+        Some(copied) => decode(copied.as_bytes()).map_or(Origin::Foreign, Origin::Copy),
+        None => Origin::Foreign,
+    }
+}
+
+/// Where the host executable runs from.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn origin() -> Origin {
+    Origin::Disk
+}
+
+/// Copy the host executable into memory and execute the copy in place of this process.
+///
+/// Only returns on failure. Refused under secure execution, as the copy would run without the
+/// privileges the executable grants.
+#[cfg(target_os = "linux")]
+pub(crate) fn reexec() -> io::Error {
+    if crate::inherit::secure_execution() {
+        return io::ErrorKind::PermissionDenied.into();
+    }
+    execute_copy().unwrap_err()
+}
+
+#[cfg(target_os = "linux")]
+fn execute_copy() -> io::Result<std::convert::Infallible> {
+    use std::env;
+    use std::ffi::OsString;
+    use std::fs::File;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    let path = std::fs::read_link("/proc/self/exe")?;
+    let mut executable = File::open("/proc/self/exe")?;
+    let metadata = executable.metadata()?;
+
+    let flags = libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING;
+    // SAFETY: the name is nul-terminated
+    let fd = unsafe { libc::memfd_create(c"mortem".as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor was just created, and is owned from here on
+    let mut memory = unsafe { File::from_raw_fd(fd) };
+    io::copy(&mut executable, &mut memory)?;
+    let seals = libc::F_SEAL_SEAL | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE;
+    // SAFETY: the descriptor is open
+    if unsafe { libc::fcntl(memory.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // executing a file open for writing fails on older kernels
+    let copy = File::open(format!("/proc/self/fd/{}", memory.as_raw_fd()))?;
+    drop(memory);
+
+    let mut copied = format!("{}:{}:", metadata.dev(), metadata.ino()).into_bytes();
+    copied.extend_from_slice(path.as_os_str().as_bytes());
+
+    let mut args = env::args_os();
+    let mut command = Command::new(format!("/proc/self/fd/{}", copy.as_raw_fd()));
+    if let Some(arg0) = args.next() {
+        command.arg0(arg0);
+    }
+    Err(command
+        .args(args)
+        .env(VAR, OsString::from_vec(copied))
+        .exec())
+}
+
+/// Copy the host executable into memory and execute the copy in place of this process.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn reexec() -> io::Error {
+    io::ErrorKind::Unsupported.into()
+}
+
+/// Path of the executable a copy was made from, as long as it's still the same file.
+#[cfg(target_os = "linux")]
+fn decode(copied: &[u8]) -> Option<PathBuf> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut parts = copied.splitn(3, |&byte| byte == b':');
+    let mut number = || std::str::from_utf8(parts.next()?).ok()?.parse::<u64>().ok();
+    let (dev, ino) = (number()?, number()?);
+    let path = PathBuf::from(OsStr::from_bytes(parts.next()?));
+    let metadata = std::fs::symlink_metadata(&path).ok()?;
+    (path.is_absolute() && metadata.dev() == dev && metadata.ino() == ino).then_some(path)
+}
@@ -0,0 +1,213 @@
+//! Detection of platforms needing special handling.
+
+/// Whether the process runs under Wine.
+///
+/// Wine provides Windows semantics on top of a POSIX filesystem, so the running executable can be
+/// renamed but not deleted, while repeating the deletion doesn't help either.
+/// Detected through the `wine_get_version` export of Wine's `ntdll`, or Wine's environment.
+#[cfg(windows)]
+pub fn is_wine() -> bool {
+    use std::ffi::{c_char, c_void};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetModuleHandleA(name: *const c_char) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+    }
+
+    // SAFETY: both names are nul-terminated, and ntdll is loaded in every process
+    let exported = unsafe {
+        let ntdll = GetModuleHandleA(c"ntdll.dll".as_ptr());
+        !ntdll.is_null() && !GetProcAddress(ntdll, c"wine_get_version".as_ptr()).is_null()
+    };
+    exported || std::env::var_os("WINELOADERNOEXEC").is_some()
+}
+
+/// Whether the process runs under Wine.
+///
+/// Always `false` outside Windows.
+#[cfg(not(windows))]
+pub fn is_wine() -> bool {
+    false
+}
+
+/// Whether the file at `path` is marked for deletion, pending the close of all handles on it.
+///
+/// On Windows, a file deleted while others hold handles on it lingers in this state, and any
+/// attempt to open or delete it again fails with an access error.
+/// Detected through `NtQueryInformationFile` where the file can still be opened, and otherwise
+/// through the status of the failed open.
+#[cfg(windows)]
+pub fn is_delete_pending(path: &std::path::Path) -> bool {
+    use std::ffi::c_void;
+    use std::os::windows::ffi::OsStrExt;
+
+    const FILE_SHARE_ALL: u32 = 0x1 | 0x2 | 0x4;
+    const OPEN_EXISTING: u32 = 3;
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    const INVALID_HANDLE_VALUE: *mut c_void = -1isize as *mut c_void;
+    const STATUS_DELETE_PENDING: i32 = 0xC000_0056_u32 as i32;
+    const FILE_STANDARD_INFORMATION: u32 = 5;
+
+    #[repr(C)]
+    struct IoStatusBlock {
+        status: usize,
+        information: usize,
+    }
+
+    #[repr(C)]
+    struct FileStandardInformation {
+        allocation_size: i64,
+        end_of_file: i64,
+        number_of_links: u32,
+        delete_pending: u8,
+        directory: u8,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateFileW(
+            name: *const u16,
+            access: u32,
+            share: u32,
+            security: *mut c_void,
+            disposition: u32,
+            flags: u32,
+            template: *mut c_void,
+        ) -> *mut c_void;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        fn NtQueryInformationFile(
+            handle: *mut c_void,
+            status: *mut IoStatusBlock,
+            information: *mut c_void,
+            length: u32,
+            class: u32,
+        ) -> i32;
+        fn RtlGetLastNtStatus() -> i32;
+    }
+
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    // SAFETY: `name` is nul-terminated, and the handle is closed before returning
+    unsafe {
+        let handle = CreateFileW(
+            name.as_ptr(),
+            0,
+            FILE_SHARE_ALL,
+            std::ptr::null_mut(),
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS,
+            std::ptr::null_mut(),
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return RtlGetLastNtStatus() == STATUS_DELETE_PENDING;
+        }
+
+        let mut status = IoStatusBlock {
+            status: 0,
+            information: 0,
+        };
+        let mut information: FileStandardInformation = std::mem::zeroed();
+        let queried = NtQueryInformationFile(
+            handle,
+            &mut status,
+            (&mut information as *mut FileStandardInformation).cast(),
+            std::mem::size_of::<FileStandardInformation>() as u32,
+            FILE_STANDARD_INFORMATION,
+        );
+        CloseHandle(handle);
+        queried >= 0 && information.delete_pending != 0
+    }
+}
+
+/// Whether the process runs under a debugger, as told by `IsDebuggerPresent`.
+///
+/// See [`DebuggerPolicy`](crate::DebuggerPolicy).
+#[cfg(windows)]
+pub fn is_debugged() -> bool {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn IsDebuggerPresent() -> i32;
+    }
+
+    // SAFETY: IsDebuggerPresent has no preconditions
+    unsafe { IsDebuggerPresent() != 0 }
+}
+
+/// Whether the process runs under a debugger, as told by the `TracerPid` of `/proc/self/status`.
+///
+/// Any tracer counts, such as `strace`. See [`DebuggerPolicy`](crate::DebuggerPolicy).
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn is_debugged() -> bool {
+    std::fs::read_to_string("/proc/self/status").is_ok_and(|status| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("TracerPid:"))
+            .is_some_and(|pid| pid.trim() != "0")
+    })
+}
+
+/// Whether the process runs under a debugger.
+///
+/// Always `false` on platforms other than Linux and Windows, where debuggers aren't detected. See
+/// [`DebuggerPolicy`](crate::DebuggerPolicy).
+#[cfg(not(any(windows, target_os = "linux", target_os = "android")))]
+pub fn is_debugged() -> bool {
+    false
+}
+
+/// Whether the file at `path` is marked for deletion, pending the close of all handles on it.
+///
+/// Always `false` outside Windows, where deletion takes effect immediately.
+#[cfg(not(windows))]
+pub fn is_delete_pending(_path: &std::path::Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(windows)]
+    fn recognizes_files_pending_deletion() {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        const FILE_SHARE_ALL: u32 = 0x1 | 0x2 | 0x4;
+        const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x0400_0000;
+
+        let path = std::env::temp_dir().join(format!("mortem-pending-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        assert!(!is_delete_pending(&path));
+
+        // once the handle deleting it on close is closed, the other keeps it pending deletion
+        let open = |flags| {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .share_mode(FILE_SHARE_ALL)
+                .custom_flags(flags)
+                .open(&path)
+                .unwrap()
+        };
+        let held = open(0);
+        drop(open(FILE_FLAG_DELETE_ON_CLOSE));
+        assert!(is_delete_pending(&path));
+
+        drop(held);
+        assert!(!path.exists() && !is_delete_pending(&path));
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn files_are_never_pending_deletion() {
+        let path = std::env::temp_dir().join(format!("mortem-pending-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let held = std::fs::File::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!is_delete_pending(&path));
+        drop(held);
+    }
+}
@@ -0,0 +1,11 @@
+//! Commonly used types, for glob importing.
+//!
+//! ```rust
+//! use mortem::prelude::*;
+//!
+//! let _mortem = Guard::with_mode(Mode::Hard);
+//! ```
+
+pub use crate::report::{Event, Outcome, Report};
+pub use crate::strategy::Strategy;
+pub use crate::{Guard, GuardBuilder, Mode};
@@ -0,0 +1,176 @@
+//! Stopping and signalling other processes running the target.
+
+use crate::Termination;
+
+use std::io;
+use std::path::Path;
+
+/// Stop all other processes running the file at `path`, matched by identity.
+///
+/// Sends `SIGTERM`, then `SIGKILL` to those still running after the grace period. Fails with
+/// [`io::ErrorKind::TimedOut`] if any survive that too.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn terminate(path: &Path, termination: &Termination) -> io::Result<()> {
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    let mut running = siblings(path)?;
+
+    let alive = |pid: &libc::pid_t| {
+        // SAFETY: signal 0 only checks whether the process exists
+        let exists = unsafe { libc::kill(*pid, 0) == 0 };
+        // exited children linger as zombies till reaped, which is up to their parent
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap_or_default();
+        let zombie = stat
+            .rsplit_once(") ")
+            .is_some_and(|(_, rest)| rest.starts_with('Z'));
+        exists && !zombie
+    };
+    let wait = |running: &mut Vec<libc::pid_t>, timeout: Duration| {
+        let deadline = Instant::now() + timeout;
+        while !running.is_empty() && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+            running.retain(alive);
+        }
+    };
+
+    for (signal, timeout) in [
+        (libc::SIGTERM, termination.grace),
+        (libc::SIGKILL, termination.kill_timeout),
+    ] {
+        for &pid in &running {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(pid, signal, "signalling process running the target");
+            // SAFETY: sending a signal has no memory safety preconditions
+            unsafe { libc::kill(pid, signal) };
+        }
+        wait(&mut running, timeout);
+        if running.is_empty() {
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("{} processes still running the target", running.len()),
+    ))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn terminate(_path: &Path, _termination: &Termination) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Ask all other processes running the file at `path` to finish, matched by identity.
+///
+/// Sends `SIGTERM` without waiting for them to exit.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn broadcast(path: &Path) -> io::Result<()> {
+    for pid in siblings(path)? {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(pid, "asking sibling instance to finish");
+        // SAFETY: sending a signal has no memory safety preconditions
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn broadcast(_path: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Other processes running the file at `path`, matched by identity.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn siblings(path: &Path) -> io::Result<Vec<libc::pid_t>> {
+    use crate::handle::FileId;
+
+    let target = FileId::of(path)?;
+    let own = std::process::id();
+    let mut running = Vec::new();
+    for entry in std::fs::read_dir("/proc")? {
+        let Some(pid) = entry?
+            .file_name()
+            .to_str()
+            .and_then(|pid| pid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        // processes can exit at any moment, and those of other users can't be inspected
+        let exe = Path::new("/proc").join(pid.to_string()).join("exe");
+        if pid != own && FileId::of(&exe).is_ok_and(|id| id == target) {
+            running.push(pid as libc::pid_t);
+        }
+    }
+    Ok(running)
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod tests {
+    use crate::{GuardBuilder, Outcome, Termination};
+
+    use std::io;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::PathBuf;
+    use std::process::{Child, Command};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    /// Copy of `sleep` for a test case, and a process running it.
+    fn running(case: &str) -> (PathBuf, Child) {
+        let path =
+            std::env::temp_dir().join(format!("mortem-process-{}-{case}", std::process::id()));
+        std::fs::copy("/bin/sleep", &path).unwrap();
+        // processes forked by other tests while the copy was written may keep it busy for a moment
+        for _ in 0..100 {
+            match Command::new(&path).arg("30").spawn() {
+                Ok(child) => return (path, child),
+                Err(err) if err.kind() == io::ErrorKind::ExecutableFileBusy => {
+                    sleep(Duration::from_millis(10))
+                }
+                Err(err) => panic!("failed to run copy of sleep: {err}"),
+            }
+        }
+        panic!("copy of sleep stayed busy");
+    }
+
+    #[test]
+    fn stops_processes_running_the_target_before_deleting_it() {
+        let (path, mut child) = running("terminate");
+        let termination = Termination {
+            grace: Duration::from_secs(5),
+            kill_timeout: Duration::from_secs(1),
+        };
+        let report = GuardBuilder::new()
+            .target(&path)
+            .terminate(termination)
+            .i_understand_this_is_destructive()
+            .build()
+            .execute()
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.outcome, Outcome::Deleted);
+        assert_eq!(report.degraded, []);
+        // already exited by the time the guard is done
+        let status = child.try_wait().unwrap().expect("process kept running");
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn asks_sibling_instances_to_finish() {
+        let (path, mut child) = running("broadcast");
+        let report = GuardBuilder::new()
+            .target(&path)
+            .broadcast(true)
+            .i_understand_this_is_destructive()
+            .build()
+            .execute()
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.outcome, Outcome::Deleted);
+        assert_eq!(report.degraded, []);
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+        assert!(!path.exists());
+    }
+}
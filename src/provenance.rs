@@ -0,0 +1,275 @@
+//! Build identifiers embedded in executables, for checking the provenance of targets.
+//!
+//! Each executable format records an identifier unique to the build: the `NT_GNU_BUILD_ID` note
+//! in ELF, the `LC_UUID` load command in Mach-O and the CodeView record of the debug directory in
+//! PE. They're parsed from the file itself, so any format can be checked on any platform.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// `NT_GNU_BUILD_ID`.
+const ELF_BUILD_ID: u32 = 3;
+/// `LC_UUID`.
+const MACHO_UUID: u32 = 0x1b;
+/// `IMAGE_DEBUG_TYPE_CODEVIEW`.
+const PE_CODEVIEW: u32 = 2;
+/// Upper bound on the headers walked, so malformed files can't make us read indefinitely.
+const MAX_ENTRIES: u32 = 256;
+/// Upper bound on the size of an ELF note segment read.
+const MAX_NOTES: u64 = 64 * 1024;
+
+/// Build identifier of the host executable.
+///
+/// On Linux, read through `/proc/self/exe`, which is the running image even if its path was
+/// replaced since.
+pub(crate) fn own() -> io::Result<Vec<u8>> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return of(Path::new("/proc/self/exe"));
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    return of(&std::env::current_exe()?);
+}
+
+/// Build identifier of the executable at `path`.
+pub(crate) fn of(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = Image(File::open(path)?);
+    match &file.read(0, 4)?[..] {
+        [0x7f, b'E', b'L', b'F'] => file.elf(),
+        [0xcf, 0xfa, 0xed, 0xfe] => file.macho(),
+        [b'M', b'Z', ..] => file.pe(),
+        _ => Err(unidentified()),
+    }
+}
+
+fn unidentified() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "no build identifier found")
+}
+
+/// Executable file, read at offsets.
+struct Image(File);
+
+impl Image {
+    fn read(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        self.0.seek(SeekFrom::Start(offset))?;
+        self.0.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn elf(&mut self) -> io::Result<Vec<u8>> {
+        let ident = self.read(0, 16)?;
+        let wide = ident[4] == 2;
+        let little = ident[5] == 1;
+        let int = |bytes: &[u8]| uint(bytes, little);
+
+        let header = self.read(0, if wide { 64 } else { 52 })?;
+        let (phoff, phentsize, phnum) = match wide {
+            true => (
+                int(&header[32..40]),
+                int(&header[54..56]),
+                int(&header[56..58]),
+            ),
+            false => (
+                int(&header[28..32]),
+                int(&header[42..44]),
+                int(&header[44..46]),
+            ),
+        };
+        if phentsize < if wide { 56 } else { 32 } {
+            return Err(unidentified());
+        }
+        for index in 0..phnum.min(MAX_ENTRIES.into()) {
+            let phdr = self.read(phoff.saturating_add(index * phentsize), phentsize as usize)?;
+            // PT_NOTE
+            if int(&phdr[0..4]) != 4 {
+                continue;
+            }
+            let (offset, size) = match wide {
+                true => (int(&phdr[8..16]), int(&phdr[32..40])),
+                false => (int(&phdr[4..8]), int(&phdr[16..20])),
+            };
+            if size > MAX_NOTES {
+                continue;
+            }
+            let notes = self.read(offset, size as usize)?;
+            let mut notes = &notes[..];
+            while notes.len() >= 12 {
+                let namesz = int(&notes[0..4]) as usize;
+                let descsz = int(&notes[4..8]) as usize;
+                let kind = int(&notes[8..12]);
+                let name_end = 12 + namesz.next_multiple_of(4);
+                let desc_end = name_end + descsz.next_multiple_of(4);
+                if notes.len() < desc_end {
+                    break;
+                }
+                if kind == ELF_BUILD_ID.into() && notes[12..12 + namesz] == *b"GNU\0" {
+                    return Ok(notes[name_end..name_end + descsz].to_vec());
+                }
+                notes = &notes[desc_end..];
+            }
+        }
+        Err(unidentified())
+    }
+
+    fn macho(&mut self) -> io::Result<Vec<u8>> {
+        let header = self.read(0, 32)?;
+        let ncmds = uint(&header[16..20], true);
+        let mut offset = 32;
+        for _ in 0..ncmds.min(MAX_ENTRIES.into()) {
+            let command = self.read(offset, 8)?;
+            let cmdsize = uint(&command[4..8], true);
+            if uint(&command[0..4], true) == MACHO_UUID.into() {
+                return self.read(offset + 8, 16);
+            }
+            if cmdsize < 8 {
+                break;
+            }
+            offset += cmdsize;
+        }
+        Err(unidentified())
+    }
+
+    fn pe(&mut self) -> io::Result<Vec<u8>> {
+        let pe = uint(&self.read(0x3c, 4)?, true);
+        let coff = self.read(pe, 24)?;
+        if coff[0..4] != *b"PE\0\0" {
+            return Err(unidentified());
+        }
+        let sections = uint(&coff[6..8], true);
+        let optional_size = uint(&coff[20..22], true);
+        let optional = self.read(pe + 24, optional_size as usize)?;
+        // the debug directory is the 7th data directory, which start at a width dependent offset
+        let directories = match optional.get(0..2).map(|magic| uint(magic, true)) {
+            Some(0x20b) => 112,
+            Some(0x10b) => 96,
+            _ => return Err(unidentified()),
+        };
+        let debug = directories + 6 * 8;
+        let Some(debug) = optional.get(debug..debug + 8) else {
+            return Err(unidentified());
+        };
+        let (rva, size) = (uint(&debug[0..4], true), uint(&debug[4..8], true));
+
+        // map the directory's address to its offset in the file, through the section it's in
+        let table = pe + 24 + optional_size;
+        let mut directory = None;
+        for index in 0..sections.min(MAX_ENTRIES.into()) {
+            let section = self.read(table + index * 40, 40)?;
+            let address = uint(&section[12..16], true);
+            let raw_size = uint(&section[16..20], true);
+            let raw_offset = uint(&section[20..24], true);
+            if (address..address + raw_size).contains(&rva) {
+                directory = Some(rva - address + raw_offset);
+                break;
+            }
+        }
+        let directory = directory.ok_or_else(unidentified)?;
+
+        for index in 0..(size / 28).min(MAX_ENTRIES.into()) {
+            let entry = self.read(directory + index * 28, 28)?;
+            if uint(&entry[12..16], true) != PE_CODEVIEW.into() {
+                continue;
+            }
+            // `RSDS`, the GUID and the age
+            let record = self.read(uint(&entry[24..28], true), 24)?;
+            if record[0..4] == *b"RSDS" {
+                return Ok(record[4..].to_vec());
+            }
+        }
+        Err(unidentified())
+    }
+}
+
+/// Unsigned integer of up to 8 bytes.
+fn uint(bytes: &[u8], little: bool) -> u64 {
+    let mut buf = [0; 8];
+    match little {
+        true => buf[..bytes.len()].copy_from_slice(bytes),
+        false => buf[8 - bytes.len()..].copy_from_slice(bytes),
+    }
+    match little {
+        true => u64::from_le_bytes(buf),
+        false => u64::from_be_bytes(buf),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GuardBuilder, Outcome, Refusal};
+
+    /// Minimal 64-bit little-endian ELF file with a `GNU` build identifier note of `id`.
+    fn elf(id: &[u8]) -> Vec<u8> {
+        let mut file = vec![0; 120];
+        file[..6].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1]);
+        // one program header, right after the file header
+        file[32..40].copy_from_slice(&64u64.to_le_bytes());
+        file[54..56].copy_from_slice(&56u16.to_le_bytes());
+        file[56..58].copy_from_slice(&1u16.to_le_bytes());
+        // PT_NOTE, right after the program header
+        let size = 16 + id.len().next_multiple_of(4);
+        file[64..68].copy_from_slice(&4u32.to_le_bytes());
+        file[72..80].copy_from_slice(&120u64.to_le_bytes());
+        file[96..104].copy_from_slice(&(size as u64).to_le_bytes());
+        for field in [4, id.len() as u32, ELF_BUILD_ID] {
+            file.extend_from_slice(&field.to_le_bytes());
+        }
+        file.extend_from_slice(b"GNU\0");
+        file.extend_from_slice(id);
+        file.resize(120 + size, 0);
+        file
+    }
+
+    /// Minimal 64-bit Mach-O file with an `LC_UUID` load command of `uuid`, after another command.
+    fn macho(uuid: [u8; 16]) -> Vec<u8> {
+        let mut file = vec![0; 32];
+        file[..4].copy_from_slice(&[0xcf, 0xfa, 0xed, 0xfe]);
+        file[16..20].copy_from_slice(&2u32.to_le_bytes());
+        // some other command, 16 bytes long
+        for field in [0x2a_u32, 16, 0, 0] {
+            file.extend_from_slice(&field.to_le_bytes());
+        }
+        file.extend_from_slice(&MACHO_UUID.to_le_bytes());
+        file.extend_from_slice(&24u32.to_le_bytes());
+        file.extend_from_slice(&uuid);
+        file
+    }
+
+    /// Fresh file with `contents` for a test case.
+    fn write(case: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("mortem-provenance-{}-{case}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_build_identifiers_of_any_format() {
+        let path = write("elf", &elf(&[0xab; 20]));
+        assert_eq!(of(&path).unwrap(), [0xab; 20]);
+        std::fs::write(&path, macho([0xcd; 16])).unwrap();
+        assert_eq!(of(&path).unwrap(), [0xcd; 16]);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        assert_eq!(of(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn refuses_targets_of_other_builds() {
+        let own = write("own", &std::fs::read("/proc/self/exe").unwrap());
+        let other = write("other", &elf(&[0xab; 20]));
+        let delete = |target: &Path| {
+            let guard = GuardBuilder::new()
+                .target(target)
+                .verify_provenance(true)
+                .build();
+            guard.execute().unwrap().unwrap().outcome
+        };
+
+        assert_eq!(delete(&own), Outcome::Deleted);
+        assert_eq!(delete(&other), Outcome::Refused(Refusal::Provenance));
+        assert!(other.exists());
+        std::fs::remove_file(&other).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+//! Target paths in the form system calls take them.
+//!
+//! Converting a path for a system call may allocate, and may fail on paths the platform can't
+//! represent. Preparing the path when the guard is created leaves neither to the drop, which may
+//! run while the process is dying of memory exhaustion.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Path prepared for system calls: nul-terminated bytes on Unix, nul-terminated UTF-16 on Windows.
+#[derive(Debug)]
+pub(crate) struct RawPath {
+    #[cfg(unix)]
+    raw: std::ffi::CString,
+    #[cfg(windows)]
+    raw: Vec<u16>,
+    #[cfg(not(any(unix, windows)))]
+    raw: PathBuf,
+    path: PathBuf,
+    /// Copy of the path handed out once, for the report of the deletion.
+    spare: Mutex<Option<PathBuf>>,
+}
+
+impl RawPath {
+    pub(crate) fn new(path: &Path) -> io::Result<Self> {
+        #[cfg(unix)]
+        let raw = {
+            use std::os::unix::ffi::OsStrExt;
+            std::ffi::CString::new(path.as_os_str().as_bytes())?
+        };
+        #[cfg(windows)]
+        let raw = {
+            use std::os::windows::ffi::OsStrExt;
+            let raw: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+            if raw[..raw.len() - 1].contains(&0) {
+                return Err(io::ErrorKind::InvalidInput.into());
+            }
+            raw
+        };
+        #[cfg(not(any(unix, windows)))]
+        let raw = path.to_owned();
+        Ok(RawPath {
+            raw,
+            path: path.to_owned(),
+            spare: Mutex::new(Some(path.to_owned())),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Owned copy of the path, without allocating the first time.
+    pub(crate) fn to_path_buf(&self) -> PathBuf {
+        let spare = self.spare.lock().unwrap().take();
+        spare.unwrap_or_else(|| self.path.clone())
+    }
+
+    /// Whether this is the path of the host executable, as resolved through `/proc/self/exe`.
+    ///
+    /// Unlike [`std::env::current_exe`], doesn't allocate. `false` if the link can't be read into
+    /// the buffer, and on other platforms.
+    pub(crate) fn is_current_exe(&self) -> bool {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let mut buf = [0u8; libc::PATH_MAX as usize];
+            // SAFETY: the link path is nul-terminated, and the buffer is writable for its length
+            let len = unsafe {
+                libc::readlink(
+                    c"/proc/self/exe".as_ptr(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                )
+            };
+            // a full buffer may have truncated the link
+            match usize::try_from(len) {
+                Ok(len) if len < buf.len() => self.raw.as_bytes() == &buf[..len],
+                _ => false,
+            }
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        false
+    }
+
+    /// Whether this is the prepared form of `path`, compared without allocating.
+    pub(crate) fn matches(&self, path: &Path) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            self.raw.as_bytes() == path.as_os_str().as_bytes()
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStrExt;
+            path.as_os_str()
+                .encode_wide()
+                .eq(self.raw[..self.raw.len() - 1].iter().copied())
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            self.raw == path
+        }
+    }
+
+    /// Unlink the file at the path.
+    pub(crate) fn unlink(&self) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            // SAFETY: the path is nul-terminated
+            if unsafe { libc::unlink(self.raw.as_ptr()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+        #[cfg(windows)]
+        {
+            #[link(name = "kernel32")]
+            extern "system" {
+                fn DeleteFileW(name: *const u16) -> i32;
+            }
+
+            // SAFETY: the path is nul-terminated
+            if unsafe { DeleteFileW(self.raw.as_ptr()) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            std::fs::remove_file(&self.raw)
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Remote kill-switch, triggering deletion on an authenticated command.
+//!
+//! For fleets of one-shot tools, operators may need to recall a tool before it finished. A guard
+//! [listening](listen) on a socket deletes the executable as soon as it's sent the shared token,
+//! and optionally exits the process, instead of waiting to be dropped.
+//!
+//! ```no_run
+//! use mortem::remote::{self, Auth, Command};
+//! use std::net::SocketAddr;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let addr: SocketAddr = "127.0.0.1:7878".parse()?;
+//!
+//! // in the tool
+//! let mortem = mortem::hard();
+//! remote::listen(&mortem, addr, Auth::token("s3cret"))?;
+//!
+//! // at the operator
+//! remote::send(addr, &Auth::token("s3cret"), Command::Exit)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Commands are single lines of `<command> <token>`, answered with `ok`, `failed <error>` or
+//! `denied`.
+//!
+//! # Transport
+//!
+//! The token is sent in the clear, and there's no protection against replaying it. Listen on a
+//! loopback address or a unix socket, which rely on the host and filesystem permissions, and reach
+//! remote hosts through an encrypting tunnel, e.g. SSH port forwarding or a TLS terminating proxy
+//! such as stunnel. Listening on any other address is logged as a warning with `tracing`.
+//!
+//! At most [`MAX_CONNECTIONS`] connections are served at once, each given [`TIMEOUT`] to send its
+//! command and take the reply; further connections are closed right away.
+
+use crate::{Guard, Inner};
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::iter;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+/// Longest command line accepted, so clients can't make the listener buffer without bound.
+const MAX_LINE: u64 = 1024;
+
+/// Most connections served at once, so clients can't pin threads of the listener without bound.
+pub const MAX_CONNECTIONS: usize = 8;
+
+/// Time a connection is given to send its command, and to take the reply.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where a guard listens for commands.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Endpoint {
+    /// TCP socket address.
+    Tcp(SocketAddr),
+    /// Unix socket at a path.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl From<SocketAddr> for Endpoint {
+    fn from(addr: SocketAddr) -> Self {
+        Endpoint::Tcp(addr)
+    }
+}
+
+#[cfg(unix)]
+impl From<PathBuf> for Endpoint {
+    fn from(path: PathBuf) -> Self {
+        Endpoint::Unix(path)
+    }
+}
+
+#[cfg(unix)]
+impl From<&std::path::Path> for Endpoint {
+    fn from(path: &std::path::Path) -> Self {
+        Endpoint::Unix(path.to_owned())
+    }
+}
+
+/// Shared secret authenticating commands.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Auth {
+    token: Vec<u8>,
+}
+
+impl Auth {
+    /// Authenticate with `token`, which must not contain whitespace.
+    pub fn token(token: impl Into<Vec<u8>>) -> Self {
+        Auth {
+            token: token.into(),
+        }
+    }
+
+    /// Whether `token` matches, in constant time.
+    ///
+    /// Every byte of the longer token is compared, and a difference in length only fails the
+    /// comparison rather than ending it early.
+    fn matches(&self, token: &[u8]) -> bool {
+        let len = token.len().max(self.token.len());
+        let byte = |token: &[u8], i| token.get(i).copied().unwrap_or(0);
+        let difference = (0..len).fold(token.len() ^ self.token.len(), |acc, i| {
+            acc | usize::from(byte(token, i) ^ byte(&self.token, i))
+        });
+        // keep the comparison from being short-circuited
+        std::hint::black_box(difference) == 0
+    }
+}
+
+/// Formats without the token.
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth").finish_non_exhaustive()
+    }
+}
+
+/// Command sent to a listening guard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Command {
+    /// Delete the target now, leaving the process running.
+    Delete,
+    /// Delete the target now, then exit the process with status 0.
+    ///
+    /// Before exiting, deletion also runs for every other guard armed against termination or at
+    /// exit, the way it would on `SIGTERM`. Destructors of the process don't run, so guards armed
+    /// neither way don't run deletion.
+    Exit,
+}
+
+impl Command {
+    fn as_str(self) -> &'static str {
+        match self {
+            Command::Delete => "delete",
+            Command::Exit => "exit",
+        }
+    }
+
+    fn parse(command: &str) -> Option<Self> {
+        match command {
+            "delete" => Some(Command::Delete),
+            "exit" => Some(Command::Exit),
+            _ => None,
+        }
+    }
+}
+
+/// Bound endpoint of a listening guard.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Listener {
+    endpoint: Endpoint,
+}
+
+impl Listener {
+    /// Endpoint the guard listens on, e.g. with the port chosen for port 0.
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+}
+
+/// Have `guard` delete the target on commands to `endpoint` carrying the token of `auth`.
+///
+/// Connections are served on background threads for as long as the process runs; see the
+/// [module documentation](self) for their limits and the transports to use. Once the guard is
+/// dropped or deletion already ran, commands are still acknowledged, but do nothing. Commands with
+/// the wrong token are denied.
+pub fn listen(guard: &Guard, endpoint: impl Into<Endpoint>, auth: Auth) -> io::Result<Listener> {
+    let inner = Arc::downgrade(&guard.inner);
+    let auth = Arc::new(auth);
+    let endpoint = match endpoint.into() {
+        Endpoint::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            let addr = listener.local_addr()?;
+            #[cfg(feature = "tracing")]
+            if !addr.ip().is_loopback() {
+                warn!(%addr, "remote commands carry their token in the clear; listen on a loopback address instead");
+            }
+            accept(
+                iter::from_fn(move || Some(listener.accept().map(|(stream, _)| stream))),
+                inner,
+                auth,
+            );
+            Endpoint::Tcp(addr)
+        }
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            let listener = UnixListener::bind(&path)?;
+            accept(
+                iter::from_fn(move || Some(listener.accept().map(|(stream, _)| stream))),
+                inner,
+                auth,
+            );
+            Endpoint::Unix(path)
+        }
+    };
+    Ok(Listener { endpoint })
+}
+
+/// Connection to a listening guard.
+trait Stream: Read + Write + Send + 'static {
+    fn set_timeouts(&self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn set_timeouts(&self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_timeouts(&self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+/// Slot of a connection being served, given back once dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    /// Take a slot, unless all [`MAX_CONNECTIONS`] are taken.
+    fn take(served: &Arc<AtomicUsize>) -> Option<Self> {
+        served
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |served| {
+                (served < MAX_CONNECTIONS).then_some(served + 1)
+            })
+            .ok()
+            .map(|_| Slot(Arc::clone(served)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Serve connections from `incoming` on a background thread each, as long as slots are left.
+fn accept<S: Stream>(
+    incoming: impl Iterator<Item = io::Result<S>> + Send + 'static,
+    inner: Weak<Inner>,
+    auth: Arc<Auth>,
+) {
+    let served = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in incoming {
+            let Some(slot) = Slot::take(&served) else {
+                #[cfg(feature = "tracing")]
+                warn!("too many remote connections; closing");
+                continue;
+            };
+            let (inner, auth) = (inner.clone(), Arc::clone(&auth));
+            thread::spawn(move || {
+                serve(stream, &inner, &auth);
+                drop(slot);
+            });
+        }
+    });
+}
+
+/// Serve a single connection, carrying a single command.
+fn serve<S: Stream>(stream: io::Result<S>, inner: &Weak<Inner>, auth: &Auth) {
+    let result = stream.and_then(|mut stream| {
+        stream.set_timeouts(TIMEOUT)?;
+        let mut line = String::new();
+        BufReader::new((&mut stream).take(MAX_LINE)).read_line(&mut line)?;
+        let command = line
+            .trim_end()
+            .split_once(' ')
+            .filter(|(_, token)| auth.matches(token.as_bytes()))
+            .and_then(|(command, _)| Command::parse(command));
+        let Some(command) = command else {
+            #[cfg(feature = "tracing")]
+            warn!("denied remote command");
+            return stream.write_all(b"denied\n");
+        };
+
+        #[cfg(feature = "tracing")]
+        debug!(?command, "running remote command");
+        let result = inner.upgrade().and_then(|inner| inner.run());
+        match result {
+            Some(Err(err)) => writeln!(stream, "failed {}", err.kind())?,
+            _ => stream.write_all(b"ok\n")?,
+        }
+        stream.flush()?;
+        if command == Command::Exit {
+            exit();
+        }
+        Ok(())
+    });
+    if let Err(_err) = result {
+        #[cfg(feature = "tracing")]
+        warn!(err = %_err, "failed to serve remote command");
+    }
+}
+
+/// Exit the process with status 0, as [`Command::Exit`] does.
+///
+/// Guards armed against termination are run here, as the signal thread would; guards armed at
+/// exit run on the way out of [`std::process::exit`].
+fn exit() -> ! {
+    #[cfg(feature = "signals")]
+    crate::signals::run_all();
+    std::process::exit(0)
+}
+
+/// Send `command` to the guard listening on `endpoint`, authenticated with `auth`.
+///
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the command was denied, and
+/// [`io::ErrorKind::Other`] if deletion failed.
+pub fn send(endpoint: impl Into<Endpoint>, auth: &Auth, command: Command) -> io::Result<()> {
+    let mut line = format!("{} ", command.as_str()).into_bytes();
+    line.extend_from_slice(&auth.token);
+    line.push(b'\n');
+    match endpoint.into() {
+        Endpoint::Tcp(addr) => exchange(TcpStream::connect(addr)?, &line),
+        #[cfg(unix)]
+        Endpoint::Unix(path) => exchange(UnixStream::connect(path)?, &line),
+    }
+}
+
+/// Write `line` to `stream` and interpret the reply.
+fn exchange<S: Read + Write>(mut stream: S, line: &[u8]) -> io::Result<()> {
+    stream.write_all(line)?;
+    let mut reply = String::new();
+    BufReader::new(stream.take(MAX_LINE)).read_line(&mut reply)?;
+    match reply.trim_end() {
+        "ok" => Ok(()),
+        "denied" => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "remote command denied",
+        )),
+        reply => match reply.strip_prefix("failed ") {
+            Some(err) => Err(io::Error::other(format!("remote deletion failed: {err}"))),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed remote reply",
+            )),
+        },
+    }
+}
@@ -0,0 +1,329 @@
+//! Deletion reports, plans and lifecycle events.
+
+use crate::{DebuggerPolicy, Destructive, SetuidPolicy, Strategy};
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+/// Number of events kept for [`last_events`].
+const HISTORY: usize = 32;
+
+/// Most recent events of all guards, oldest first.
+static LAST_EVENTS: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+
+/// Operation a guard performs when dropped.
+///
+/// See [`Guard::plan`](crate::Guard::plan).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Remove an additional file.
+    ///
+    /// See [`Guard::also_remove`](crate::Guard::also_remove).
+    RemoveFile(PathBuf),
+    /// Remove an additional directory with all its contents.
+    ///
+    /// See [`Guard::also_remove_dir_all`](crate::Guard::also_remove_dir_all).
+    RemoveDirAll(PathBuf),
+    /// Delete the target, escalating through the strategies as attempts fail.
+    Delete {
+        /// Path of the target, as currently resolved.
+        path: PathBuf,
+        /// Strategies deletion may use, in order.
+        strategies: Vec<Strategy>,
+    },
+    /// Have the package manager owning the target remove it.
+    ///
+    /// See [`PackagePolicy::Delegate`](crate::PackagePolicy::Delegate).
+    Delegate {
+        /// Path of the target, as currently resolved.
+        path: PathBuf,
+        /// Package owning the target.
+        package: String,
+        /// Command run, with the program first.
+        command: Vec<String>,
+    },
+}
+
+/// Summary of a finished deletion.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub struct Report {
+    /// Path that was deleted, or attempted to be.
+    ///
+    /// `None` when the target couldn't be resolved, or had already been deleted.
+    pub path: Option<PathBuf>,
+    /// Number of deletion attempts made.
+    pub attempts: u32,
+    /// Strategy used in the last attempt.
+    pub strategy: Strategy,
+    /// How deletion ended.
+    pub outcome: Outcome,
+    /// Policy applied because the target had the setuid or setgid bit set, if it had.
+    pub setuid: Option<SetuidPolicy>,
+    /// Policy applied because the process ran under a debugger, if it did.
+    pub debugger: Option<DebuggerPolicy>,
+    /// Features that were configured but couldn't be provided.
+    pub degraded: Vec<Degradation>,
+}
+
+/// Configured feature a guard had to do without.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Degradation {
+    /// No handle could be held on the target, e.g. because the process ran out of file
+    /// descriptors, so renames weren't followed and deletion went by path.
+    ///
+    /// See [`GuardBuilder::follow_renames`](crate::GuardBuilder::follow_renames).
+    NoHandle(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+    /// No handle could be held on the directory of the target, so deletion went by path.
+    ///
+    /// See [`GuardBuilder::hold_directory`](crate::GuardBuilder::hold_directory).
+    NoDirectoryHandle(
+        #[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind,
+    ),
+    /// The contents of the target couldn't be overwritten, e.g. because it's a running executable
+    /// on Linux, so it was deleted without.
+    ///
+    /// See [`GuardBuilder::wipe`](crate::GuardBuilder::wipe). Wiping [cancelled
+    /// midway](crate::GuardBuilder::wipe_while) is reported as [`io::ErrorKind::Interrupted`].
+    NotWiped(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+    /// Other processes running the target couldn't all be stopped, e.g. because they survived
+    /// [`io::ErrorKind::TimedOut`], so it was deleted regardless.
+    ///
+    /// See [`GuardBuilder::terminate`](crate::GuardBuilder::terminate).
+    NotTerminated(
+        #[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind,
+    ),
+    /// Deletion couldn't be confirmed through filesystem events, either because no watch could be
+    /// installed, or because the event wasn't observed in time ([`io::ErrorKind::TimedOut`]).
+    ///
+    /// Only with the `watch` feature.
+    Unconfirmed(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+    /// No placeholder could be left at the path of the target, e.g. because something took its
+    /// place already, or it couldn't be made immutable ([`io::ErrorKind::PermissionDenied`] without
+    /// the privilege to), in which case it was left mutable.
+    ///
+    /// See [`GuardBuilder::placeholder`](crate::GuardBuilder::placeholder).
+    NoPlaceholder(
+        #[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind,
+    ),
+    /// A wrapper script launching the target couldn't be removed; the target was regardless.
+    ///
+    /// See [`GuardBuilder::wrapper`](crate::GuardBuilder::wrapper).
+    WrapperNotRemoved(
+        #[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind,
+    ),
+    /// Sibling instances of the target couldn't be asked to finish, e.g. because the platform
+    /// isn't supported ([`io::ErrorKind::Unsupported`]).
+    ///
+    /// See [`GuardBuilder::broadcast`](crate::GuardBuilder::broadcast).
+    NotBroadcast(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+    /// A destructive operation was configured without being unlocked, so it was skipped.
+    ///
+    /// See [`GuardBuilder::i_understand_this_is_destructive`](crate::GuardBuilder::i_understand_this_is_destructive).
+    Locked(Destructive),
+    /// No handlers could be installed to delete the target when the process is asked to
+    /// terminate.
+    ///
+    /// Only with the `signals` feature; see
+    /// [`Guard::with_signal_handling`](crate::Guard::with_signal_handling).
+    NoSignalHandling(
+        #[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind,
+    ),
+    /// An additional path couldn't be removed; the others and the target were regardless.
+    ///
+    /// See [`Guard::also_remove`](crate::Guard::also_remove).
+    NotRemoved(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+    /// Lifecycle events were dropped, as their channel was full; holds how many, up to the report.
+    ///
+    /// See [`GuardBuilder::events`](crate::GuardBuilder::events).
+    EventsDropped(u64),
+    /// A [hook](crate::Hook) couldn't be started, or exited unsuccessfully
+    /// ([`io::ErrorKind::Other`]); deletion went on regardless.
+    HookFailed(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+    /// Runs of the target couldn't be kept track of, so its guard was built disarmed, or they
+    /// couldn't be forgotten once it was deleted.
+    ///
+    /// See [`GuardBuilder::max_runs`](crate::GuardBuilder::max_runs).
+    NoState(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+    /// The symlink leading into the Nix store couldn't be removed.
+    ///
+    /// See [`GuardBuilder::remove_nix_link`](crate::GuardBuilder::remove_nix_link).
+    NixLinkNotRemoved(
+        #[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind,
+    ),
+    /// The executable couldn't be re-executed from memory, so it's deleted when the guard drops
+    /// rather than right away.
+    ///
+    /// Only with the `reexec-memfd` feature. See
+    /// [`GuardBuilder::reexec_from_memory`](crate::GuardBuilder::reexec_from_memory).
+    NotReexecuted(
+        #[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind,
+    ),
+}
+
+/// How a deletion ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Outcome {
+    /// The target was deleted.
+    Deleted,
+    /// The target had already been deleted.
+    AlreadyDeleted,
+    /// The target was replaced by another file, so deletion was skipped.
+    ///
+    /// See [`GuardBuilder::skip_if_replaced`](crate::GuardBuilder::skip_if_replaced).
+    Replaced,
+    /// The target was marked for deletion, which completes once all handles on it are closed.
+    ///
+    /// Only on Windows. See [`platform::is_delete_pending`](crate::platform::is_delete_pending).
+    DeletePending,
+    /// Deletion was scheduled to happen after the guard finished.
+    ///
+    /// See [`Strategy::Schedule`] and [`Strategy::Helper`].
+    Scheduled,
+    /// Deletion was handed to the package manager owning the target, which succeeded.
+    ///
+    /// See [`PackagePolicy::Delegate`](crate::PackagePolicy::Delegate).
+    Delegated,
+    /// Deletion went through without deleting anything, as asked.
+    ///
+    /// See [`GuardBuilder::dry_run`](crate::GuardBuilder::dry_run).
+    DryRun,
+    /// Deletion was refused by policy.
+    Refused(Refusal),
+    /// Deletion failed.
+    Failed(#[cfg_attr(feature = "serde", serde(with = "crate::schema::kind"))] io::ErrorKind),
+}
+
+/// Reason deletion was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Refusal {
+    /// The sources of the host executable's path disagree.
+    ///
+    /// See [`SourcePolicy`](crate::SourcePolicy).
+    SourcesDisagree,
+    /// The target has the setuid or setgid bit set.
+    ///
+    /// See [`SetuidPolicy`].
+    Setuid,
+    /// The target looks like a multi-call binary.
+    ///
+    /// See [`MultiCallPolicy`](crate::MultiCallPolicy).
+    MultiCall,
+    /// The target is owned by a system package.
+    ///
+    /// See [`PackagePolicy`](crate::PackagePolicy).
+    Packaged,
+    /// The target isn't the same build as the host executable.
+    ///
+    /// See [`GuardBuilder::verify_provenance`](crate::GuardBuilder::verify_provenance).
+    Provenance,
+    /// The target lies in the read-only Nix store.
+    ///
+    /// See [`resolve::is_nix_store`](crate::resolve::is_nix_store) and
+    /// [`GuardBuilder::remove_nix_link`](crate::GuardBuilder::remove_nix_link).
+    NixStore,
+    /// The process runs under a debugger.
+    ///
+    /// See [`DebuggerPolicy::Skip`].
+    Debugged,
+}
+
+impl Outcome {
+    /// Whether the target is gone, or was deliberately left alone.
+    ///
+    /// Refusals count as deliberate.
+    pub fn is_success(&self) -> bool {
+        !matches!(self, Outcome::Failed(_))
+    }
+}
+
+/// Lifecycle event of a guard.
+///
+/// See [`GuardBuilder::events`](crate::GuardBuilder::events).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The guard was created, or [rearmed](crate::Guard::rearm).
+    Armed,
+    /// The guard was [disarmed](crate::Guard::disarm).
+    Disarmed,
+    /// A destructive operation was authorized, when the guard was created or the operation
+    /// requested.
+    ///
+    /// See [`GuardBuilder::i_understand_this_is_destructive`](crate::GuardBuilder::i_understand_this_is_destructive).
+    Authorized(Destructive),
+    /// Deletion started.
+    DeletionStarted,
+    /// Deletion failed and is being retried, for the `n`th time.
+    Retry(u32),
+    /// Deletion escalated to another strategy.
+    Fallback(Strategy),
+    /// Deletion finished.
+    Done(Report),
+}
+
+/// Record `event` in the history returned by [`last_events`].
+pub(crate) fn record(event: Event) {
+    // a panic elsewhere doesn't make the history any less useful
+    let mut last = LAST_EVENTS.lock().unwrap_or_else(PoisonError::into_inner);
+    if last.len() == HISTORY {
+        last.pop_front();
+    }
+    last.push_back(event);
+}
+
+/// Allocate the full history up front, so recording doesn't allocate.
+pub(crate) fn reserve() {
+    let mut last = LAST_EVENTS.lock().unwrap_or_else(PoisonError::into_inner);
+    let len = last.len();
+    last.reserve(HISTORY - len);
+}
+
+/// Most recent lifecycle events of all guards in the process, oldest first.
+///
+/// Always available, with or without the `tracing` feature or an
+/// [event channel](crate::GuardBuilder::events), so what happened can be included in bug reports.
+/// Only the last few events are kept.
+///
+/// ### Usage
+/// ```rust
+/// drop(mortem::Guard::with_mode(mortem::Mode::Soft));
+/// for event in mortem::last_events() {
+///     eprintln!("{event:?}");
+/// }
+/// ```
+pub fn last_events() -> Vec<Event> {
+    let last = LAST_EVENTS.lock().unwrap_or_else(PoisonError::into_inner);
+    last.iter().cloned().collect()
+}
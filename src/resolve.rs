@@ -0,0 +1,246 @@
+//! Path resolution and safety checks applied to targets.
+//!
+//! Guards apply these when built; they're public for tools making the same decisions.
+
+use crate::handle::FileId;
+use crate::Canonicalization;
+
+use std::env::current_dir;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+/// Freeze a relative `path` against `base`, or the current working directory.
+///
+/// If the working directory can't be determined the path is kept as is.
+pub fn resolve_relative(path: PathBuf, base: Option<PathBuf>) -> PathBuf {
+    if path.is_absolute() {
+        return path;
+    }
+
+    match base.map_or_else(current_dir, Ok) {
+        Ok(base) => base.join(path),
+        Err(_) => {
+            #[cfg(feature = "tracing")]
+            warn!("failed to resolve relative target; keeping it relative");
+            path
+        }
+    }
+}
+
+/// Canonicalize `path`, returning the canonicalization that was actually applied.
+///
+/// See [`Canonicalization`].
+pub fn canonicalize(
+    path: PathBuf,
+    canonicalization: Canonicalization,
+) -> (PathBuf, Canonicalization) {
+    match canonicalization {
+        Canonicalization::None => return (path, Canonicalization::None),
+        Canonicalization::Full => match path.canonicalize() {
+            Ok(path) => return (path, Canonicalization::Full),
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                warn!(err = %_err, "failed to canonicalize target; normalizing instead");
+            }
+        },
+        Canonicalization::Lexical => {}
+    }
+    (normalize(&path), Canonicalization::Lexical)
+}
+
+/// Remove `.` and `..` components from `path`, without consulting the filesystem.
+///
+/// Leading `..` components of relative paths are kept, and `..` of the root is the root itself.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // `..` of the root is the root itself
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Whether `path` has the setuid or setgid bit set.
+///
+/// See [`SetuidPolicy`](crate::SetuidPolicy).
+#[cfg(unix)]
+pub fn is_setuid(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .map(|metadata| metadata.permissions().mode() & 0o6000 != 0)
+        .unwrap_or(false)
+}
+
+/// Whether `path` has the setuid or setgid bit set.
+///
+/// See [`SetuidPolicy`](crate::SetuidPolicy).
+#[cfg(not(unix))]
+pub fn is_setuid(_path: &Path) -> bool {
+    false
+}
+
+/// Whether the file at `path` is a script, starting with a `#!` line naming its interpreter.
+pub fn is_script(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut magic = [0; 2];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| magic == *b"#!")
+}
+
+/// Whether `path` looks like a multi-call binary, installed under several names.
+///
+/// That is, it has several hard links. Always `false` on other platforms than Unix.
+///
+/// See [`MultiCallPolicy`](crate::MultiCallPolicy).
+#[cfg(unix)]
+pub fn is_multi_call(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.nlink() > 1)
+}
+
+/// Whether `path` looks like a multi-call binary, installed under several names.
+///
+/// See [`MultiCallPolicy`](crate::MultiCallPolicy).
+#[cfg(not(unix))]
+pub fn is_multi_call(_path: &Path) -> bool {
+    false
+}
+
+/// Whether `invoked`, the name the process was run as (`argv[0]`), differs from the file name of
+/// `path`.
+///
+/// Multi-call binaries are run under other names than their file name, but so are executables
+/// run through differently named symlinks, renamed copies and `exec -a`.
+///
+/// See [`MultiCallPolicy::RefuseRenamed`](crate::MultiCallPolicy::RefuseRenamed).
+pub fn is_renamed(path: &Path, invoked: Option<&OsStr>) -> bool {
+    let Some(invoked) = invoked.and_then(|invoked| Path::new(invoked).file_name()) else {
+        return false;
+    };
+    let invoked = invoked.to_string_lossy();
+    // login shells are run as `-sh`
+    let invoked = invoked.strip_prefix('-').unwrap_or(&invoked);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    match cfg!(windows) {
+        // names are case-insensitive, and the extension may be left out
+        true => {
+            let stem = |name: &str| {
+                let stem = Path::new(name).file_stem().unwrap_or_default();
+                stem.to_string_lossy().to_lowercase()
+            };
+            stem(invoked) != stem(&name)
+        }
+        false => invoked != name,
+    }
+}
+
+/// Name of the system package owning `path`, as looked up with `dpkg-query -S` or `rpm -qf`.
+///
+/// `None` if neither package manager claims it or is available, and on other platforms than
+/// Linux. See [`PackagePolicy`](crate::PackagePolicy).
+#[cfg(target_os = "linux")]
+pub fn package_owner(path: &Path) -> Option<String> {
+    use std::process::{Command, Stdio};
+
+    let query = |program: &str, args: &[&str]| {
+        let output = Command::new(program)
+            .args(args)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    // `package[:arch][, other]: path`, preceded by lines about diversions
+    let dpkg = query("dpkg-query", &["-S", "--"]).and_then(|output| {
+        let line = output
+            .lines()
+            .find(|line| !line.starts_with("diversion "))?;
+        let (packages, _) = line.split_once(": ")?;
+        let package = packages.split(", ").next()?;
+        Some(package.split(':').next()?.to_owned())
+    });
+    dpkg.or_else(|| {
+        let output = query("rpm", &["-qf", "--queryformat", "%{NAME}\\n", "--"])?;
+        Some(output.lines().next()?.to_owned())
+    })
+    .filter(|package| !package.is_empty())
+}
+
+/// Name of the system package owning `path`, as looked up with `dpkg-query -S` or `rpm -qf`.
+///
+/// `None` if neither package manager claims it or is available, and on other platforms than
+/// Linux. See [`PackagePolicy`](crate::PackagePolicy).
+#[cfg(not(target_os = "linux"))]
+pub fn package_owner(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Whether `path` lies in the Nix store, `$NIX_STORE_DIR` or else `/nix/store`.
+///
+/// The store is read-only and content-addressed; its paths aren't deleted, but garbage collected
+/// once nothing refers to them. See [`Refusal::NixStore`](crate::Refusal::NixStore).
+pub fn is_nix_store(path: &Path) -> bool {
+    let store = std::env::var_os("NIX_STORE_DIR")
+        .map_or_else(|| PathBuf::from("/nix/store"), PathBuf::from);
+    let store = store.canonicalize().unwrap_or(store);
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    path != store && path.starts_with(&store)
+}
+
+/// Symlink outside the Nix store leading into it, that the process was run through as `invoked`
+/// (`argv[0]`).
+///
+/// Bare names are looked up in `PATH`. Links inside the store, such as those of profiles managed
+/// by Nix, aren't returned; those are removed with e.g. `nix profile remove`.
+///
+/// See [`GuardBuilder::remove_nix_link`](crate::GuardBuilder::remove_nix_link).
+pub fn nix_link(invoked: &OsStr) -> Option<PathBuf> {
+    let invoked = Path::new(invoked);
+    let link = if invoked.components().count() > 1 {
+        resolve_relative(invoked.to_owned(), None)
+    } else {
+        std::env::split_paths(&std::env::var_os("PATH")?)
+            .map(|dir| dir.join(invoked))
+            .find(|path| path.symlink_metadata().is_ok())?
+    };
+    let is_link = link
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.file_type().is_symlink());
+    (is_link && !is_nix_store(link.parent()?) && is_nix_store(&link)).then_some(link)
+}
+
+/// Whether `a` and `b` refer to the same file, by identity where supported.
+///
+/// See [`SourcePolicy`](crate::SourcePolicy).
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (FileId::of(a), FileId::of(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => match (a.canonicalize(), b.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => a == b,
+        },
+    }
+}
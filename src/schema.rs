@@ -0,0 +1,155 @@
+//! Versioned, machine-readable form of plans and reports.
+//!
+//! Orchestration wrapping mortem, such as configuration management or fleet dashboards, can
+//! exchange [plans](crate::Guard::plan) and [reports](crate::Report) as [`Document`]s, e.g. as
+//! JSON:
+//! ```json
+//! {"version":1,"data":{"path":"/usr/local/bin/tool","attempts":1,"strategy":"unlink","outcome":"deleted","setuid":null,"debugger":null,"degraded":[]}}
+//! ```
+//!
+//! Within a version, the schema only grows: fields and enum variants may be added, but are never
+//! removed, renamed or changed in meaning, so parsers that ignore what they don't know keep
+//! working across upgrades of the crate. Anything else bumps [`VERSION`]. Error kinds are written
+//! as the snake case names of [`std::io::ErrorKind`] variants, e.g. `permission_denied`.
+//!
+//! Only with the `serde` feature.
+
+use std::fmt;
+
+/// Current version of the schema.
+pub const VERSION: u32 = 1;
+
+/// Plan, report or other value, tagged with the version of the schema it was written in.
+///
+/// ### Usage
+/// ```rust
+/// use mortem::schema::Document;
+///
+/// let guard = mortem::hard();
+/// let plan = Document::new(guard.plan());
+/// # guard.disarm();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Document<T> {
+    /// Version of the schema the document was written in.
+    pub version: u32,
+    /// The plan, report or other value.
+    pub data: T,
+}
+
+impl<T> Document<T> {
+    /// Tag `data` with the current version.
+    pub fn new(data: T) -> Self {
+        Document {
+            version: VERSION,
+            data,
+        }
+    }
+
+    /// The value, unless the document was written in a version that isn't supported.
+    pub fn into_data(self) -> Result<T, UnsupportedVersion> {
+        match self.version {
+            1..=VERSION => Ok(self.data),
+            version => Err(UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Document written in a version of the schema that isn't supported, e.g. by a newer release.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsupportedVersion(pub u32);
+
+/// Formats as e.g. `unsupported schema version 2, expected at most 1`.
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unsupported schema version {}, expected at most {VERSION}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
+/// (De)serialization of [`ErrorKind`](std::io::ErrorKind) by the snake case name of its variant.
+///
+/// Kinds unknown when reading, e.g. those added to later releases of Rust, read as
+/// [`ErrorKind::Other`](std::io::ErrorKind::Other).
+pub(crate) mod kind {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::io::ErrorKind;
+
+    /// Kinds that are read back by name.
+    const KINDS: &[ErrorKind] = &[
+        ErrorKind::NotFound,
+        ErrorKind::PermissionDenied,
+        ErrorKind::ConnectionRefused,
+        ErrorKind::ConnectionReset,
+        ErrorKind::HostUnreachable,
+        ErrorKind::NetworkUnreachable,
+        ErrorKind::ConnectionAborted,
+        ErrorKind::NotConnected,
+        ErrorKind::AddrInUse,
+        ErrorKind::AddrNotAvailable,
+        ErrorKind::NetworkDown,
+        ErrorKind::BrokenPipe,
+        ErrorKind::AlreadyExists,
+        ErrorKind::WouldBlock,
+        ErrorKind::NotADirectory,
+        ErrorKind::IsADirectory,
+        ErrorKind::DirectoryNotEmpty,
+        ErrorKind::ReadOnlyFilesystem,
+        ErrorKind::StaleNetworkFileHandle,
+        ErrorKind::InvalidInput,
+        ErrorKind::InvalidData,
+        ErrorKind::TimedOut,
+        ErrorKind::WriteZero,
+        ErrorKind::StorageFull,
+        ErrorKind::NotSeekable,
+        ErrorKind::QuotaExceeded,
+        ErrorKind::FileTooLarge,
+        ErrorKind::ResourceBusy,
+        ErrorKind::ExecutableFileBusy,
+        ErrorKind::Deadlock,
+        ErrorKind::CrossesDevices,
+        ErrorKind::TooManyLinks,
+        ErrorKind::InvalidFilename,
+        ErrorKind::ArgumentListTooLong,
+        ErrorKind::Interrupted,
+        ErrorKind::Unsupported,
+        ErrorKind::UnexpectedEof,
+        ErrorKind::OutOfMemory,
+        ErrorKind::Other,
+    ];
+
+    /// Snake case name of the variant of `kind`.
+    fn name(kind: ErrorKind) -> String {
+        let mut name = String::new();
+        for (i, c) in format!("{kind:?}").char_indices() {
+            if c.is_ascii_uppercase() && i > 0 {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        }
+        name
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        kind: &ErrorKind,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&name(*kind))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ErrorKind, D::Error> {
+        let read = String::deserialize(deserializer)?;
+        Ok(KINDS
+            .iter()
+            .copied()
+            .find(|&kind| name(kind) == read)
+            .unwrap_or(ErrorKind::Other))
+    }
+}
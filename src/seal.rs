@@ -0,0 +1,167 @@
+//! Sealing state markers with a caller-provided key.
+//!
+//! Markers such as the status files of the `mortem` command line interface and the status records
+//! of janitors reveal tools and paths. Sealed, they're encrypted and authenticated: readers without the key learn nothing but their length, and [opening](open)
+//! one that was tampered with, or sealed with another key, fails.
+//!
+//! ```rust
+//! let key = mortem::seal::Key::new(b"shared secret");
+//! let sealed = mortem::seal::seal(&key, b"status=succeeded exit=0").unwrap();
+//! assert_eq!(mortem::seal::open(&key, &sealed).unwrap(), b"status=succeeded exit=0");
+//! assert!(mortem::seal::open(&mortem::seal::Key::new(b"other"), &sealed).is_err());
+//! ```
+//!
+//! Records are sealed with XChaCha20-Poly1305 under a key derived from the caller's secret with
+//! HKDF-SHA256, each with a random nonce of its own drawn from the operating system.
+//!
+//! Requires the `seal` feature.
+
+use std::fmt;
+use std::io;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Prefix of sealed records, naming the format version.
+const PREFIX: &str = "mortem-sealed-v2:";
+
+/// Length of nonces, in bytes.
+const NONCE: usize = 24;
+
+/// Key records are sealed with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// Derive a key from `secret`, which may be of any length.
+    ///
+    /// The secret isn't stretched, so it should be random, such as the contents of a key file,
+    /// rather than a password.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        let mut key = [0; 32];
+        Hkdf::<Sha256>::new(Some(PREFIX.as_bytes()), secret.as_ref())
+            .expand(b"mortem seal key", &mut key)
+            .expect("32 bytes is a valid length for HKDF-SHA256");
+        Key(key)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// Seal `record` with `key`, encrypting and authenticating it.
+///
+/// The result is printable, and differs each time, as every record gets a nonce of its own. Fails
+/// if the operating system can't provide randomness for the nonce.
+pub fn seal(key: &Key, record: &[u8]) -> io::Result<String> {
+    let mut nonce = [0; NONCE];
+    getrandom::getrandom(&mut nonce).map_err(io::Error::from)?;
+    let payload = Payload {
+        msg: record,
+        aad: PREFIX.as_bytes(),
+    };
+    let ciphertext = key
+        .cipher()
+        .encrypt(XNonce::from_slice(&nonce), payload)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too long to seal"))?;
+    Ok(format!("{PREFIX}{}:{}", hex(&nonce), hex(&ciphertext)))
+}
+
+/// Open `sealed`, a record [sealed](seal) with `key`, returning its contents.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if it isn't a sealed record, was tampered with, or
+/// was sealed with another key. Surrounding whitespace is ignored.
+pub fn open(key: &Key, sealed: &str) -> io::Result<Vec<u8>> {
+    let invalid = |reason| io::Error::new(io::ErrorKind::InvalidData, reason);
+    let fields = sealed
+        .trim()
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| invalid("not a sealed record"))?;
+    let mut fields = fields.split(':').map(unhex);
+    let (Some(Some(nonce)), Some(Some(ciphertext)), None) =
+        (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(invalid("malformed sealed record"));
+    };
+    if nonce.len() != NONCE {
+        return Err(invalid("malformed sealed record"));
+    }
+    let payload = Payload {
+        msg: &ciphertext,
+        aad: PREFIX.as_bytes(),
+    };
+    key.cipher()
+        .decrypt(XNonce::from_slice(&nonce), payload)
+        .map_err(|_| invalid("sealed record was tampered with or sealed with another key"))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_depend_on_the_whole_secret() {
+        assert_eq!(Key::new(b"secret"), Key::new(b"secret"));
+        assert_ne!(Key::new(b"secret"), Key::new(b"secret "));
+    }
+
+    #[test]
+    fn sealed_records_round_trip_and_hide_their_contents() {
+        let key = Key::new(b"secret");
+        let record = b"status=failed exit=1 path=/opt/tool/bin/tool".repeat(3);
+        let sealed = seal(&key, &record).unwrap();
+        assert!(!sealed.contains("tool"));
+        assert_ne!(sealed, seal(&key, &record).unwrap());
+        assert_eq!(open(&key, &sealed).unwrap(), record);
+    }
+
+    #[test]
+    fn nonces_never_repeat() {
+        let key = Key::new(b"secret");
+        let nonces: std::collections::HashSet<_> = (0..1000)
+            .map(|_| {
+                let sealed = seal(&key, b"status=running").unwrap();
+                sealed.split(':').nth(1).unwrap().to_owned()
+            })
+            .collect();
+        assert_eq!(nonces.len(), 1000);
+    }
+
+    #[test]
+    fn tampered_records_fail_to_open() {
+        let key = Key::new(b"secret");
+        let sealed = seal(&key, b"status=running").unwrap();
+        let mut tampered = sealed.into_bytes();
+        let last = tampered.len() - 10;
+        tampered[last] = if tampered[last] == b'0' { b'1' } else { b'0' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        let err = open(&key, &tampered).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        assert!(open(&key, "status=running").is_err());
+        assert!(open(&Key::new(b"other"), &seal(&key, b"status=running").unwrap()).is_err());
+    }
+}
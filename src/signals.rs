@@ -0,0 +1,140 @@
+//! Running deletion when the process is asked to terminate.
+//!
+//! On unix, the handlers only write the signal to a pipe, as nothing else is safe in a signal
+//! handler; a background thread runs deletion, then terminates the process by the same signal. On
+//! Windows, console control handlers run on a thread of their own already.
+
+use crate::{Inner, SHUTDOWN_BUDGET};
+
+use std::io;
+use std::sync::{Arc, Mutex, Once, PoisonError, Weak};
+
+/// Guards to run deletion of on termination.
+static GUARDS: Mutex<Vec<Weak<Inner>>> = Mutex::new(Vec::new());
+
+/// Outcome of installing the handlers, shared by all guards.
+static INSTALLED: Mutex<Option<io::ErrorKind>> = Mutex::new(None);
+
+/// Run deletion of `inner` when the process is asked to terminate.
+pub(crate) fn register(inner: &Arc<Inner>) -> io::Result<()> {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        if let Err(err) = install() {
+            *INSTALLED.lock().unwrap_or_else(PoisonError::into_inner) = Some(err.kind());
+        }
+    });
+    if let Some(kind) = *INSTALLED.lock().unwrap_or_else(PoisonError::into_inner) {
+        return Err(kind.into());
+    }
+    let mut guards = GUARDS.lock().unwrap_or_else(PoisonError::into_inner);
+    guards.retain(|guard| guard.strong_count() > 0);
+    guards.push(Arc::downgrade(inner));
+    Ok(())
+}
+
+/// Run deletion of all registered guards still alive, each within the [`SHUTDOWN_BUDGET`].
+pub(crate) fn run_all() {
+    let guards = GUARDS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    for inner in guards.iter().filter_map(Weak::upgrade) {
+        let _ = inner.run_within(SHUTDOWN_BUDGET);
+    }
+}
+
+/// Write end of the pipe the signal handler writes to.
+#[cfg(unix)]
+static PIPE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    use std::sync::atomic::Ordering;
+
+    let byte = signal as u8;
+    // SAFETY: `write` is async-signal-safe, and the byte outlives the call
+    unsafe { libc::write(PIPE.load(Ordering::SeqCst), (&byte as *const u8).cast(), 1) };
+}
+
+#[cfg(unix)]
+fn install() -> io::Result<()> {
+    use std::sync::atomic::Ordering;
+
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for both ends of the pipe
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    for fd in fds {
+        // SAFETY: `fd` was just opened
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    let [read, write] = fds;
+    PIPE.store(write, Ordering::SeqCst);
+
+    std::thread::Builder::new()
+        .name("mortem-signals".into())
+        .spawn(move || {
+            let mut byte = 0u8;
+            // SAFETY: `byte` has room for the single byte read
+            while unsafe { libc::read(read, (&mut byte as *mut u8).cast(), 1) } != 1 {}
+            #[cfg(feature = "tracing")]
+            tracing::debug!(signal = byte, "asked to terminate; running deletion");
+            run_all();
+
+            let signal = libc::c_int::from(byte);
+            // SAFETY: restoring the default disposition and raising the signal terminates the
+            // process the way it would have without the handler
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+            std::process::exit(128 + signal);
+        })?;
+
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        // SAFETY: the action is fully initialized, and the handler only calls `write`
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            action.sa_flags = libc::SA_RESTART;
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(signal, &action, &mut previous) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // signals ignored on purpose, e.g. SIGHUP under nohup, stay ignored
+            if previous.sa_sigaction == libc::SIG_IGN {
+                libc::sigaction(signal, &previous, std::ptr::null_mut());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn install() -> io::Result<()> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    extern "system" fn on_control(_event: u32) -> i32 {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(event = _event, "asked to terminate; running deletion");
+        run_all();
+        // not handled, so the default handler terminates the process
+        0
+    }
+
+    // SAFETY: the handler is a plain function living as long as the process
+    if unsafe { SetConsoleCtrlHandler(on_control, 1) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn install() -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
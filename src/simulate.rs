@@ -0,0 +1,348 @@
+//! Simulating deletion against a snapshot of a directory tree.
+//!
+//! Uninstall plans spanning many files are hard to verify without running them. A [`Snapshot`]
+//! records the layout of a tree, without the contents of its files, and
+//! [simulating](Snapshot::simulate) a guard configuration materializes it in a scratch copy, in
+//! memory where the platform allows, then runs the full strategy engine there. The real tree is
+//! never touched.
+//!
+//! ```rust
+//! use mortem::simulate::Snapshot;
+//! use mortem::{GuardConfig, Outcome};
+//! # let root = std::env::temp_dir().join(format!("mortem-doc-{}-simulate", std::process::id()));
+//! # std::fs::create_dir_all(root.join("bin")).unwrap();
+//! # std::fs::write(root.join("bin/tool"), "").unwrap();
+//! # std::fs::create_dir_all(root.join("share")).unwrap();
+//!
+//! let snapshot = Snapshot::capture(&root)?;
+//! let mut config = GuardConfig::default();
+//! config.target = Some(root.join("bin/tool"));
+//! config.also_remove_dir_all = vec![root.join("share")];
+//! config.destructive = true;
+//!
+//! let simulation = snapshot.simulate(config)?;
+//! assert_eq!(simulation.report.unwrap().unwrap().outcome, Outcome::Deleted);
+//! assert_eq!(simulation.removed, [root.join("bin/tool"), root.join("share")]);
+//! assert_eq!(simulation.remaining, [root.join("bin")]);
+//! assert!(root.join("bin/tool").exists());
+//! # std::fs::remove_dir_all(&root).unwrap();
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Requires the `test-util` feature.
+
+use crate::{GuardBuilder, GuardConfig, PackagePolicy, Report, Strategy, TempLocation};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Layout of a directory tree, without the contents of its files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    root: PathBuf,
+    /// Entries below the root, relative to it, parents before their children.
+    entries: Vec<(PathBuf, Entry)>,
+}
+
+/// Entry of a snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Entry {
+    /// Regular file, of a length and with permissions.
+    File { len: u64, mode: u32 },
+    /// Directory, with permissions.
+    Dir { mode: u32 },
+    /// Symbolic link, to a path.
+    Symlink(PathBuf),
+}
+
+/// Outcome of [simulating](Snapshot::simulate) deletion.
+///
+/// Paths are those of the snapshotted tree rather than of the scratch copy, in the order of the
+/// snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Simulation {
+    /// Report of deletion, as [`Guard::execute`](crate::Guard::execute) returns it, or its error.
+    pub report: Result<Option<Report>, crate::Error>,
+    /// Entries of the snapshot deleted.
+    pub removed: Vec<PathBuf>,
+    /// Entries of the snapshot left in place.
+    pub remaining: Vec<PathBuf>,
+    /// Entries that weren't in the snapshot, e.g. [placeholders](GuardBuilder::placeholder).
+    pub created: Vec<PathBuf>,
+}
+
+impl Snapshot {
+    /// Record the tree below `root`, without following symlinks.
+    pub fn capture(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = std::path::absolute(root.as_ref())?;
+        let mut entries = Vec::new();
+        walk(&root, Path::new(""), &mut |path, metadata| {
+            let entry = if metadata.is_dir() {
+                Entry::Dir {
+                    mode: mode(metadata),
+                }
+            } else if metadata.is_symlink() {
+                Entry::Symlink(fs::read_link(root.join(path))?)
+            } else {
+                Entry::File {
+                    len: metadata.len(),
+                    mode: mode(metadata),
+                }
+            };
+            entries.push((path.to_owned(), entry));
+            Ok(())
+        })?;
+        Ok(Snapshot { root, entries })
+    }
+
+    /// Root of the snapshotted tree.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Paths of the entries below the root.
+    pub fn paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.entries.iter().map(|(path, _)| self.root.join(path))
+    }
+
+    /// Run deletion as configured by `config` against a scratch copy of the tree.
+    ///
+    /// Paths of the configuration below the root of the snapshot are moved into the copy. The
+    /// configuration is run as it would be on the run deleting the target, with
+    /// [run limits](GuardBuilder::max_runs) left out, and whatever would reach outside the copy
+    /// left out as well: [hooks](crate::Hook), [termination](GuardBuilder::terminate),
+    /// [broadcasts](GuardBuilder::broadcast), [inheritance](GuardBuilder::inherit) and package
+    /// managers. Temporary files are kept in the directory of the target.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the configuration targets the host executable
+    /// or paths outside the root, or deletes through [`Strategy::Schedule`] or
+    /// [`Strategy::Helper`], which outlive the process.
+    pub fn simulate(&self, config: GuardConfig) -> io::Result<Simulation> {
+        let scratch = Scratch::new()?;
+        let copy = scratch.0.join("root");
+        self.materialize(&copy)?;
+        let config = self.confine(config, &copy)?;
+        let report = GuardBuilder::from(config).try_build()?.execute();
+
+        let mut simulation = Simulation {
+            report: match report {
+                Ok(report) => Ok(report.map(|report| self.unmapped(report, &copy))),
+                Err(err) => (self.unmapped(err.into_report(), &copy).into_result()).map(Some),
+            },
+            removed: Vec::new(),
+            remaining: Vec::new(),
+            created: Vec::new(),
+        };
+        for (path, _) in &self.entries {
+            match copy.join(path).symlink_metadata() {
+                Ok(_) => simulation.remaining.push(self.root.join(path)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    simulation.removed.push(self.root.join(path))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        walk(&copy, Path::new(""), &mut |path, _| {
+            if !self.entries.iter().any(|(entry, _)| entry == path) {
+                simulation.created.push(self.root.join(path));
+            }
+            Ok(())
+        })?;
+        Ok(simulation)
+    }
+
+    /// Recreate the tree at `copy`, with files as long as the originals but holding no data.
+    fn materialize(&self, copy: &Path) -> io::Result<()> {
+        fs::create_dir(copy)?;
+        let mut modes = Vec::new();
+        for (path, entry) in &self.entries {
+            let at = copy.join(path);
+            match entry {
+                Entry::Dir { mode } => {
+                    fs::create_dir(&at)?;
+                    // applied last, so read-only directories can still be filled
+                    modes.push((at, *mode));
+                }
+                Entry::File { len, mode } => {
+                    fs::File::create(&at)?.set_len(*len)?;
+                    modes.push((at, *mode));
+                }
+                Entry::Symlink(target) => {
+                    let target = match target.strip_prefix(&self.root) {
+                        Ok(inside) => copy.join(inside),
+                        Err(_) => target.clone(),
+                    };
+                    symlink(&target, &at)?;
+                }
+            }
+        }
+        for (path, mode) in modes.into_iter().rev() {
+            set_mode(&path, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Move the paths of `config` into `copy`, and leave out what would reach outside it.
+    fn confine(&self, mut config: GuardConfig, copy: &Path) -> io::Result<GuardConfig> {
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidInput, reason.to_owned());
+        let base_dir = match config.base_dir.take() {
+            Some(base_dir) => Some(std::path::absolute(base_dir)?),
+            None => None,
+        };
+        let moved = |path: PathBuf| -> io::Result<PathBuf> {
+            let path = match (path.is_relative(), &base_dir) {
+                (true, Some(base_dir)) => base_dir.join(path),
+                _ => std::path::absolute(path)?,
+            };
+            match path.strip_prefix(&self.root) {
+                Ok(inside) => Ok(copy.join(inside)),
+                Err(_) => Err(invalid("path lies outside the snapshot")),
+            }
+        };
+
+        let target = config
+            .target
+            .take()
+            .ok_or_else(|| invalid("the host executable can't be simulated"))?;
+        config.target = Some(moved(target)?);
+        for paths in [
+            &mut config.wrappers,
+            &mut config.also_remove,
+            &mut config.also_remove_dir_all,
+        ] {
+            *paths = paths.drain(..).map(moved).collect::<io::Result<_>>()?;
+        }
+        config.temp_location = match config.temp_location {
+            TempLocation::Custom(dir) => TempLocation::Custom(moved(dir)?),
+            _ => TempLocation::TargetDir,
+        };
+
+        let outliving = [Strategy::Schedule, Strategy::Helper];
+        if (config.strategies.iter().chain(&config.fallback)).any(|s| outliving.contains(s)) {
+            return Err(invalid("deferred strategies outlive the simulation"));
+        }
+        config.max_runs = None;
+        config.expires_after = None;
+        config.hooks.clear();
+        config.terminate = None;
+        config.broadcast = false;
+        config.inherit = false;
+        config.detached = false;
+        config.package_policy = PackagePolicy::Ignore;
+        Ok(config)
+    }
+
+    /// `report` with the path of the copy replaced by that of the snapshotted tree.
+    fn unmapped(&self, mut report: Report, copy: &Path) -> Report {
+        report.path = report.path.map(|path| match path.strip_prefix(copy) {
+            Ok(inside) => self.root.join(inside),
+            Err(_) => path,
+        });
+        report
+    }
+}
+
+/// Call `visit` on every entry below `root`, joined to `relative`, parents first.
+fn walk(
+    root: &Path,
+    relative: &Path,
+    visit: &mut dyn FnMut(&Path, &fs::Metadata) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(root.join(relative))?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        let metadata = entry.metadata()?;
+        visit(&path, &metadata)?;
+        if metadata.is_dir() {
+            walk(root, &path, visit)?;
+        }
+    }
+    Ok(())
+}
+
+/// Directory removed with its contents once dropped, in memory where the platform allows.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new() -> io::Result<Self> {
+        // tmpfs keeps the copy off the disk
+        let shm = Path::new("/dev/shm");
+        let parent = match cfg!(target_os = "linux") && shm.is_dir() {
+            true => shm.to_owned(),
+            false => std::env::temp_dir(),
+        };
+        for attempt in 0.. {
+            let dir = parent.join(format!(
+                ".mortem-simulation-{}-{attempt}",
+                std::process::id()
+            ));
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(Scratch(dir)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        // directories made read-only by the snapshot would keep their contents
+        let _ = walk(&self.0, Path::new(""), &mut |path, metadata| {
+            if metadata.is_dir() {
+                set_mode(&self.0.join(path), 0o700)?;
+            }
+            Ok(())
+        });
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    match metadata.permissions().readonly() {
+        true => 0o444,
+        false => 0o644,
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, at: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, at)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, at: &Path) -> io::Result<()> {
+    match target.is_dir() {
+        true => std::os::windows::fs::symlink_dir(target, at),
+        false => std::os::windows::fs::symlink_file(target, at),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_target: &Path, _at: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,80 @@
+//! Cross-checking the sources the host executable's path can be resolved from.
+
+use crate::handle::Handle;
+use crate::resolve::{is_script, same_file};
+
+use std::env;
+use std::path::{PathBuf, MAIN_SEPARATOR};
+
+/// Path of the host executable according to each available source.
+#[derive(Debug, Default)]
+pub(crate) struct Sources {
+    pub(crate) current_exe: Option<PathBuf>,
+    pub(crate) procfs: Option<PathBuf>,
+    pub(crate) argv0: Option<PathBuf>,
+    pub(crate) handle: Option<PathBuf>,
+    /// Script `argv[0]` names instead of the executable, such as a wrapper executing it with
+    /// `exec -a "$0"`.
+    pub(crate) wrapper: Option<PathBuf>,
+}
+
+impl Sources {
+    pub(crate) fn gather(handle: Option<&Handle>) -> Self {
+        // a script can't be the executable itself, so it doesn't disagree with the other sources
+        let (argv0, wrapper) = match argv0() {
+            Some(argv0) if is_script(&argv0) => (None, Some(argv0)),
+            argv0 => (argv0, None),
+        };
+        Sources {
+            current_exe: env::current_exe().ok(),
+            procfs: procfs(),
+            argv0,
+            handle: handle.and_then(|handle| handle.path().ok().flatten()),
+            wrapper,
+        }
+    }
+
+    /// Whether all available sources, other than `argv[0]` and a wrapper, point at the same file.
+    ///
+    /// `argv[0]` is whatever the launcher chose, resolved against the current directory or `PATH`
+    /// after the fact, so it may well name an unrelated file, e.g. one shadowing the executable
+    /// earlier in `PATH`. It's only advisory.
+    pub(crate) fn agree(&self) -> bool {
+        let mut paths = [&self.current_exe, &self.procfs, &self.handle]
+            .into_iter()
+            .flatten();
+        match paths.next() {
+            Some(first) => paths.all(|path| same_file(first, path)),
+            None => true,
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn procfs() -> Option<PathBuf> {
+    std::fs::read_link("/proc/self/exe").ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn procfs() -> Option<PathBuf> {
+    None
+}
+
+/// Resolve `argv[0]` the way a shell would have; `None` if it doesn't name an existing file.
+fn argv0() -> Option<PathBuf> {
+    let argv0 = PathBuf::from(env::args_os().next()?);
+    if argv0.as_os_str().is_empty() {
+        return None;
+    }
+
+    let has_separator = argv0.to_string_lossy().contains(MAIN_SEPARATOR)
+        || (cfg!(windows) && argv0.to_string_lossy().contains('/'));
+    if has_separator {
+        let path = env::current_dir().ok()?.join(argv0);
+        return path.is_file().then_some(path);
+    }
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&argv0))
+        .find(|path| path.is_file())
+}
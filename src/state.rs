@@ -0,0 +1,569 @@
+//! Bookkeeping kept across runs, for run limits and expiry.
+//!
+//! Guards built with [`GuardBuilder::max_runs`] or [`GuardBuilder::expires_after`] count the runs
+//! of the target and remember when it first ran. Where that state is kept is up to a [`Store`]:
+//! - [`StateDir`], a file per value in a directory, by default the user's state directory;
+//! - `Xattr`, extended attributes on a file, e.g. the target itself, so the state goes with it,
+//!   on Linux and Android;
+//! - `Registry`, values under a key of the current user in the Windows registry, on Windows;
+//! - anything else implementing [`Store`].
+//!
+//! ```rust,no_run
+//! use mortem::state::StateDir;
+//!
+//! // delete the tool on its third run
+//! let _mortem = mortem::Guard::builder()
+//!     .max_runs(3)
+//!     .state(StateDir::new("/var/lib/tool/state"))
+//!     .build();
+//! ```
+//!
+//! State of a target is cleared once it's deleted. Values are short strings of ASCII digits.
+//!
+//! [`GuardBuilder::max_runs`]: crate::GuardBuilder::max_runs
+//! [`GuardBuilder::expires_after`]: crate::GuardBuilder::expires_after
+
+use crate::strategy::sync_dir;
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where guards keep their bookkeeping across runs.
+///
+/// Keys are made of ASCII letters, digits, `-` and `.`, and values are short. A key that was never
+/// [saved](Store::save) or was [removed](Store::remove) loads as `None`.
+pub trait Store: Send + Sync {
+    /// Load the value of `key`.
+    fn load(&self, key: &str) -> io::Result<Option<String>>;
+
+    /// Save `value` under `key`, replacing any value it had.
+    fn save(&self, key: &str, value: &str) -> io::Result<()>;
+
+    /// Remove `key`, succeeding if it had no value.
+    fn remove(&self, key: &str) -> io::Result<()>;
+}
+
+/// Files in a directory, one per key.
+///
+/// Values are written to a sibling and renamed over, then synced along with the directory, so a
+/// crash leaves either the old or the new value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateDir {
+    dir: PathBuf,
+}
+
+impl StateDir {
+    /// Keep state in `dir`, creating it when first saving.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        StateDir { dir: dir.into() }
+    }
+
+    /// Keep state in the user's state directory, if there is one.
+    ///
+    /// That's `$XDG_STATE_HOME/mortem`, falling back to `~/.local/state/mortem`, on unix, and
+    /// `%LOCALAPPDATA%\mortem` on Windows.
+    pub fn user() -> Option<Self> {
+        #[cfg(unix)]
+        let dir = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| {
+                let home = std::env::var_os("HOME").filter(|home| !home.is_empty())?;
+                Some(Path::new(&home).join(".local").join("state"))
+            });
+        #[cfg(windows)]
+        let dir = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+        #[cfg(not(any(unix, windows)))]
+        let dir = None::<PathBuf>;
+        dir.map(|dir| StateDir::new(dir.join("mortem")))
+    }
+
+    /// Directory state is kept in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Store for StateDir {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.dir.join(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save(&self, key: &str, value: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let partial = self.dir.join(format!(".{key}.partial"));
+        let mut file = File::create(&partial)?;
+        file.write_all(value.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&partial, self.dir.join(key))?;
+        sync_dir(&self.dir)
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.dir.join(key)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => sync_dir(&self.dir),
+        }
+    }
+}
+
+/// Extended attributes of a file, named `user.mortem.<key>`.
+///
+/// Kept on the target itself, the state is deleted along with it, and copies of the target start
+/// over. Fails with [`io::ErrorKind::Unsupported`] on filesystems without user attributes, such as
+/// tmpfs on older kernels.
+///
+/// Only on Linux and Android.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Xattr {
+    path: PathBuf,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Xattr {
+    /// Keep state in the extended attributes of the file at `path`.
+    pub fn on(path: impl Into<PathBuf>) -> Self {
+        Xattr { path: path.into() }
+    }
+
+    /// Null-terminated path of the file, and name of the attribute holding `key`.
+    fn names(&self, key: &str) -> io::Result<(std::ffi::CString, std::ffi::CString)> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let invalid = |_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a nul byte");
+        let path = std::ffi::CString::new(self.path.as_os_str().as_bytes()).map_err(invalid)?;
+        let name = std::ffi::CString::new(format!("user.mortem.{key}")).map_err(invalid)?;
+        Ok((path, name))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Store for Xattr {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        let (path, name) = self.names(key)?;
+        let mut value = vec![0; 64];
+        loop {
+            // SAFETY: both names are nul-terminated, and the buffer is as long as passed
+            let len = unsafe {
+                libc::getxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value.as_mut_ptr().cast(),
+                    value.len(),
+                )
+            };
+            if let Ok(len) = usize::try_from(len) {
+                value.truncate(len);
+                let invalid = |_| io::Error::new(io::ErrorKind::InvalidData, "value isn't UTF-8");
+                return String::from_utf8(value).map(Some).map_err(invalid);
+            }
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::ENODATA) => return Ok(None),
+                Some(libc::ERANGE) if value.len() < 4096 => value.resize(value.len() * 4, 0),
+                _ => return Err(err),
+            }
+        }
+    }
+
+    fn save(&self, key: &str, value: &str) -> io::Result<()> {
+        let (path, name) = self.names(key)?;
+        // SAFETY: both names are nul-terminated, and the value is as long as passed
+        let result = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        match result {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        let (path, name) = self.names(key)?;
+        // SAFETY: both names are nul-terminated
+        if unsafe { libc::removexattr(path.as_ptr(), name.as_ptr()) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::ENODATA) => Ok(()),
+            _ => Err(err),
+        }
+    }
+}
+
+/// Values under a key of the current user in the Windows registry, `HKEY_CURRENT_USER\<subkey>`.
+///
+/// Only on Windows.
+#[cfg(windows)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registry {
+    subkey: String,
+}
+
+#[cfg(windows)]
+impl Registry {
+    /// Keep state in values under `HKEY_CURRENT_USER\<subkey>`, e.g. `Software\Vendor\Tool`,
+    /// creating the key when first used.
+    pub fn new(subkey: impl Into<String>) -> Self {
+        Registry {
+            subkey: subkey.into(),
+        }
+    }
+
+    /// Run `f` on the opened key, closing it after.
+    fn with_key<T>(&self, f: impl FnOnce(registry::Key) -> io::Result<T>) -> io::Result<T> {
+        let key = registry::Key::open(&self.subkey)?;
+        let result = f(key);
+        key.close();
+        result
+    }
+}
+
+#[cfg(windows)]
+impl Store for Registry {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        self.with_key(|handle| handle.get(key))
+    }
+
+    fn save(&self, key: &str, value: &str) -> io::Result<()> {
+        self.with_key(|handle| handle.set(key, value))
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        self.with_key(|handle| handle.delete(key))
+    }
+}
+
+#[cfg(windows)]
+mod registry {
+    use std::ffi::c_void;
+    use std::io;
+    use std::ptr;
+
+    const HKEY_CURRENT_USER: isize = 0x8000_0001_u32 as i32 as isize;
+    const KEY_READ: u32 = 0x2_0019;
+    const KEY_WRITE: u32 = 0x2_0006;
+    const REG_SZ: u32 = 1;
+    const ERROR_FILE_NOT_FOUND: i32 = 2;
+    const ERROR_MORE_DATA: i32 = 234;
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn RegCreateKeyExW(
+            key: *mut c_void,
+            subkey: *const u16,
+            reserved: u32,
+            class: *const u16,
+            options: u32,
+            access: u32,
+            security: *const c_void,
+            result: *mut *mut c_void,
+            disposition: *mut u32,
+        ) -> i32;
+        fn RegQueryValueExW(
+            key: *mut c_void,
+            name: *const u16,
+            reserved: *mut u32,
+            kind: *mut u32,
+            data: *mut u8,
+            len: *mut u32,
+        ) -> i32;
+        fn RegSetValueExW(
+            key: *mut c_void,
+            name: *const u16,
+            reserved: u32,
+            kind: u32,
+            data: *const u8,
+            len: u32,
+        ) -> i32;
+        fn RegDeleteValueW(key: *mut c_void, name: *const u16) -> i32;
+        fn RegCloseKey(key: *mut c_void) -> i32;
+    }
+
+    fn wide(name: &str) -> Vec<u16> {
+        name.encode_utf16().chain([0]).collect()
+    }
+
+    fn check(status: i32) -> io::Result<()> {
+        match status {
+            0 => Ok(()),
+            status => Err(io::Error::from_raw_os_error(status)),
+        }
+    }
+
+    /// Open registry key, closed explicitly.
+    #[derive(Clone, Copy)]
+    pub(super) struct Key(*mut c_void);
+
+    impl Key {
+        pub(super) fn open(subkey: &str) -> io::Result<Self> {
+            let subkey = wide(subkey);
+            let mut key = ptr::null_mut();
+            // SAFETY: the subkey is nul-terminated, and the result points to a live handle
+            check(unsafe {
+                RegCreateKeyExW(
+                    HKEY_CURRENT_USER as *mut c_void,
+                    subkey.as_ptr(),
+                    0,
+                    ptr::null(),
+                    0,
+                    KEY_READ | KEY_WRITE,
+                    ptr::null(),
+                    &mut key,
+                    ptr::null_mut(),
+                )
+            })?;
+            Ok(Key(key))
+        }
+
+        pub(super) fn get(self, name: &str) -> io::Result<Option<String>> {
+            let name = wide(name);
+            let mut value = vec![0_u16; 32];
+            loop {
+                let mut len = u32::try_from(value.len() * 2).unwrap_or(u32::MAX);
+                // SAFETY: the name is nul-terminated, and the buffer is as long as passed
+                let status = unsafe {
+                    RegQueryValueExW(
+                        self.0,
+                        name.as_ptr(),
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        value.as_mut_ptr().cast(),
+                        &mut len,
+                    )
+                };
+                match status {
+                    0 => break value.truncate(len as usize / 2),
+                    ERROR_FILE_NOT_FOUND => return Ok(None),
+                    ERROR_MORE_DATA if value.len() < 4096 => value.resize(value.len() * 4, 0),
+                    status => check(status)?,
+                }
+            }
+            // string values usually carry their terminator
+            while value.last() == Some(&0) {
+                value.pop();
+            }
+            let invalid = |_| io::Error::new(io::ErrorKind::InvalidData, "value isn't UTF-16");
+            String::from_utf16(&value).map(Some).map_err(invalid)
+        }
+
+        pub(super) fn set(self, name: &str, value: &str) -> io::Result<()> {
+            let (name, value) = (wide(name), wide(value));
+            let len = u32::try_from(value.len() * 2)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "value too long"))?;
+            // SAFETY: the name is nul-terminated, and the value is as long as passed
+            check(unsafe {
+                RegSetValueExW(self.0, name.as_ptr(), 0, REG_SZ, value.as_ptr().cast(), len)
+            })
+        }
+
+        pub(super) fn delete(self, name: &str) -> io::Result<()> {
+            let name = wide(name);
+            // SAFETY: the name is nul-terminated
+            match unsafe { RegDeleteValueW(self.0, name.as_ptr()) } {
+                ERROR_FILE_NOT_FOUND => Ok(()),
+                status => check(status),
+            }
+        }
+
+        pub(super) fn close(self) {
+            // SAFETY: the key was opened and isn't used after
+            unsafe { RegCloseKey(self.0) };
+        }
+    }
+}
+
+/// Limits on the runs of a target, after which it's deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub(crate) max_runs: Option<u32>,
+    pub(crate) expires_after: Option<Duration>,
+}
+
+/// State of a target kept in a store, under keys derived from its path.
+pub(crate) struct Tracked {
+    store: Arc<dyn Store>,
+    prefix: String,
+}
+
+impl fmt::Debug for Tracked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracked")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracked {
+    /// Track the target identified by `id` in `store`.
+    pub(crate) fn new(store: Arc<dyn Store>, id: u64) -> Self {
+        Tracked {
+            store,
+            prefix: format!("{id:016x}"),
+        }
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}.{name}", self.prefix)
+    }
+
+    /// Count this run, and tell whether `limits` are reached, either one sufficing.
+    pub(crate) fn run(&self, limits: Limits) -> io::Result<bool> {
+        let load = |name| -> io::Result<Option<u64>> {
+            let value = self.store.load(&self.key(name))?;
+            value
+                .map(|value| value.trim().parse())
+                .transpose()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "corrupt run state"))
+        };
+
+        let runs = load("runs")?.unwrap_or(0).saturating_add(1);
+        self.store.save(&self.key("runs"), &runs.to_string())?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let first = match load("first-run")? {
+            Some(first) => first,
+            None => {
+                self.store.save(&self.key("first-run"), &now.to_string())?;
+                now
+            }
+        };
+
+        let ran_out = limits
+            .max_runs
+            .is_some_and(|max_runs| runs >= u64::from(max_runs));
+        let expired = limits
+            .expires_after
+            .is_some_and(|after| now.saturating_sub(first) >= after.as_secs());
+        Ok(ran_out || expired)
+    }
+
+    /// Forget the state of the target, once it's deleted.
+    pub(crate) fn clear(&self) -> io::Result<()> {
+        self.store.remove(&self.key("runs"))?;
+        self.store.remove(&self.key("first-run"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("mortem-unit-{}-state-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn state_dirs_round_trip_values() {
+        let dir = scratch("dir");
+        let store = StateDir::new(&dir);
+        assert_eq!(store.load("key").unwrap(), None);
+        store.save("key", "1").unwrap();
+        store.save("key", "2").unwrap();
+        assert_eq!(store.load("key").unwrap().as_deref(), Some("2"));
+        store.remove("key").unwrap();
+        store.remove("key").unwrap();
+        assert_eq!(store.load("key").unwrap(), None);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn counts_runs_till_the_limit() {
+        let dir = scratch("runs");
+        let tracked = Tracked::new(Arc::new(StateDir::new(&dir)), 7);
+        let limits = Limits {
+            max_runs: Some(3),
+            expires_after: None,
+        };
+        assert!(!tracked.run(limits).unwrap());
+        assert!(!tracked.run(limits).unwrap());
+        assert!(tracked.run(limits).unwrap());
+        assert!(tracked.run(limits).unwrap());
+
+        tracked.clear().unwrap();
+        assert!(!tracked.run(limits).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn expires_after_the_first_run() {
+        let dir = scratch("expiry");
+        let store = StateDir::new(&dir);
+        let tracked = Tracked::new(Arc::new(store.clone()), 7);
+        let limits = Limits {
+            max_runs: None,
+            expires_after: Some(Duration::from_secs(3600)),
+        };
+        assert!(!tracked.run(limits).unwrap());
+        let long_ago = SystemTime::now() - Duration::from_secs(7200);
+        let long_ago = long_ago.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        store
+            .save("0000000000000007.first-run", &long_ago.to_string())
+            .unwrap();
+        assert!(tracked.run(limits).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_state_fails() {
+        let dir = scratch("corrupt");
+        let store = StateDir::new(&dir);
+        store.save("0000000000000007.runs", "many").unwrap();
+        let tracked = Tracked::new(Arc::new(store), 7);
+        let limits = Limits {
+            max_runs: Some(3),
+            expires_after: None,
+        };
+        let err = tracked.run(limits).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn xattrs_round_trip_values() {
+        let dir = scratch("xattr");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("target");
+        fs::write(&file, "").unwrap();
+        let store = Xattr::on(&file);
+        match store.save("key", "12") {
+            Ok(()) => {}
+            // e.g. tmpfs without user attributes
+            Err(err) if err.raw_os_error() == Some(libc::EOPNOTSUPP) => {
+                fs::remove_dir_all(dir).unwrap();
+                return;
+            }
+            Err(err) => panic!("{err}"),
+        }
+        assert_eq!(store.load("key").unwrap().as_deref(), Some("12"));
+        assert_eq!(store.load("other").unwrap(), None);
+        store.remove("key").unwrap();
+        store.remove("key").unwrap();
+        assert_eq!(store.load("key").unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
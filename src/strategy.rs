@@ -0,0 +1,1140 @@
+//! Deletion strategies, escalated through by hard guards.
+
+use crate::{TempLocation, TempNaming};
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, remove_file};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Strategy for deleting a target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Plainly unlink the target.
+    Unlink,
+    /// Clear attributes preventing deletion, then unlink the target.
+    ///
+    /// Clears the read-only attribute, and on Linux the immutable and append-only flags, as far as
+    /// permitted; the unlink is attempted either way.
+    ClearAttributes,
+    /// Rename the target to a temporary name, by default hidden in its directory, then unlink it.
+    ///
+    /// See [`TempLocation`] and [`TempNaming`].
+    ///
+    /// Frees up the original path even if the target itself can't be deleted yet; further attempts
+    /// are made on the new name.
+    /// If renaming fails because the filesystem is full or the quota exhausted, the target is
+    /// unlinked in place instead.
+    RenameAway,
+    /// Schedule deletion of the target for later.
+    ///
+    /// On Linux, shortly after through a transient systemd unit, running
+    /// `systemd-run --on-active=2s rm -f <target>` in the user's service manager unless running as
+    /// root. On Windows, on the next reboot through `MoveFileExW` with
+    /// `MOVEFILE_DELAY_UNTIL_REBOOT`, which takes administrator rights. Succeeding only means
+    /// deletion was scheduled; the target is deleted by path, whatever is at it by then.
+    ///
+    /// Only part of the default escalation on Windows, where the running executable can't be
+    /// deleted; there, guards giving up earlier fall back to it as well. Unsupported elsewhere.
+    Schedule,
+    /// Hand deletion of the target to a detached helper process, which deletes it once this
+    /// process exited.
+    ///
+    /// For platforms and filesystems keeping the running executable locked, such as Windows. The
+    /// helper is `sh` on Unix and PowerShell on Windows, in a process group of its own so it
+    /// outlives interrupts sent to the group of this process. Succeeding only means the helper was
+    /// started; the target is deleted by path, whatever is at it by then. Not part of the default
+    /// escalation.
+    ///
+    /// The helper runs a script passed to it inline, rather than a stub written to disk, and is
+    /// started by its path on the system: `/bin/sh`, or PowerShell in the system directory. There
+    /// is no file in a shared temporary directory for other local users to swap out. Temporary
+    /// directories mounted `noexec` don't get in the way either. If the helper can't be started,
+    /// e.g. in minimal containers without a shell, the attempt fails right away, while this
+    /// process can still report it.
+    Helper,
+}
+
+impl Strategy {
+    /// Strategies hard guards escalate through by default, in order.
+    #[cfg(not(windows))]
+    pub(crate) const ESCALATION: &'static [Strategy] = &[
+        Strategy::Unlink,
+        Strategy::ClearAttributes,
+        Strategy::RenameAway,
+    ];
+
+    /// Strategies hard guards escalate through by default, in order.
+    #[cfg(windows)]
+    pub(crate) const ESCALATION: &'static [Strategy] = &[
+        Strategy::Unlink,
+        Strategy::ClearAttributes,
+        Strategy::RenameAway,
+        Strategy::Schedule,
+    ];
+
+    /// Number of attempts made with the first strategy before escalating.
+    pub(crate) const ESCALATE_AFTER: u32 = 3;
+
+    /// Strategy of `chain` for the `attempt`th attempt of a hard guard, counting from 1.
+    pub(crate) fn escalated(chain: &[Strategy], attempt: u32) -> Self {
+        let step = attempt.saturating_sub(Self::ESCALATE_AFTER) as usize;
+        chain[step.min(chain.len() - 1)]
+    }
+}
+
+/// Clear attributes of `path` as far as possible, then unlink it.
+///
+/// Failing to clear attributes doesn't prevent the unlink. Where file ownership is unmapped, such
+/// as in rootless containers, changing attributes fails with a permission error regardless of
+/// whether unlinking can succeed; the unlink's error is the one that matters.
+fn clear_then_unlink(path: &Path) -> io::Result<()> {
+    if let Err(_err) = clear_attributes(path) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(err = %_err, "failed to clear attributes; unlinking regardless");
+    }
+    remove_file(path)
+}
+
+/// Schedule deletion of `path` through a transient systemd unit.
+#[cfg(target_os = "linux")]
+fn schedule(path: &Path) -> io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    // units run with the root directory as working directory
+    let path = std::path::absolute(path)?;
+    let mut command = Command::new("systemd-run");
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        command.arg("--user");
+    }
+    let status = command
+        .args(["--quiet", "--collect", "--on-active=2s", "rm", "-f", "--"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("systemd-run failed: {status}"))),
+    }
+}
+
+/// Schedule deletion of `path` on the next reboot.
+#[cfg(windows)]
+fn schedule(path: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    const MOVEFILE_DELAY_UNTIL_REBOOT: u32 = 0x4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+
+    // the pending operation is carried out without a working directory
+    let path = std::path::absolute(path)?;
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // SAFETY: the path is nul-terminated, and no new name means deletion
+    if unsafe { MoveFileExW(path.as_ptr(), std::ptr::null(), MOVEFILE_DELAY_UNTIL_REBOOT) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn schedule(_path: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Start a detached helper process deleting `path` once this process exited.
+#[cfg(unix)]
+fn spawn_helper(path: &Path) -> io::Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    const SCRIPT: &str = r#"while kill -0 "$1" 2>/dev/null; do sleep 0.1; done; rm -f -- "$2""#;
+
+    // the helper may outlive the working directory
+    let path = std::path::absolute(path)?;
+    Command::new("/bin/sh")
+        .args(["-c", SCRIPT, "mortem"])
+        .arg(std::process::id().to_string())
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map(drop)
+}
+
+/// Start a detached helper process deleting `path` once this process exited.
+#[cfg(windows)]
+fn spawn_helper(path: &Path) -> io::Result<()> {
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    // the path is passed through the environment, so it needs no quoting
+    const SCRIPT: &str = "Wait-Process -Id $env:MORTEM_PID -ErrorAction SilentlyContinue; \
+        Remove-Item -LiteralPath $env:MORTEM_TARGET -Force";
+
+    let path = std::path::absolute(path)?;
+    Command::new(powershell())
+        .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+        .env("MORTEM_PID", std::process::id().to_string())
+        .env("MORTEM_TARGET", path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW)
+        .spawn()
+        .map(drop)
+}
+
+/// Path of PowerShell in the system directory, rather than wherever a search would find it first.
+#[cfg(windows)]
+pub(crate) fn powershell() -> PathBuf {
+    match env::var_os("SystemRoot") {
+        Some(root) => Path::new(&root).join(r"System32\WindowsPowerShell\v1.0\powershell.exe"),
+        None => PathBuf::from("powershell.exe"),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn spawn_helper(_path: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Fill in the `template` of a command delegating deletion of `path`, owned by `package`.
+///
+/// See [`PackagePolicy::Delegate`](crate::PackagePolicy::Delegate).
+pub(crate) fn delegation(template: &[String], package: &str, path: &Path) -> Vec<String> {
+    let path = path.to_string_lossy();
+    template
+        .iter()
+        .map(|arg| arg.replace("{package}", package).replace("{path}", &path))
+        .collect()
+}
+
+/// Have the package manager remove the target, running the filled in `command`.
+pub(crate) fn delegate(command: &[String]) -> io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    let (program, args) = command.split_first().ok_or(io::ErrorKind::InvalidInput)?;
+    let status = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "package manager failed: {status}"
+        ))),
+    }
+}
+
+/// Whether deletion can be scheduled, by running `systemd-run --version`.
+#[cfg(target_os = "linux")]
+pub(crate) fn probe_schedule() -> io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    let status = Command::new("systemd-run")
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("systemd-run failed: {status}"))),
+    }
+}
+
+/// Whether deletion can be scheduled, which on Windows is only known once it's tried.
+#[cfg(windows)]
+pub(crate) fn probe_schedule() -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub(crate) fn probe_schedule() -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Whether `err` is due to a full filesystem or exhausted quota.
+pub(crate) fn is_storage_full(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+    )
+}
+
+/// How to [wipe](wipe) files.
+pub(crate) struct Wiping<'a> {
+    /// Write as much as possible bypassing the page cache.
+    pub(crate) unbuffered: bool,
+    /// Times to overwrite the contents.
+    pub(crate) passes: u32,
+    /// Checked before every write, failing with [`io::ErrorKind::Interrupted`] once it doesn't
+    /// hold.
+    pub(crate) proceed: &'a dyn Fn() -> bool,
+    /// Pace of the writes, if limited.
+    pub(crate) throttle: Option<Throttle>,
+    /// Key the journal is sealed with, if any.
+    #[cfg(feature = "seal")]
+    pub(crate) key: Option<&'a crate::seal::Key>,
+}
+
+impl Wiping<'_> {
+    /// Get ready to write `len` bytes, pacing the writes and failing with
+    /// [`io::ErrorKind::Interrupted`] unless wiping is to proceed.
+    fn ready(&self, len: usize) -> io::Result<()> {
+        if let Some(throttle) = &self.throttle {
+            throttle.pace(len as u64);
+        }
+        match (self.proceed)() {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "wiping was cancelled",
+            )),
+        }
+    }
+}
+
+/// Pace of writes, limited to a number of bytes per second.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    rate: u64,
+    started: Instant,
+    /// Bytes written since `started`.
+    written: Cell<u64>,
+}
+
+impl Throttle {
+    pub(crate) fn new(rate: u64) -> Self {
+        Throttle {
+            rate: rate.max(1),
+            started: Instant::now(),
+            written: Cell::new(0),
+        }
+    }
+
+    /// Sleep till `len` more bytes may be written without exceeding the rate.
+    fn pace(&self, len: u64) {
+        let written = self.written.get();
+        self.written.set(written + len);
+        let due = Duration::from_secs_f64(written as f64 / self.rate as f64);
+        if let Some(ahead) = due.checked_sub(self.started.elapsed()) {
+            std::thread::sleep(ahead);
+        }
+    }
+}
+
+/// Overwrite the contents of `path` as many times as `wiping` says, flushing each pass to disk.
+///
+/// All passes but the last write random data, fresh for every chunk, and the last writes zeroes.
+/// The file isn't truncated, so its blocks are overwritten in place where the filesystem allows.
+///
+/// The header is zeroed first, so a wipe cut short, even by killing the process, leaves nothing
+/// to run. Progress is kept in a [journal](Journal) next to the file, from which a later wipe of
+/// the same path resumes; it's removed once the wipe is done.
+pub(crate) fn wipe(path: &Path, wiping: &Wiping) -> io::Result<()> {
+    let passes = wiping.passes;
+    zero_header(path)?;
+    let mut journal = Journal::open(path, wiping);
+    let mut pattern = Box::new(Pattern {
+        chunk: [0; WIPE_CHUNK],
+        random: None,
+    });
+    let mut state = RandomState::new().build_hasher().finish() | 1;
+    for pass in journal.pass..=passes {
+        pattern.random = (pass < passes).then_some(state);
+        pattern.chunk.fill(0);
+        journal.pass = pass;
+        if let Err(err) = wipe_pass(path, wiping, &mut pattern, &mut journal) {
+            journal.record();
+            return Err(err);
+        }
+        state = pattern.random.unwrap_or(state);
+        (journal.offset, journal.recorded) = (0, 0);
+    }
+    journal.finish();
+    Ok(())
+}
+
+/// [Wipe](wipe) the files in the tree at `path`, then remove it, renaming each entry to a random
+/// name before unlinking it, so neither contents nor names are left behind.
+///
+/// Symlinks are removed without being followed, and directories are removed bottom-up once
+/// emptied. Stops at the first error, leaving the rest of the tree in place.
+pub(crate) fn wipe_tree(path: &Path, wiping: &Wiping) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        // listed up front, as wiping leaves journals next to the files for a while
+        let entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        for entry in entries {
+            wipe_tree(&entry.path(), wiping)?;
+        }
+    } else if metadata.is_file() {
+        wipe(path, wiping)?;
+    }
+    let renamed = temp_path(path, &TempLocation::TargetDir, &TempNaming::Random)?;
+    fs::rename(path, &renamed)?;
+    match metadata.is_dir() {
+        true => fs::remove_dir(&renamed),
+        false => remove_file(&renamed),
+    }
+}
+
+/// Bytes at the start of a file holding its header, such as the ELF, Mach-O or PE one.
+const HEADER: u64 = 4096;
+
+/// Zero the header of `path`, and flush it to disk.
+fn zero_header(path: &Path) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len().min(HEADER);
+    file.write_all(&[0; HEADER as usize][..len as usize])?;
+    file.sync_data()
+}
+
+/// Progress of a wipe, kept in a file next to the wiped one.
+///
+/// The journal holds a single line: the format version `2`, then the number of passes, the pass
+/// in progress and the offset up to which it's done, [sealed](crate::seal) if a key is given. A
+/// wipe with a different number of passes, or an unreadable journal, starts over.
+#[derive(Debug)]
+struct Journal {
+    /// Path of the journal, if the wiped path has a name to derive it from.
+    path: Option<PathBuf>,
+    /// Whether the directory was synced since the journal was created.
+    synced: bool,
+    /// Key the journal is sealed with, if any.
+    #[cfg(feature = "seal")]
+    key: Option<crate::seal::Key>,
+    passes: u32,
+    /// Pass in progress, counting from 1.
+    pass: u32,
+    /// Offset up to which the pass in progress is done.
+    offset: u64,
+    /// Offset last recorded.
+    recorded: u64,
+}
+
+/// Bytes written between recording progress in the [journal](Journal).
+const JOURNAL_EVERY: u64 = 16 * WIPE_CHUNK as u64;
+
+impl Journal {
+    /// Journal of wiping `path` as `wiping` says, resuming the one left there if any.
+    fn open(path: &Path, wiping: &Wiping) -> Journal {
+        let passes = wiping.passes;
+        #[cfg(feature = "seal")]
+        let key = wiping.key.cloned();
+        let journal = journal_path(path);
+        let resumed = journal
+            .as_ref()
+            .and_then(|journal| fs::read_to_string(journal).ok())
+            .and_then(|recorded| {
+                #[cfg(feature = "seal")]
+                let recorded = match &key {
+                    Some(key) => String::from_utf8(crate::seal::open(key, &recorded).ok()?).ok()?,
+                    None => recorded,
+                };
+                let mut fields = recorded.split_whitespace();
+                if fields.next() != Some("2") {
+                    return None;
+                }
+                let mut next = || fields.next()?.parse::<u64>().ok();
+                Some((next()?, next()?, next()?))
+            })
+            .filter(|&(recorded, pass, _)| {
+                recorded == passes as u64 && (1..=recorded).contains(&pass)
+            });
+        let (pass, offset) = match resumed {
+            Some((_, pass, offset)) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(pass, offset, "resuming wipe");
+                (pass as u32, offset)
+            }
+            None => (1, 0),
+        };
+        Journal {
+            path: journal,
+            synced: false,
+            #[cfg(feature = "seal")]
+            key,
+            passes,
+            pass,
+            offset,
+            recorded: offset,
+        }
+    }
+
+    /// Note that the pass in progress is done up to `offset`, recording it every so often.
+    fn advance(&mut self, offset: u64) {
+        self.offset = offset;
+        if offset >= self.recorded + JOURNAL_EVERY {
+            self.record();
+        }
+    }
+
+    /// Record progress so far, as far as possible, and flush it to disk.
+    fn record(&mut self) {
+        if let Err(_err) = self.write() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(err = %_err, "failed to record wipe progress");
+        }
+        self.recorded = self.offset;
+    }
+
+    fn write(&mut self) -> io::Result<()> {
+        use std::io::Write;
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let line = format!("2 {} {} {}", self.passes, self.pass, self.offset);
+        #[cfg(feature = "seal")]
+        let line = match &self.key {
+            Some(key) => crate::seal::seal(key, line.as_bytes())?,
+            None => line,
+        };
+        let mut file = fs::File::create(path)?;
+        file.write_all(format!("{line}\n").as_bytes())?;
+        file.sync_all()?;
+        // the journal is only found again once its directory entry is on disk too
+        if !self.synced {
+            sync_dir(path.parent().unwrap_or(Path::new(".")))?;
+            self.synced = true;
+        }
+        Ok(())
+    }
+
+    /// Remove the journal of the finished wipe.
+    fn finish(self) {
+        if let Some(path) = &self.path {
+            let _ = remove_file(path);
+        }
+    }
+}
+
+/// Path of the journal of wiping `path`, hidden next to it under a name revealing nothing of
+/// what made it.
+fn journal_path(path: &Path) -> Option<PathBuf> {
+    let mut name = OsString::from(".");
+    name.push(path.file_name()?);
+    name.push(".part");
+    Some(path.with_file_name(name))
+}
+
+/// Flush changes to the entries of the directory `dir` to disk.
+#[cfg(unix)]
+pub(crate) fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+/// Flush changes to the entries of the directory `dir` to disk, which on Windows happens with
+/// the files themselves.
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Remove the journal of an unfinished wipe of `path`, once it's no use.
+pub(crate) fn forget_wipe(path: &Path) {
+    if let Some(journal) = journal_path(path) {
+        let _ = remove_file(journal);
+    }
+}
+
+/// Truncate `path` to nothing, which is quick and leaves nothing of it to run.
+pub(crate) fn neuter(path: &Path) -> io::Result<()> {
+    let file = fs::OpenOptions::new().write(true).open(path)?;
+    file.set_len(0)?;
+    file.sync_all()
+}
+
+/// Size of the writes wiping is done in.
+const WIPE_CHUNK: usize = 64 * 1024;
+
+/// Data written over targets, aligned for `O_DIRECT` writes.
+#[repr(C, align(4096))]
+struct Pattern {
+    chunk: [u8; WIPE_CHUNK],
+    /// State of the generator of random data, or `None` to write zeroes.
+    random: Option<u64>,
+}
+
+impl Pattern {
+    /// Next `len` bytes to write: random ones, fresh for every call, or zeroes.
+    fn next(&mut self, len: usize) -> &[u8] {
+        if let Some(state) = &mut self.random {
+            // xorshift; unpredictable enough to hide what was there, not meant to be secret
+            for word in self.chunk[..len].chunks_mut(8) {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                word.copy_from_slice(&state.to_ne_bytes()[..word.len()]);
+            }
+        }
+        &self.chunk[..len]
+    }
+}
+
+/// Overwrite the contents of `path` with `pattern` once, from where `journal` left off, and flush
+/// them to disk.
+fn wipe_pass(
+    path: &Path,
+    wiping: &Wiping,
+    pattern: &mut Pattern,
+    journal: &mut Journal,
+) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    if wiping.unbuffered {
+        wipe_unbuffered(path, len, pattern, wiping, journal)?;
+    }
+    let mut written = journal.offset.min(len);
+    file.seek(SeekFrom::Start(written))?;
+    while written < len {
+        let chunk = (len - written).min(WIPE_CHUNK as u64) as usize;
+        wiping.ready(chunk)?;
+        file.write_all(pattern.next(chunk))?;
+        written += chunk as u64;
+        journal.advance(written);
+    }
+    file.sync_all()
+}
+
+/// Overwrite the leading whole blocks of `path` with `O_DIRECT`, from where `journal` left off.
+///
+/// Writes with `O_DIRECT` must be aligned, so the trailing partial block is left to the caller.
+/// Filesystems not supporting `O_DIRECT`, such as tmpfs, are left to the caller entirely.
+#[cfg(target_os = "linux")]
+fn wipe_unbuffered(
+    path: &Path,
+    len: u64,
+    pattern: &mut Pattern,
+    wiping: &Wiping,
+    journal: &mut Journal,
+) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    /// Alignment satisfying the logical block size of practically all devices.
+    const ALIGN: u64 = 4096;
+
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) if err.raw_os_error() == Some(libc::EINVAL) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let aligned = len - len % ALIGN;
+    let mut written = journal.offset - journal.offset % ALIGN;
+    file.seek(SeekFrom::Start(written))?;
+    while written < aligned {
+        let chunk = (aligned - written).min(WIPE_CHUNK as u64) as usize;
+        wiping.ready(chunk)?;
+        file.write_all(pattern.next(chunk))?;
+        written += chunk as u64;
+        journal.advance(written);
+    }
+    Ok(())
+}
+
+/// Overwrite all of `path` with `F_NOCACHE`, from where `journal` left off.
+#[cfg(target_os = "macos")]
+fn wipe_unbuffered(
+    path: &Path,
+    len: u64,
+    pattern: &mut Pattern,
+    wiping: &Wiping,
+    journal: &mut Journal,
+) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::os::unix::io::AsRawFd;
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    // SAFETY: F_NOCACHE takes an int argument and has no other preconditions
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut written = journal.offset.min(len);
+    file.seek(SeekFrom::Start(written))?;
+    while written < len {
+        let chunk = (len - written).min(WIPE_CHUNK as u64) as usize;
+        wiping.ready(chunk)?;
+        file.write_all(pattern.next(chunk))?;
+        written += chunk as u64;
+        journal.advance(written);
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn wipe_unbuffered(
+    _path: &Path,
+    _len: u64,
+    _pattern: &mut Pattern,
+    _wiping: &Wiping,
+    _journal: &mut Journal,
+) -> io::Result<()> {
+    Ok(())
+}
+
+/// Clear attributes of `path` that prevent its deletion.
+pub(crate) fn clear_attributes(path: &Path) -> io::Result<()> {
+    // immutable files can't have their permissions changed either
+    #[cfg(target_os = "linux")]
+    clear_inode_flags(path)?;
+
+    let mut permissions = fs::symlink_metadata(path)?.permissions();
+    if permissions.readonly() {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | 0o200);
+        }
+        #[cfg(not(unix))]
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+/// Immutable inode flag, preventing unlinking even by root.
+#[cfg(target_os = "linux")]
+const FS_IMMUTABLE_FL: std::os::raw::c_int = 0x10;
+/// Append-only inode flag, preventing unlinking even by root.
+#[cfg(target_os = "linux")]
+const FS_APPEND_FL: std::os::raw::c_int = 0x20;
+
+/// Inode flags of `file`, or `None` if its filesystem has none.
+#[cfg(target_os = "linux")]
+fn inode_flags(file: &std::fs::File) -> io::Result<Option<std::os::raw::c_int>> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut flags = 0;
+    // SAFETY: FS_IOC_GETFLAGS writes a single int to the given pointer
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+        let err = io::Error::last_os_error();
+        // filesystems without inode flags can't have them set either
+        return match err.raw_os_error() {
+            Some(libc::ENOTTY | libc::EOPNOTSUPP) => Ok(None),
+            _ => Err(err),
+        };
+    }
+    Ok(Some(flags))
+}
+
+/// Whether `path` has attributes [`Strategy::ClearAttributes`] would clear.
+pub(crate) fn protected(path: &Path) -> io::Result<bool> {
+    if fs::symlink_metadata(path)?.permissions().readonly() {
+        return Ok(true);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(flags) = inode_flags(&std::fs::File::open(path)?)? {
+        return Ok(flags & (FS_IMMUTABLE_FL | FS_APPEND_FL) != 0);
+    }
+
+    Ok(false)
+}
+
+/// Clear the immutable and append-only inode flags, which prevent unlinking even by root.
+#[cfg(target_os = "linux")]
+fn clear_inode_flags(path: &Path) -> io::Result<()> {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        // out of descriptors; attempt deletion without rather than not at all
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EMFILE | libc::ENFILE)) => {
+            return Ok(())
+        }
+        Err(err) => return Err(err),
+    };
+    let Some(mut flags) = inode_flags(&file)? else {
+        return Ok(());
+    };
+
+    if flags & (FS_IMMUTABLE_FL | FS_APPEND_FL) != 0 {
+        flags &= !(FS_IMMUTABLE_FL | FS_APPEND_FL);
+        // SAFETY: FS_IOC_SETFLAGS reads a single int from the given pointer
+        if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Leave an empty, immutable placeholder at `path`, which must not exist.
+///
+/// A placeholder that can't be made immutable is left in place, but reported as an error.
+pub(crate) fn place_placeholder(path: &Path) -> io::Result<()> {
+    use std::fs::OpenOptions;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o444);
+    }
+    let file = options.open(path)?;
+    file.sync_all()?;
+
+    let mut permissions = file.metadata()?.permissions();
+    permissions.set_readonly(true);
+    file.set_permissions(permissions)?;
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        if let Some(flags) = inode_flags(&file)? {
+            let flags = flags | FS_IMMUTABLE_FL;
+            // SAFETY: FS_IOC_SETFLAGS reads a single int from the given pointer
+            if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        // SAFETY: fchflags has no memory safety preconditions
+        if unsafe { libc::fchflags(file.as_raw_fd(), libc::UF_IMMUTABLE) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Remove a placeholder left at `path` by a guard, clearing what makes it immutable first.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the file at `path` isn't empty, as it then can't
+/// be a placeholder. Clearing the immutable inode flag on Linux takes `CAP_LINUX_IMMUTABLE`, as
+/// setting it did.
+///
+/// See [`GuardBuilder::placeholder`](crate::GuardBuilder::placeholder).
+pub fn remove_placeholder(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_file() || metadata.len() != 0 {
+        return Err(io::ErrorKind::InvalidInput.into());
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: the path is nul-terminated
+        if unsafe { libc::chflags(path.as_ptr(), 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    clear_attributes(path)?;
+    remove_file(path)
+}
+
+/// Path `path` is renamed away to.
+pub(crate) fn temp_path(
+    path: &Path,
+    location: &TempLocation,
+    naming: &TempNaming,
+) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+    let temp_name = match naming {
+        TempNaming::Hidden => {
+            let mut temp_name = OsString::from(".");
+            temp_name.push(name);
+            temp_name.push(format!(".mortem-{}", std::process::id()));
+            temp_name
+        }
+        TempNaming::Random => {
+            let random = RandomState::new().build_hasher().finish();
+            format!(".mortem-{random:016x}").into()
+        }
+        TempNaming::Prefix(prefix) => {
+            let mut temp_name = OsString::from(prefix);
+            temp_name.push(name);
+            temp_name
+        }
+    };
+
+    let runtime_dir = match location {
+        TempLocation::RuntimeDir => env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from),
+        _ => None,
+    };
+    Ok(match (location, runtime_dir) {
+        (TempLocation::Custom(dir), _) => dir.join(temp_name),
+        (_, Some(dir)) => dir.join(temp_name),
+        _ => path.with_file_name(temp_name),
+    })
+}
+
+/// Apply `strategy` to `path`, returning the path further attempts should be made on.
+pub(crate) fn apply(
+    strategy: Strategy,
+    path: &Path,
+    location: &TempLocation,
+    naming: &TempNaming,
+) -> (Option<PathBuf>, io::Result<()>) {
+    match strategy {
+        Strategy::Unlink => (None, remove_file(path)),
+        Strategy::ClearAttributes => (None, clear_then_unlink(path)),
+        Strategy::Schedule => (None, schedule(path)),
+        Strategy::Helper => (None, spawn_helper(path)),
+        Strategy::RenameAway => {
+            let away = match temp_path(path, location, naming) {
+                Ok(away) => away,
+                Err(err) => return (None, Err(err)),
+            };
+            match fs::rename(path, &away) {
+                Ok(()) => {
+                    let result = clear_then_unlink(&away);
+                    (Some(away), result)
+                }
+                // renaming may need space unlinking doesn't; a full disk mustn't prevent the unlink
+                Err(err) if is_storage_full(&err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(%err, "no space to rename away; unlinking in place");
+                    (None, clear_then_unlink(path))
+                }
+                Err(err) => (None, Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh file of `len` bytes of `0xAA` for a test case, along with its journal.
+    fn target(case: &str, len: usize) -> (PathBuf, PathBuf) {
+        let path = env::temp_dir().join(format!("mortem-wipe-{}-{case}", std::process::id()));
+        fs::write(&path, vec![0xAA; len]).unwrap();
+        let journal = journal_path(&path).unwrap();
+        let _ = remove_file(&journal);
+        (path, journal)
+    }
+
+    /// Single buffered pass, going on while `proceed` holds.
+    fn wiping(proceed: &dyn Fn() -> bool) -> Wiping<'_> {
+        Wiping {
+            unbuffered: false,
+            passes: 1,
+            proceed,
+            throttle: None,
+            #[cfg(feature = "seal")]
+            key: None,
+        }
+    }
+
+    #[test]
+    fn interrupted_wipe_leaves_no_header_and_resumes() {
+        let (path, journal) = target("interrupted", 40 * WIPE_CHUNK);
+
+        let err = wipe(&path, &wiping(&|| false)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        let contents = fs::read(&path).unwrap();
+        assert!(contents[..HEADER as usize].iter().all(|&byte| byte == 0));
+        assert!(contents[HEADER as usize..].iter().all(|&byte| byte == 0xAA));
+        assert_eq!(fs::read_to_string(&journal).unwrap(), "2 1 1 0\n");
+
+        // cut short midway, having recorded progress along the way
+        let writes = std::cell::Cell::new(0);
+        let proceed = || {
+            writes.set(writes.get() + 1);
+            writes.get() <= 20
+        };
+        wipe(&path, &wiping(&proceed)).unwrap_err();
+        let offset = 20 * WIPE_CHUNK;
+        assert_eq!(
+            fs::read_to_string(&journal).unwrap(),
+            format!("2 1 1 {offset}\n")
+        );
+
+        wipe(&path, &wiping(&|| true)).unwrap();
+        assert!(fs::read(&path).unwrap().iter().all(|&byte| byte == 0));
+        assert!(!journal.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn random_passes_write_fresh_data_for_every_chunk() {
+        let (path, journal) = target("fresh", 3 * WIPE_CHUNK);
+        // cut short once the first of two passes is done
+        let writes = std::cell::Cell::new(0);
+        let proceed = || {
+            writes.set(writes.get() + 1);
+            writes.get() <= 3
+        };
+        let wiping = Wiping {
+            passes: 2,
+            ..wiping(&proceed)
+        };
+
+        wipe(&path, &wiping).unwrap_err();
+        let contents = fs::read(&path).unwrap();
+        let chunks: Vec<_> = contents.chunks(WIPE_CHUNK).collect();
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.iter().any(|&byte| byte != 0xAA)));
+        assert!(chunks[0] != chunks[1] && chunks[1] != chunks[2] && chunks[0] != chunks[2]);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&journal).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "seal")]
+    fn sealed_journals_reveal_nothing_and_resume() {
+        let (path, journal) = target("sealed", 40 * WIPE_CHUNK);
+        let key = crate::seal::Key::new(b"secret");
+        let writes = std::cell::Cell::new(0);
+        let proceed = || {
+            writes.set(writes.get() + 1);
+            writes.get() <= 20
+        };
+        let sealed = |proceed| Wiping {
+            key: Some(&key),
+            ..wiping(proceed)
+        };
+
+        wipe(&path, &sealed(&proceed)).unwrap_err();
+        let recorded = fs::read_to_string(&journal).unwrap();
+        assert!(!recorded.contains(&(20 * WIPE_CHUNK).to_string()));
+        let opened = crate::seal::open(&key, &recorded).unwrap();
+        assert_eq!(opened, format!("2 1 1 {}", 20 * WIPE_CHUNK).as_bytes());
+
+        // resumed from the sealed journal, the first half isn't written again
+        fs::write(&path, vec![0xAA; 40 * WIPE_CHUNK]).unwrap();
+        wipe(&path, &sealed(&|| true)).unwrap();
+        let contents = fs::read(&path).unwrap();
+        assert!(contents[HEADER as usize..20 * WIPE_CHUNK]
+            .iter()
+            .all(|&byte| byte == 0xAA));
+        assert!(contents[20 * WIPE_CHUNK..].iter().all(|&byte| byte == 0));
+        assert!(!journal.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wipe_resumes_where_its_journal_left_off() {
+        let (path, journal) = target("resumed", 3 * WIPE_CHUNK);
+        fs::write(&journal, format!("2 1 1 {}\n", 2 * WIPE_CHUNK)).unwrap();
+
+        wipe(&path, &wiping(&|| true)).unwrap();
+        let contents = fs::read(&path).unwrap();
+        // the header is zeroed regardless, and what the journal records as done is skipped
+        assert!(contents[..HEADER as usize].iter().all(|&byte| byte == 0));
+        assert!(contents[HEADER as usize..2 * WIPE_CHUNK]
+            .iter()
+            .all(|&byte| byte == 0xAA));
+        assert!(contents[2 * WIPE_CHUNK..].iter().all(|&byte| byte == 0));
+        assert!(!journal.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn wipes_trees_before_removing_them() {
+        let dir = env::temp_dir().join(format!("mortem-wipe-{}-tree", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let tree = dir.join("tree");
+        fs::create_dir_all(tree.join("nested")).unwrap();
+        let (secret, outside) = (tree.join("nested").join("secret"), dir.join("outside"));
+        fs::write(&secret, [0xAA; 3 * WIPE_CHUNK]).unwrap();
+        fs::write(&outside, [0xAA; 10]).unwrap();
+        // a hard link outside the tree shows what became of the contents
+        fs::hard_link(&secret, dir.join("link")).unwrap();
+        std::os::unix::fs::symlink(&outside, tree.join("symlink")).unwrap();
+
+        wipe_tree(&tree, &wiping(&|| true)).unwrap();
+        assert!(!tree.exists());
+        let wiped = fs::read(dir.join("link")).unwrap();
+        assert!(wiped.len() == 3 * WIPE_CHUNK && wiped.iter().all(|&byte| byte == 0));
+        // symlinks are removed, not followed
+        assert_eq!(fs::read(&outside).unwrap(), [0xAA; 10]);
+        // nothing but the paths outside the tree remains, renamed or not
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unbuffered_wipe_covers_the_trailing_partial_block() {
+        // on filesystems without O_DIRECT, such as tmpfs, the writes fall back to buffered ones
+        let len = 3 * WIPE_CHUNK + 1000;
+        let (path, _) = target("unbuffered", len);
+        let wiping = Wiping {
+            unbuffered: true,
+            passes: 2,
+            ..wiping(&|| true)
+        };
+
+        wipe(&path, &wiping).unwrap();
+        let wiped = fs::read(&path).unwrap();
+        assert!(wiped.len() == len && wiped.iter().all(|&byte| byte == 0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn throttled_wipe_keeps_to_its_rate() {
+        let (path, _) = target("throttled", 5 * WIPE_CHUNK);
+        let wiping = Wiping {
+            throttle: Some(Throttle::new(20 * WIPE_CHUNK as u64)),
+            ..wiping(&|| true)
+        };
+
+        // the first chunk goes out right away, and each of the other four takes 1/20 s
+        let started = Instant::now();
+        wipe(&path, &wiping).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(fs::read(&path).unwrap().iter().all(|&byte| byte == 0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wipe_starts_over_with_other_passes() {
+        let (path, journal) = target("other-passes", 3 * WIPE_CHUNK);
+        fs::write(&journal, format!("2 3 3 {}\n", 2 * WIPE_CHUNK)).unwrap();
+
+        wipe(&path, &wiping(&|| true)).unwrap();
+        assert!(fs::read(&path).unwrap().iter().all(|&byte| byte == 0));
+        assert!(!journal.exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
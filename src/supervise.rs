@@ -0,0 +1,243 @@
+//! Deleting the executables of other processes, for supervisors.
+//!
+//! Rather than every child embedding a guard, an orchestrator can supervise its children and
+//! delete their executables once they exit, with all the strategies of a guard. The executable is
+//! resolved while the process still runs, as it can't be afterwards.
+//!
+//! ```no_run
+//! use mortem::supervise::Supervised;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let child = std::process::Command::new("./worker").spawn()?;
+//! let worker = Supervised::new(child.id())?;
+//!
+//! // wait for the worker to exit, then delete it the way a hard guard would
+//! worker.delete_after_exit(mortem::Guard::builder().ensure(true))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Supported on Linux, macOS and Windows.
+
+use crate::GuardBuilder;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Process whose executable is deleted once it exits.
+#[derive(Debug)]
+pub struct Supervised {
+    pid: u32,
+    path: PathBuf,
+    process: Process,
+}
+
+impl Supervised {
+    /// Supervise the running process `pid`, resolving its executable.
+    pub fn new(pid: u32) -> io::Result<Self> {
+        let process = Process::open(pid)?;
+        let path = process.executable()?;
+        Ok(Supervised { pid, path, process })
+    }
+
+    /// Identifier of the process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Path of the executable of the process, as resolved while it ran.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Block till the process exited.
+    ///
+    /// On Windows, the handle held on the process keeps its identifier from being reused. On
+    /// Unix, the process is polled by its identifier, which could in principle be reused by
+    /// another process in between. Children exited but not yet reaped count as exited on Linux;
+    /// elsewhere they have to be reaped first, e.g. with [`std::process::Child::wait`].
+    pub fn wait(&self) -> io::Result<()> {
+        self.process.wait()
+    }
+
+    /// Delete the executable now, with a guard built from `builder` targeting it.
+    ///
+    /// The target of `builder` is replaced; everything else applies as configured, including
+    /// reporting through [events](GuardBuilder::events) and
+    /// [callbacks](GuardBuilder::on_failure). Meant for processes known to have exited, such as
+    /// reaped children.
+    pub fn delete(self, builder: GuardBuilder) -> io::Result<()> {
+        let guard = builder.target(self.path).build();
+        guard.inner.run().unwrap_or(Ok(()))
+    }
+
+    /// Wait for the process to exit, then [delete](Self::delete) its executable.
+    pub fn delete_after_exit(self, builder: GuardBuilder) -> io::Result<()> {
+        self.wait()?;
+        self.delete(builder)
+    }
+}
+
+/// Process, referred to by its identifier.
+#[cfg(unix)]
+#[derive(Debug)]
+struct Process(libc::pid_t);
+
+#[cfg(unix)]
+impl Process {
+    fn open(pid: u32) -> io::Result<Self> {
+        let pid = libc::pid_t::try_from(pid).map_err(|_| io::ErrorKind::InvalidInput)?;
+        let process = Process(pid);
+        match process.exists() {
+            true => Ok(process),
+            false => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Whether the process exists, including as a zombie.
+    fn exists(&self) -> bool {
+        // SAFETY: signal 0 only checks whether the process exists
+        let signalled = unsafe { libc::kill(self.0, 0) == 0 };
+        signalled || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn executable(&self) -> io::Result<PathBuf> {
+        std::fs::read_link(format!("/proc/{}/exe", self.0))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn executable(&self) -> io::Result<PathBuf> {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut buf = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+        // SAFETY: the buffer is valid for writes of its length
+        let len = unsafe { libc::proc_pidpath(self.0, buf.as_mut_ptr().cast(), buf.len() as u32) };
+        if len <= 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(len as usize);
+        Ok(OsString::from_vec(buf).into())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+    fn executable(&self) -> io::Result<PathBuf> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn wait(&self) -> io::Result<()> {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        while self.exists() && !self.zombie() {
+            sleep(Duration::from_millis(50));
+        }
+        Ok(())
+    }
+
+    /// Whether the process exited, but wasn't reaped yet by its parent.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn zombie(&self) -> bool {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", self.0)).unwrap_or_default();
+        stat.rsplit_once(") ")
+            .is_some_and(|(_, rest)| rest.starts_with('Z'))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn zombie(&self) -> bool {
+        false
+    }
+}
+
+/// Process, referred to by a handle.
+#[cfg(windows)]
+#[derive(Debug)]
+struct Process(std::os::windows::io::OwnedHandle);
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+
+    pub(super) const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    pub(super) const SYNCHRONIZE: u32 = 0x0010_0000;
+    pub(super) const INFINITE: u32 = 0xFFFF_FFFF;
+    pub(super) const WAIT_FAILED: u32 = 0xFFFF_FFFF;
+    /// Longest path the image name is queried with, as with the `\\?\` prefix.
+    pub(super) const MAX_PATH: usize = 32 * 1024;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub(super) fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        pub(super) fn QueryFullProcessImageNameW(
+            process: *mut c_void,
+            flags: u32,
+            name: *mut u16,
+            size: *mut u32,
+        ) -> i32;
+        pub(super) fn WaitForSingleObject(handle: *mut c_void, timeout: u32) -> u32;
+    }
+}
+
+#[cfg(windows)]
+impl Process {
+    fn open(pid: u32) -> io::Result<Self> {
+        use std::os::windows::io::{FromRawHandle, OwnedHandle};
+
+        let access = sys::PROCESS_QUERY_LIMITED_INFORMATION | sys::SYNCHRONIZE;
+        // SAFETY: OpenProcess has no preconditions
+        let handle = unsafe { sys::OpenProcess(access, 0, pid) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle was just opened, and is owned from here on
+        Ok(Process(unsafe { OwnedHandle::from_raw_handle(handle) }))
+    }
+
+    fn executable(&self) -> io::Result<PathBuf> {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::OsStringExt;
+        use std::os::windows::io::AsRawHandle;
+
+        let mut buf = vec![0u16; sys::MAX_PATH];
+        let mut len = buf.len() as u32;
+        // SAFETY: the buffer is valid for writes of `len` characters
+        let queried = unsafe {
+            sys::QueryFullProcessImageNameW(self.0.as_raw_handle(), 0, buf.as_mut_ptr(), &mut len)
+        };
+        if queried == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(len as usize);
+        Ok(OsString::from_wide(&buf).into())
+    }
+
+    fn wait(&self) -> io::Result<()> {
+        use std::os::windows::io::AsRawHandle;
+
+        // SAFETY: the handle is open, with synchronize access
+        match unsafe { sys::WaitForSingleObject(self.0.as_raw_handle(), sys::INFINITE) } {
+            sys::WAIT_FAILED => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+#[derive(Debug)]
+struct Process;
+
+#[cfg(not(any(unix, windows)))]
+impl Process {
+    fn open(_pid: u32) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn executable(&self) -> io::Result<PathBuf> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn wait(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,135 @@
+//! Confirming deletion through filesystem events.
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Time to wait for the deletion event.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Watch on the directory of a target, observing its name being removed.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) struct Watch {
+    fd: std::os::fd::OwnedFd,
+    name: std::ffi::OsString,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Watch {
+    /// Start watching the directory of `path`.
+    pub(crate) fn new(path: &Path) -> io::Result<Self> {
+        use std::ffi::CString;
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?
+            .to_owned();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+
+        // SAFETY: inotify_init1 has no preconditions
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor was just opened, and is owned from here on
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mask = libc::IN_DELETE | libc::IN_MOVED_FROM;
+        // SAFETY: `dir` is a valid nul-terminated string
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Watch { fd, name })
+    }
+
+    /// Wait up to `timeout` for the name of the target to be removed from its directory.
+    pub(crate) fn confirm(&self, timeout: Duration) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+        use std::os::unix::ffi::OsStrExt;
+        use std::time::Instant;
+
+        #[repr(C, align(4))]
+        struct Buffer([u8; 4096]);
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+        let deadline = Instant::now() + timeout;
+        let mut buffer = Buffer([0; 4096]);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut poll = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = remaining.as_millis().min(i32::MAX as u128) as i32;
+            // SAFETY: `poll` is a single valid pollfd
+            match unsafe { libc::poll(&mut poll, 1, millis) } {
+                0 => return Err(io::ErrorKind::TimedOut.into()),
+                n if n < 0 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(err);
+                }
+                _ => {}
+            }
+
+            // SAFETY: the buffer is valid for writes of its length
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.0.as_mut_ptr().cast(),
+                    buffer.0.len(),
+                )
+            };
+            if read < 0 {
+                let err = io::Error::last_os_error();
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                ) {
+                    continue;
+                }
+                return Err(err);
+            }
+
+            let events = &buffer.0[..read as usize];
+            let mut offset = 0;
+            while offset + HEADER <= events.len() {
+                // SAFETY: the kernel wrote a whole event header at `offset`
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(events[offset..].as_ptr().cast()) };
+                let name = &events[offset + HEADER..][..event.len as usize];
+                // names are padded with nul bytes
+                let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+                if name == self.name.as_bytes() {
+                    return Ok(());
+                }
+                offset += HEADER + event.len as usize;
+            }
+        }
+    }
+}
+
+/// Watch on the directory of a target, observing its name being removed.
+///
+/// Only supported on Linux, failing with [`io::ErrorKind::Unsupported`] elsewhere.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) enum Watch {}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+impl Watch {
+    pub(crate) fn new(_path: &Path) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub(crate) fn confirm(&self, _timeout: Duration) -> io::Result<()> {
+        match *self {}
+    }
+}
@@ -0,0 +1,189 @@
+//! Watchdog processes deleting the target once the process exited.
+
+use std::io;
+use std::path::Path;
+
+/// Tries at deleting the target once the process exited.
+const TRIES: u32 = 100;
+
+/// Running watchdog process.
+#[cfg(unix)]
+#[derive(Debug)]
+pub(crate) struct Watchdog(libc::pid_t);
+
+#[cfg(windows)]
+#[derive(Debug)]
+pub(crate) struct Watchdog(std::process::Child);
+
+#[cfg(not(any(unix, windows)))]
+#[derive(Debug)]
+pub(crate) enum Watchdog {}
+
+/// Fork a watchdog deleting `path` once this process exited.
+///
+/// The watchdog notices the exit by being orphaned, rather than by polling the identifier of this
+/// process, which could be reused. The target is only deleted if it's still the same file.
+#[cfg(unix)]
+pub(crate) fn spawn(path: &Path) -> io::Result<Watchdog> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let path = std::path::absolute(path)?;
+    let name = CString::new(path.as_os_str().as_bytes())?;
+    let metadata = std::fs::metadata(&path)?;
+    // SAFETY: getpid has no preconditions
+    let parent = unsafe { libc::getpid() };
+    // SAFETY: the child only makes async-signal-safe calls before exiting
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        // SAFETY: this is the freshly forked child
+        0 => unsafe { watch(parent, &name, metadata.dev(), metadata.ino()) },
+        pid => Ok(Watchdog(pid)),
+    }
+}
+
+/// Body of the forked watchdog.
+///
+/// Forked from a possibly multithreaded process, so nothing but async-signal-safe calls are made:
+/// no allocations, locks or standard library I/O.
+#[cfg(unix)]
+unsafe fn watch(parent: libc::pid_t, name: &std::ffi::CStr, dev: u64, ino: u64) -> ! {
+    use std::ptr::null_mut;
+
+    // leave the session of the process, and let go of its descriptors, such as captured output
+    libc::setsid();
+    let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+    for fd in 0..3 {
+        libc::dup2(null, fd);
+    }
+    for fd in 3..1024 {
+        libc::close(fd);
+    }
+
+    let pause = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 100_000_000,
+    };
+    while libc::getppid() == parent {
+        libc::nanosleep(&pause, null_mut());
+    }
+    for _ in 0..TRIES {
+        let mut stat: libc::stat = std::mem::zeroed();
+        if libc::stat(name.as_ptr(), &mut stat) != 0 {
+            break; // already gone
+        }
+        if stat.st_dev as u64 != dev || stat.st_ino as u64 != ino {
+            break; // replaced by another file
+        }
+        if libc::unlink(name.as_ptr()) == 0 {
+            break;
+        }
+        libc::nanosleep(&pause, null_mut());
+    }
+    libc::_exit(0)
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+
+    pub(super) const SYNCHRONIZE: u32 = 0x0010_0000;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub(super) fn GetCurrentProcess() -> *mut c_void;
+        pub(super) fn DuplicateHandle(
+            source_process: *mut c_void,
+            source: *mut c_void,
+            target_process: *mut c_void,
+            target: *mut *mut c_void,
+            access: u32,
+            inherit: i32,
+            options: u32,
+        ) -> i32;
+        pub(super) fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+}
+
+/// Spawn PowerShell deleting `path` once this process exited.
+///
+/// PowerShell inherits a handle on this process to wait on, which keeps its identifier from being
+/// reused in the meantime.
+#[cfg(windows)]
+pub(crate) fn spawn(path: &Path) -> io::Result<Watchdog> {
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use std::ptr::null_mut;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+
+    let script = format!(
+        "$h = [Microsoft.Win32.SafeHandles.SafeWaitHandle]::new([IntPtr][long]$env:MORTEM_HANDLE, $true); \
+         $w = [System.Threading.ManualResetEvent]::new($false); $w.SafeWaitHandle = $h; \
+         [void]$w.WaitOne(); \
+         for ($i = 0; $i -lt {TRIES}; $i++) {{ \
+         if (-not (Test-Path -LiteralPath $env:MORTEM_TARGET)) {{ break }}; \
+         try {{ Remove-Item -LiteralPath $env:MORTEM_TARGET -Force -ErrorAction Stop; break }} \
+         catch {{ Start-Sleep -Milliseconds 100 }} }}"
+    );
+
+    let mut handle = null_mut();
+    // SAFETY: the pseudo handle of the current process is duplicated into an owned, inheritable one
+    let duplicated = unsafe {
+        let process = sys::GetCurrentProcess();
+        sys::DuplicateHandle(
+            process,
+            process,
+            process,
+            &mut handle,
+            sys::SYNCHRONIZE,
+            1,
+            0,
+        )
+    };
+    if duplicated == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let child = Command::new(crate::strategy::powershell())
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .env("MORTEM_HANDLE", (handle as usize).to_string())
+        .env("MORTEM_TARGET", path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW | DETACHED_PROCESS)
+        .spawn();
+    // SAFETY: the handle was duplicated above, and PowerShell holds its own copy by now
+    unsafe { sys::CloseHandle(handle) };
+    child.map(Watchdog)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn spawn(_path: &Path) -> io::Result<Watchdog> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+impl Watchdog {
+    /// Stop the watchdog without it deleting anything.
+    #[cfg(unix)]
+    pub(crate) fn cancel(self) {
+        // SAFETY: the watchdog is a child of this process, reaped right after
+        unsafe {
+            libc::kill(self.0, libc::SIGKILL);
+            libc::waitpid(self.0, std::ptr::null_mut(), 0);
+        }
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn cancel(mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    pub(crate) fn cancel(self) {
+        match self {}
+    }
+}
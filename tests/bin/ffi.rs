@@ -0,0 +1,20 @@
+//! Helper executable deleting itself through the C API, spawned by the integration tests.
+//!
+//! usage: helper-ffi
+//!
+//! Registers deletion at exit with `mortem_register_atexit`, and prints `status <status>` once
+//! notified of the outcome.
+
+use mortem::ffi::mortem_register_atexit;
+
+use std::ffi::{c_int, c_void};
+
+unsafe extern "C" fn notify(status: c_int, _userdata: *mut c_void) {
+    println!("status {status}");
+}
+
+fn main() {
+    // SAFETY: the callback ignores its userdata, and is fine to call at exit
+    let registered = unsafe { mortem_register_atexit(0, Some(notify), std::ptr::null_mut()) };
+    assert_eq!(registered, mortem::ffi::MORTEM_SUCCESS);
+}
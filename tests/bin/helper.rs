@@ -0,0 +1,286 @@
+//! Helper executable deleting itself, spawned by the integration tests.
+//!
+//! usage: helper hard|bounded|secure [unlink|clear_attributes|rename_away|helper|placeholder|wrapper|panic|exit|nix_link|debugged|trigger|respect_env|twice|disarm_first|watchdog|refuse_renamed|degraded|hold_directory|userns|chroot|follow_renames|rename|skip_if_replaced|replace|payload|full_disk|unresolvable]...
+//!
+//! Prints the outcome of deletion as its only line of output. With `placeholder`, one is left
+//! behind; with `wrapper`, a wrapper script launching the helper is detected and deleted; with
+//! `panic`, the guard drops while panicking, and is configured to keep the helper then; with
+//! `exit`, the process exits without dropping the guard, which deletes the helper at exit; with
+//! `nix_link`, the symlink the helper was run through is removed if it lies in the Nix store; with
+//! `debugged`, the helper has its parent trace it, and is configured to keep itself then (Linux
+//! only); with `trigger`, deletion is triggered from another thread before the guard drops; with
+//! `respect_env`, the guard is disabled by `MORTEM_DISABLE=1`; with `twice`, a second guard is
+//! created and dropped first; with `disarm_first`, a guard created first is disarmed and dropped
+//! before the guard; with `watchdog`, a watchdog is spawned and the process is killed with
+//! the guard held, printing nothing; with `refuse_renamed`, deletion is refused when the helper
+//! is run under another name; with `degraded`, the degradations of deletion follow the outcome;
+//! with `hold_directory`, the guard holds the directory of the helper; with `userns`, the helper
+//! enters a user namespace of its own, mapping its user to root, once the guard is built; with
+//! `chroot`, it then changes its root to the `root` directory next to it; with `follow_renames`,
+//! the guard follows renames of the helper, and with `rename`, the helper renames itself to
+//! `<name>.renamed` once the guard is built; with `skip_if_replaced`, the guard skips deletion if
+//! the helper was replaced, and with `replace`, the helper puts a copy of itself in its place once
+//! the guard is built; with `payload`, the guard wipes, and removes the `payload` directory next to
+//! the helper first; with `full_disk`, that directory is instead in a full filesystem of a few
+//! pages mounted on the `disk` directory next to the helper, holding a sparse file; with
+//! `unresolvable`, the guard gives up on resolving the path of the helper rather than falling back
+//! to the original one. `userns`, `chroot` and `full_disk` print `Unsupported` and keep the helper
+//! where user namespaces are unavailable (unix only).
+
+use mortem::{DebuggerPolicy, Event, Guard, Mode, MultiCallPolicy, ResolutionPolicy, Strategy};
+
+use std::env;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::sync_channel;
+use std::time::Duration;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mode = args.next().expect("missing mode");
+    let (options, args): (Vec<_>, Vec<_>) = args.partition(|arg| {
+        matches!(
+            arg.as_str(),
+            "placeholder"
+                | "wrapper"
+                | "panic"
+                | "exit"
+                | "nix_link"
+                | "debugged"
+                | "trigger"
+                | "respect_env"
+                | "twice"
+                | "disarm_first"
+                | "watchdog"
+                | "refuse_renamed"
+                | "degraded"
+                | "hold_directory"
+                | "userns"
+                | "chroot"
+                | "follow_renames"
+                | "rename"
+                | "skip_if_replaced"
+                | "replace"
+                | "payload"
+                | "full_disk"
+                | "unresolvable"
+        )
+    });
+    let strategies: Vec<_> = args
+        .into_iter()
+        .map(|strategy| match strategy.as_str() {
+            "unlink" => Strategy::Unlink,
+            "clear_attributes" => Strategy::ClearAttributes,
+            "rename_away" => Strategy::RenameAway,
+            "helper" => Strategy::Helper,
+            strategy => panic!("unknown strategy `{strategy}`"),
+        })
+        .collect();
+
+    let mut builder = Guard::builder().mode(match mode.as_str() {
+        "hard" => Mode::Hard,
+        "bounded" => Mode::Bounded {
+            retries: 5,
+            delay: Duration::from_millis(20),
+        },
+        "secure" => Mode::Secure,
+        mode => panic!("unknown mode `{mode}`"),
+    });
+    if !strategies.is_empty() {
+        builder = builder.strategies(strategies);
+    }
+    builder = builder
+        .placeholder(options.iter().any(|option| option == "placeholder"))
+        .detect_wrapper(options.iter().any(|option| option == "wrapper"))
+        .delete_on_panic(!options.iter().any(|option| option == "panic"))
+        .remove_nix_link(options.iter().any(|option| option == "nix_link"))
+        .hold_directory(options.iter().any(|option| option == "hold_directory"))
+        .follow_renames(options.iter().any(|option| option == "follow_renames"))
+        .skip_if_replaced(options.iter().any(|option| option == "skip_if_replaced"));
+    let exe = env::current_exe().unwrap();
+    let full_disk = options.iter().any(|option| option == "full_disk");
+    if options.iter().any(|option| option == "payload") {
+        let payload = match full_disk {
+            true => exe.with_file_name("disk").join("payload"),
+            false => exe.with_file_name("payload"),
+        };
+        builder = builder
+            .wipe(true)
+            .also_remove_dir_all(payload)
+            .i_understand_this_is_destructive();
+    }
+    if options.iter().any(|option| option == "unresolvable") {
+        let mut policy = ResolutionPolicy::default();
+        policy.retries = 2;
+        policy.delay = Duration::from_millis(10);
+        policy.fall_back_to_original = false;
+        builder = builder.resolution_policy(policy);
+    }
+    if options.iter().any(|option| option == "refuse_renamed") {
+        builder = builder.multi_call_policy(MultiCallPolicy::RefuseRenamed);
+    }
+    if options.iter().any(|option| option == "respect_env") {
+        builder = builder.respect_env();
+    }
+    if options.iter().any(|option| option == "debugged") {
+        // SAFETY: PTRACE_TRACEME takes no further arguments
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0)
+        };
+        builder = builder.debugger_policy(DebuggerPolicy::Skip);
+    }
+    let (sender, receiver) = sync_channel(64);
+    let builder = builder.events(sender);
+    let first = options
+        .iter()
+        .any(|option| option == "disarm_first")
+        .then(|| builder.clone().build());
+    let guard = builder.clone().build();
+    if let Some(first) = first {
+        first.disarm();
+        drop(first);
+    }
+    if options.iter().any(|option| option == "rename") {
+        let mut renamed = exe.clone().into_os_string();
+        renamed.push(".renamed");
+        std::fs::rename(&exe, renamed).unwrap();
+    }
+    if options.iter().any(|option| option == "replace") {
+        let update = exe.with_extension("update");
+        std::fs::copy(&exe, &update).unwrap();
+        std::fs::rename(update, &exe).unwrap();
+    }
+    let chroot = options.iter().any(|option| option == "chroot");
+    if chroot || full_disk || options.iter().any(|option| option == "userns") {
+        #[cfg(unix)]
+        let confined = match full_disk {
+            true => fill_disk(&exe.with_file_name("disk")),
+            false => confine(chroot.then(|| exe.with_file_name("root")).as_deref()),
+        };
+        #[cfg(not(unix))]
+        let confined = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::Unsupported));
+        if let Err(err) = confined {
+            // nothing to test without user namespaces; keep the helper
+            eprintln!("{err}");
+            println!("Unsupported");
+            std::process::exit(0);
+        }
+    }
+    if options.iter().any(|option| option == "twice") {
+        drop(builder.build());
+    }
+    if options.iter().any(|option| option == "trigger") {
+        let trigger = guard.trigger_handle();
+        std::thread::spawn(move || trigger.trigger())
+            .join()
+            .unwrap();
+    }
+    if options.iter().any(|option| option == "watchdog") {
+        guard.watchdog().unwrap();
+        // SAFETY: getpid has no preconditions
+        #[cfg(unix)]
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGKILL)
+        };
+        std::process::abort();
+    }
+    if options.iter().any(|option| option == "exit") {
+        guard.arm_at_exit().unwrap();
+        std::process::exit(0);
+    } else if options.iter().any(|option| option == "panic") {
+        let _ = catch_unwind(AssertUnwindSafe(move || {
+            let _guard = guard;
+            panic!("crashing with the guard held");
+        }));
+    } else {
+        drop(guard);
+    }
+
+    for event in receiver.try_iter() {
+        if let Event::Done(report) = event {
+            match options.iter().any(|option| option == "degraded") {
+                true => println!("{:?} {:?}", report.outcome, report.degraded),
+                false => println!("{:?}", report.outcome),
+            }
+        }
+    }
+}
+
+/// Enter a user namespace of its own, as root mapped to the current user, then change the root to
+/// `root` if given, as rootless containers do.
+#[cfg(unix)]
+fn confine(root: Option<&std::path::Path>) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        unshare(libc::CLONE_NEWUSER)?;
+        if let Some(root) = root {
+            std::os::unix::fs::chroot(root)?;
+            env::set_current_dir("/")?;
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = root;
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Mount a filesystem of a few pages on `disk`, in user and mount namespaces of its own, then
+/// fill it up, leaving a sparse file in the `payload` directory on it.
+#[cfg(unix)]
+fn fill_disk(disk: &std::path::Path) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::ffi::CString;
+        use std::io::Write;
+        use std::os::unix::ffi::OsStrExt;
+
+        unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS)?;
+        let target = CString::new(disk.as_os_str().as_bytes())?;
+        // SAFETY: all arguments are valid C strings
+        let mounted = unsafe {
+            libc::mount(
+                c"tmpfs".as_ptr(),
+                target.as_ptr(),
+                c"tmpfs".as_ptr(),
+                0,
+                c"size=64k".as_ptr().cast(),
+            )
+        };
+        if mounted != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        std::fs::create_dir(disk.join("payload"))?;
+        // wiping has to allocate what was never written
+        std::fs::File::create(disk.join("payload").join("secret"))?.set_len(1 << 20)?;
+        let mut filler = std::fs::File::create(disk.join("filler"))?;
+        loop {
+            match filler.write_all(&[0; 4096]) {
+                Ok(()) => continue,
+                Err(err) if err.kind() == std::io::ErrorKind::StorageFull => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = disk;
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Enter the namespaces of `flags`, including a user namespace, as root mapped to the current
+/// user.
+#[cfg(target_os = "linux")]
+fn unshare(flags: libc::c_int) -> std::io::Result<()> {
+    // SAFETY: these have no preconditions
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    // SAFETY: unshare has no memory safety preconditions
+    if unsafe { libc::unshare(flags) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    std::fs::write("/proc/self/setgroups", "deny")?;
+    std::fs::write("/proc/self/uid_map", format!("0 {uid} 1"))?;
+    std::fs::write("/proc/self/gid_map", format!("0 {gid} 1"))
+}
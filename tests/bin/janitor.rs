@@ -0,0 +1,17 @@
+//! Helper executable handing off paths to a janitor, spawned by the integration tests.
+//!
+//! usage: helper-janitor <socket> <path>...
+//!
+//! Prints `ok` once the paths are handed off, or the kind of error the janitor failed with, as its
+//! only line of output, and exits, which has the janitor delete them.
+
+use std::env;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let socket = args.next().expect("missing socket");
+    match mortem::janitor::hand_off(socket, args) {
+        Ok(()) => println!("ok"),
+        Err(err) => println!("{:?}", err.kind()),
+    }
+}
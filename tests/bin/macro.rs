@@ -0,0 +1,16 @@
+//! Helper executable guarding `main` with `#[mortem::main]`, spawned by the integration tests.
+//!
+//! usage: helper-macro ok|err
+//!
+//! With `err`, `main` fails through `?`, which still deletes the executable.
+
+#[mortem::main(hard)]
+fn main() -> Result<(), String> {
+    let result = match std::env::args().nth(1).as_deref() {
+        Some("err") => Err("failing as asked".to_owned()),
+        _ => Ok(()),
+    };
+    result?;
+    println!("returned");
+    Ok(())
+}
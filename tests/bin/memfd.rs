@@ -0,0 +1,28 @@
+//! Helper executable re-executing itself from memory, spawned by the integration tests.
+//!
+//! usage: helper-memfd
+//!
+//! Prints whether its executable still exists once the guard is built, followed by the outcome
+//! of deletion.
+
+use mortem::{Event, Guard};
+
+use std::sync::mpsc::sync_channel;
+
+fn main() {
+    let (sender, receiver) = sync_channel(64);
+    let path = std::env::args_os().next().unwrap();
+    let guard = Guard::builder()
+        .reexec_from_memory(true)
+        .events(sender)
+        .build();
+
+    print!("{}", std::path::Path::new(&path).exists());
+    drop(guard);
+    for event in receiver.try_iter() {
+        if let Event::Done(report) = event {
+            print!(" {:?}", report.outcome);
+        }
+    }
+    println!();
+}
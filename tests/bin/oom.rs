@@ -0,0 +1,53 @@
+//! Helper executable deleting itself with allocation failing, spawned by the integration tests.
+//!
+//! usage: helper-oom soft|hard
+//!
+//! Every allocation fails while the guard drops, aborting the process if deletion allocates.
+//! Prints the outcome of deletion as its only line of output.
+
+use mortem::{Event, Guard, Mode};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// System allocator, failing every allocation while armed.
+struct Failing;
+
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+unsafe impl GlobalAlloc for Failing {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match ARMED.load(Ordering::SeqCst) {
+            true => ptr::null_mut(),
+            false => System.alloc(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Failing = Failing;
+
+fn main() {
+    let mode = match env::args().nth(1).as_deref() {
+        Some("soft") => Mode::Soft,
+        Some("hard") => Mode::Hard,
+        mode => panic!("unknown mode `{mode:?}`"),
+    };
+    let guard = Guard::with_mode(mode);
+
+    ARMED.store(true, Ordering::SeqCst);
+    drop(guard);
+    ARMED.store(false, Ordering::SeqCst);
+
+    for event in mortem::last_events() {
+        if let Event::Done(report) = event {
+            println!("{:?}", report.outcome);
+        }
+    }
+}
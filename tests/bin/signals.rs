@@ -0,0 +1,27 @@
+//! Helper executable terminated while its guard keeps failing, spawned by the integration tests.
+//!
+//! usage: helper-signals <directory>
+//!
+//! Guards the directory with a hard guard only unlinking it, which fails for good, and prints
+//! `ready` once the signal handlers are installed, then waits to be terminated.
+
+use mortem::{Guard, Reporter, Strategy};
+
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    let directory = env::args().nth(1).expect("missing directory");
+    let _guard = Guard::builder()
+        .ensure(true)
+        .strategies([Strategy::Unlink])
+        .target(directory)
+        .reporter(Reporter::Silent)
+        .build()
+        .with_signal_handling();
+    println!("ready");
+    loop {
+        thread::sleep(Duration::from_secs(60));
+    }
+}
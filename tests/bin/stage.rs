@@ -0,0 +1,33 @@
+//! Helper executable handing over its deletion to the next stage, spawned by the integration tests.
+//!
+//! usage: helper-stage [next stage]
+//!
+//! Executes the next stage in its place if given; otherwise prints the outcome of deletion as its
+//! only line of output.
+
+use mortem::{Event, Guard};
+
+use std::env;
+use std::process::Command;
+use std::sync::mpsc::sync_channel;
+
+fn main() {
+    let (sender, receiver) = sync_channel(64);
+    let guard = Guard::builder().inherit(true).events(sender).build();
+
+    #[cfg(unix)]
+    if let Some(next) = env::args().nth(1) {
+        use std::os::unix::process::CommandExt;
+
+        let mut next = Command::new(next);
+        guard.hand_over(&mut next).unwrap();
+        panic!("failed to execute next stage: {}", next.exec());
+    }
+
+    drop(guard);
+    for event in receiver.try_iter() {
+        if let Event::Done(report) = event {
+            println!("{:?}", report.outcome);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Running the `mortem` command line interface.
+
+#![cfg(feature = "cli")]
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+/// Fresh directory for a test case.
+fn dir(case: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mortem-cli-{}-{case}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Run `mortem` with `args`.
+fn mortem<I, S>(args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_mortem"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn rejects_invalid_arguments() {
+    for args in [
+        &[][..],
+        &["remove", "path"],
+        &["delete"],
+        &["delete", "--frobnicate", "path"],
+        &["delete", "--wait-pid", "nope", "path"],
+        &["delete", "--strategy", "shred", "path"],
+        &["delete", "--strategy"],
+        &["delete", "--status-key", "key", "path"],
+    ] {
+        let output = mortem(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("usage: mortem delete"),
+            "{args:?}"
+        );
+    }
+}
+
+#[test]
+fn deletes_paths() {
+    let dir = dir("delete");
+    let paths = [dir.join("first"), dir.join("second")];
+    for path in &paths {
+        fs::write(path, "contents").unwrap();
+    }
+
+    let args = [
+        "delete",
+        "--secure",
+        "--strategy",
+        "unlink",
+        "--strategy",
+        "rename-away",
+    ];
+    let output = mortem(
+        args.iter()
+            .map(OsStr::new)
+            .chain(paths.iter().map(|path| path.as_os_str())),
+    );
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    assert!(paths.iter().all(|path| !path.exists()));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fails_on_paths_it_cant_delete() {
+    let dir = dir("failed");
+    // a directory can't be unlinked as a file
+    let output = mortem([
+        OsStr::new("delete"),
+        OsStr::new("--strategy"),
+        OsStr::new("unlink"),
+        dir.as_os_str(),
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("failed to delete"));
+    assert!(dir.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn reports_refusals() {
+    let dir = dir("refused");
+    let path = dir.join("linked");
+    fs::write(&path, "").unwrap();
+    fs::hard_link(&path, dir.join("link")).unwrap();
+
+    let output = mortem([OsStr::new("delete"), path.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("refused to delete"), "{stderr}");
+    assert!(stderr.contains("hard links"), "{stderr}");
+    assert!(path.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn waits_for_process() {
+    let dir = dir("wait");
+    let path = dir.join("target");
+    fs::write(&path, "").unwrap();
+    let mut sleep = Command::new("sleep").arg("0.5").spawn().unwrap();
+
+    let started = Instant::now();
+    let mut mortem = Command::new(env!("CARGO_BIN_EXE_mortem"))
+        .arg("delete")
+        .arg("--wait-pid")
+        .arg(sleep.id().to_string())
+        .arg(&path)
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(path.exists());
+    // reaped, so the process is gone rather than lingering as a zombie
+    sleep.wait().unwrap();
+    assert_eq!(mortem.wait().unwrap().code(), Some(0));
+    assert!(started.elapsed() >= Duration::from_millis(400));
+    assert!(!path.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
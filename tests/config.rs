@@ -0,0 +1,132 @@
+//! (De)serializing guard configurations, as orchestration systems ship them.
+
+#![cfg(feature = "serde")]
+
+use mortem::{
+    Backoff, GuardConfig, Hook, HookPoint, Mode, Reporter, Retries, Strategy, TempLocation,
+    TempNaming, Termination,
+};
+
+use std::time::Duration;
+
+#[test]
+fn round_trips_configurations() {
+    let mut termination = Termination::default();
+    termination.grace = Duration::from_millis(250);
+    let mut config = GuardConfig::default();
+    config.retries = Retries::Limit(7);
+    config.delay = Duration::from_millis(30);
+    config.backoff = Backoff::Exponential {
+        max: Duration::from_secs(2),
+    };
+    config.strategies = vec![Strategy::RenameAway, Strategy::Unlink];
+    config.reporter = Some(Reporter::Silent);
+    config.target = Some("/opt/tool/bin/tool".into());
+    config.temp_location = TempLocation::Custom("/var/tmp".into());
+    config.temp_naming = TempNaming::Prefix(".old-".into());
+    config.terminate = Some(termination);
+    config.deadline = Some(Duration::from_secs(5));
+    config.fallback = Some(Strategy::Helper);
+    config.disable_var = Some("TOOL_KEEP".into());
+    config.max_runs = Some(10);
+    config.expires_after = Some(Duration::from_secs(86400));
+    config.also_remove = vec!["/etc/tool.toml".into()];
+    config.also_remove_dir_all = vec!["/var/lib/tool".into()];
+    config.hooks = vec![Hook::new(HookPoint::OnFailure, "/usr/bin/logger").arg("tool stayed")];
+
+    let json = serde_json::to_string(&config).unwrap();
+    let parsed: GuardConfig = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, config);
+}
+
+#[test]
+fn fills_in_missing_fields() {
+    let parsed: GuardConfig = serde_json::from_str("{}").unwrap();
+    assert_eq!(parsed, GuardConfig::default());
+}
+
+#[test]
+fn names_variants_in_snake_case() {
+    let parsed: GuardConfig = serde_json::from_str(
+        r#"{
+            "retries": { "limit": 3 },
+            "backoff": { "exponential": { "max": { "secs": 1, "nanos": 0 } } },
+            "strategies": ["clear_attributes", "rename_away"],
+            "temp_location": "runtime_dir",
+            "temp_naming": { "prefix": ".old-" }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(parsed.retries, Retries::Limit(3));
+    assert_eq!(
+        parsed.backoff,
+        Backoff::Exponential {
+            max: Duration::from_secs(1)
+        }
+    );
+    assert_eq!(
+        parsed.strategies,
+        [Strategy::ClearAttributes, Strategy::RenameAway]
+    );
+    assert_eq!(parsed.temp_location, TempLocation::RuntimeDir);
+    assert_eq!(parsed.temp_naming, TempNaming::Prefix(".old-".into()));
+    assert_eq!(parsed.delay, GuardConfig::default().delay);
+}
+
+#[test]
+fn round_trips_modes() {
+    for mode in [
+        Mode::Soft,
+        Mode::Bounded {
+            retries: 4,
+            delay: Duration::from_millis(100),
+        },
+        Mode::Hard,
+        Mode::Secure,
+        Mode::Custom(vec![Strategy::Unlink, Strategy::Schedule]),
+    ] {
+        let json = serde_json::to_string(&mode).unwrap();
+        assert_eq!(serde_json::from_str::<Mode>(&json).unwrap(), mode, "{json}");
+    }
+    assert_eq!(
+        serde_json::from_str::<Mode>(r#""secure""#).unwrap(),
+        Mode::Secure
+    );
+}
+
+#[test]
+fn describes_hooks_declaratively() {
+    let parsed: GuardConfig = serde_json::from_str(
+        r#"{
+            "also_remove": ["/etc/tool.toml"],
+            "hooks": [
+                { "when": "before_delete", "program": "/usr/bin/systemctl", "args": ["stop", "tool"] },
+                { "when": "on_success", "program": "/usr/bin/true" }
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        parsed.also_remove,
+        ["/etc/tool.toml"].map(std::path::PathBuf::from)
+    );
+    assert_eq!(
+        parsed.hooks,
+        [
+            Hook::new(HookPoint::BeforeDelete, "/usr/bin/systemctl")
+                .arg("stop")
+                .arg("tool"),
+            Hook::new(HookPoint::OnSuccess, "/usr/bin/true"),
+        ]
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn leaves_file_descriptors_out() {
+    // descriptor numbers mean nothing to the process reading the configuration
+    let mut config = GuardConfig::default();
+    config.reporter = Some(Reporter::Fd(2));
+    assert!(serde_json::to_string(&config).is_err());
+    assert!(serde_json::from_str::<GuardConfig>(r#"{ "reporter": { "fd": 2 } }"#).is_err());
+}
@@ -0,0 +1,36 @@
+//! Deletion through the C API, exercised on a helper built from `tests/bin`.
+
+#![cfg(all(unix, feature = "ffi"))]
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn deletes_itself_at_exit_and_notifies_the_outcome() {
+    // examples are built alongside the tests, next to the directory of the test executable
+    let built = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .join("examples")
+        .join("helper-ffi");
+    assert!(
+        built.is_file(),
+        "helper not built at {}; it is built with the examples by `cargo test`",
+        built.display()
+    );
+    let dir = env::temp_dir().join(format!("mortem-ffi-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let helper = dir.join("helper-ffi");
+    fs::copy(&built, &helper).unwrap();
+
+    let output = Command::new(&helper).output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "status 0");
+    assert!(!helper.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
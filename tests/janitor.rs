@@ -0,0 +1,417 @@
+//! Handing off deletions to a janitor served on a unix socket in the test process.
+//!
+//! Processes handing off paths are copies of a helper built from `tests/bin`.
+
+#![cfg(all(unix, feature = "janitor"))]
+
+use mortem::janitor::{self, Status, Submission, SubmitOptions};
+#[cfg(feature = "seal")]
+use mortem::seal::Key;
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+/// Fresh directory for a test case, with the socket of a janitor in it.
+fn dir(case: &str) -> (PathBuf, PathBuf) {
+    let dir = env::temp_dir().join(format!("mortem-janitor-{}-{case}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("janitor.sock");
+    (dir, socket)
+}
+
+/// Wait for the janitor at `socket` to accept connections.
+fn wait_for(socket: &Path) {
+    for _ in 0..100 {
+        if socket.exists() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("janitor didn't start");
+}
+
+/// Serve a janitor at `socket` on a background thread, once it accepts connections.
+fn serve(socket: &Path) {
+    let served = socket.to_owned();
+    thread::spawn(move || janitor::serve(served));
+    wait_for(socket);
+}
+
+/// Copy of the helper handing off paths, in `dir`.
+fn helper(dir: &Path) -> PathBuf {
+    // examples are built alongside the tests, next to the directory of the test executable
+    let built = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .join("examples")
+        .join("helper-janitor");
+    assert!(
+        built.is_file(),
+        "helper not built at {}; it is built with the examples by `cargo test`",
+        built.display()
+    );
+    let path = dir.join("helper-janitor");
+    fs::copy(&built, &path).unwrap();
+    path
+}
+
+/// Poll `path` till it's gone.
+fn deleted(path: &Path) -> bool {
+    for _ in 0..100 {
+        if !path.exists() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+/// Poll `submission` till the janitor is done with it.
+fn done(submission: &Submission) -> Status {
+    for _ in 0..100 {
+        match submission.status().unwrap() {
+            Status::Pending => thread::sleep(Duration::from_millis(20)),
+            status => return status,
+        }
+    }
+    panic!("janitor didn't finish the submission");
+}
+
+#[test]
+#[cfg(feature = "seal")]
+fn seals_status_records() {
+    let (dir, socket) = dir("sealed");
+    let key = Key::new(b"janitor key");
+    let served = socket.clone();
+    let server_key = key.clone();
+    thread::spawn(move || janitor::serve_sealed(served, server_key));
+    wait_for(&socket);
+
+    let target = dir.join("target");
+    fs::write(&target, "").unwrap();
+    let mut options = SubmitOptions::default();
+    options.on_exit = false;
+    options.key = Some(key);
+    let submission = janitor::submit(&socket, [&target], &options).unwrap();
+    assert_eq!(
+        done(&submission),
+        Status::Done {
+            deleted: 1,
+            failed: 0
+        }
+    );
+    assert!(!target.exists());
+
+    // the record reveals nothing without the key, and doesn't open with another
+    assert!(janitor::status(&socket, submission.id()).is_err());
+    let err = janitor::status_sealed(&socket, submission.id(), &Key::new(b"other")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn deletes_handed_off_paths_once_the_process_exited() {
+    let (dir, socket) = dir("hand-off");
+    serve(&socket);
+    let target = dir.join("target");
+    fs::write(&target, "").unwrap();
+
+    let output = Command::new(helper(&dir))
+        .arg(&socket)
+        .arg(&target)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "ok");
+    assert!(deleted(&target));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuses_paths_of_other_users() {
+    // only root can hand off as another user
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let (dir, socket) = dir("refused");
+    serve(&socket);
+    // let the unprivileged helper reach the socket and run
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o777)).unwrap();
+    let target = dir.join("target");
+    fs::write(&target, "").unwrap();
+
+    let output = Command::new(helper(&dir))
+        .arg(&socket)
+        .arg(&target)
+        .uid(65534)
+        .gid(65534)
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "PermissionDenied",
+        "{output:?}"
+    );
+    assert!(!deleted(&target));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Output of the helper handing off `target` to the janitor at `socket` as the user `nobody`.
+fn hand_off_as_nobody(dir: &Path, socket: &Path, target: &Path) -> String {
+    let output = Command::new(helper(dir))
+        .arg(socket)
+        .arg(target)
+        .uid(65534)
+        .gid(65534)
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout).trim().to_owned()
+}
+
+#[test]
+fn decides_by_the_directory_rather_than_the_owner() {
+    // only root can hand off as another user
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let (dir, socket) = dir("unlink-permission");
+    serve(&socket);
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o777)).unwrap();
+    let nobody = |path: &Path| std::os::unix::fs::chown(path, Some(65534), Some(65534)).unwrap();
+
+    // owned by the peer, but in a directory it can't write to
+    let owned = dir.join("owned");
+    fs::write(&owned, "").unwrap();
+    nobody(&owned);
+    assert_eq!(
+        hand_off_as_nobody(&dir, &socket, &owned),
+        "PermissionDenied"
+    );
+    assert!(!deleted(&owned));
+
+    // owned by root, but in a directory the peer may write to
+    let writable = dir.join("writable");
+    fs::create_dir(&writable).unwrap();
+    nobody(&writable);
+    let foreign = writable.join("foreign");
+    fs::write(&foreign, "").unwrap();
+    assert_eq!(hand_off_as_nobody(&dir, &socket, &foreign), "ok");
+    assert!(deleted(&foreign));
+
+    // in a sticky directory, only the peer's own files
+    let sticky = dir.join("sticky");
+    fs::create_dir(&sticky).unwrap();
+    fs::set_permissions(&sticky, fs::Permissions::from_mode(0o1777)).unwrap();
+    let foreign = sticky.join("foreign");
+    fs::write(&foreign, "").unwrap();
+    assert_eq!(
+        hand_off_as_nobody(&dir, &socket, &foreign),
+        "PermissionDenied"
+    );
+    assert!(!deleted(&foreign));
+    let own = sticky.join("own");
+    fs::write(&own, "").unwrap();
+    nobody(&own);
+    assert_eq!(hand_off_as_nobody(&dir, &socket, &own), "ok");
+    assert!(deleted(&own));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_status_of_submissions() {
+    let (dir, socket) = dir("status");
+    serve(&socket);
+    let targets = [dir.join("first"), dir.join("second")];
+    for target in &targets {
+        fs::write(target, "").unwrap();
+    }
+
+    let mut options = SubmitOptions::default();
+    options.on_exit = false;
+    let submission = janitor::submit(&socket, &targets, &options).unwrap();
+    assert_eq!(
+        done(&submission),
+        Status::Done {
+            deleted: 2,
+            failed: 0
+        }
+    );
+    assert!(targets.iter().all(|target| !target.exists()));
+
+    // missing paths have no owner to check them against
+    let next = janitor::submit(&socket, [dir.join("missing")], &options);
+    assert_eq!(
+        next.unwrap_err().kind(),
+        std::io::ErrorKind::PermissionDenied
+    );
+    // the refused submission got no identifier, so later ones are unknown
+    assert_eq!(
+        janitor::status(&socket, submission.id() + 1).unwrap(),
+        Status::Unknown
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn hangs_up_on_other_protocol_versions() {
+    let (dir, socket) = dir("protocol");
+    serve(&socket);
+    let target = dir.join("target");
+    fs::write(&target, "").unwrap();
+    let mut hand_off = b"D".to_vec();
+    hand_off.extend_from_slice(target.as_os_str().as_bytes());
+    hand_off.extend_from_slice(b"\0C\0");
+
+    // a tool speaking a later version is told so, and nothing it sends after is read
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    stream.write_all(b"Hmortem-janitor 2\0").unwrap();
+    stream.write_all(&hand_off).unwrap();
+    let mut reply = Vec::new();
+    BufReader::new(&stream).read_until(0, &mut reply).unwrap();
+    assert_eq!(
+        reply,
+        format!("unsupported {}\0", janitor::PROTOCOL).as_bytes()
+    );
+
+    // one predating the handshake is hung up on
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    stream.write_all(&hand_off).unwrap();
+    assert!(!matches!(stream.read(&mut [0; 64]), Ok(1..)));
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(target.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fails_on_janitors_of_other_protocol_versions() {
+    let (dir, socket) = dir("old-janitor");
+    let listener = UnixListener::bind(&socket).unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut hello = Vec::new();
+        BufReader::new(&stream).read_until(0, &mut hello).unwrap();
+        stream.write_all(b"unsupported 2\0").unwrap();
+    });
+
+    let mut options = SubmitOptions::default();
+    options.on_exit = false;
+    let err = janitor::submit(&socket, [dir.join("target")], &options).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported, "{err}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Connection to the janitor at `socket`, past the handshake.
+fn greeted(socket: &Path) -> UnixStream {
+    let mut stream = UnixStream::connect(socket).unwrap();
+    stream
+        .write_all(format!("Hmortem-janitor {}\0", janitor::PROTOCOL).as_bytes())
+        .unwrap();
+    let mut reply = Vec::new();
+    BufReader::new(&stream).read_until(0, &mut reply).unwrap();
+    assert_eq!(
+        reply,
+        format!("mortem-janitor {}\0", janitor::PROTOCOL).as_bytes()
+    );
+    stream
+}
+
+/// Whether the janitor hung up on `stream` without replying, within twice its timeout.
+fn hung_up(stream: &mut UnixStream) -> bool {
+    stream.set_read_timeout(Some(janitor::TIMEOUT * 2)).unwrap();
+    // closing with records left unread resets the connection instead
+    match stream.read(&mut [0; 64]) {
+        Ok(len) => len == 0,
+        Err(err) => err.kind() == std::io::ErrorKind::ConnectionReset,
+    }
+}
+
+#[test]
+fn bounds_connections_and_what_they_send() {
+    let (dir, socket) = dir("bounds");
+    serve(&socket);
+
+    // overlong records are dropped without being buffered
+    let mut stream = greeted(&socket);
+    let mut record = b"D".to_vec();
+    record.resize(64 * 1024, b'a');
+    let _ = stream.write_all(&record);
+    assert!(hung_up(&mut stream));
+
+    // and so are connections handing off more than their share
+    let paths: Vec<_> = (0..=janitor::MAX_PATHS)
+        .map(|i| dir.join(format!("target-{i}")))
+        .collect();
+    let mut options = SubmitOptions::default();
+    options.on_exit = false;
+    assert!(janitor::submit(&socket, &paths, &options).is_err());
+
+    // connections beyond the limit are closed right away, and slots are given back
+    let held: Vec<_> = (0..janitor::MAX_CONNECTIONS)
+        .map(|_| greeted(&socket))
+        .collect();
+    let mut extra = UnixStream::connect(&socket).unwrap();
+    assert!(hung_up(&mut extra));
+    drop(held);
+    thread::sleep(Duration::from_millis(100));
+    greeted(&socket);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn hangs_up_on_idle_connections() {
+    let (dir, socket) = dir("idle");
+    serve(&socket);
+    let mut stream = greeted(&socket);
+    assert!(hung_up(&mut stream));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn forgets_statuses_of_long_gone_submitters() {
+    let (dir, socket) = dir("retired");
+    serve(&socket);
+    let mut options = SubmitOptions::default();
+    options.on_exit = false;
+    let paths: [&Path; 0] = [];
+    let first = janitor::submit(&socket, paths, &options).unwrap();
+    assert_eq!(
+        done(&first),
+        Status::Done {
+            deleted: 0,
+            failed: 0
+        }
+    );
+    let mut last = None;
+    for _ in 0..janitor::MAX_RETIRED {
+        last = Some(janitor::submit(&socket, paths, &options).unwrap());
+    }
+    // the submitter of the last one may not have been retired just yet
+    thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(first.status().unwrap(), Status::Unknown);
+    assert_ne!(last.unwrap().status().unwrap(), Status::Unknown);
+    fs::remove_dir_all(&dir).unwrap();
+}
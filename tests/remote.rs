@@ -0,0 +1,84 @@
+//! Commanding guards listening on loopback sockets of the test process.
+
+#![cfg(feature = "remote")]
+
+use mortem::remote::{self, Auth, Command, Endpoint};
+use mortem::Guard;
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Fresh file for a guard of a test case to target.
+fn target(case: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("mortem-remote-{}-{case}", std::process::id()));
+    fs::write(&path, "").unwrap();
+    path
+}
+
+/// Guard targeting `path`, listening on a free loopback port.
+fn listen(path: &PathBuf) -> (Guard, SocketAddr) {
+    let guard = Guard::builder().target(path).build();
+    let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let listener = remote::listen(&guard, addr, Auth::token("s3cret")).unwrap();
+    let Endpoint::Tcp(addr) = *listener.endpoint() else {
+        unreachable!("listening on TCP");
+    };
+    (guard, addr)
+}
+
+#[test]
+fn denies_wrong_token() {
+    let path = target("denied");
+    let (guard, addr) = listen(&path);
+
+    for token in ["wrong", "s3cre", "s3crett", ""] {
+        let err = remote::send(addr, &Auth::token(token), Command::Delete).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{token:?}");
+    }
+    assert!(path.exists());
+
+    guard.disarm();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn deletes_on_command() {
+    let path = target("delete");
+    let (_guard, addr) = listen(&path);
+
+    remote::send(addr, &Auth::token("s3cret"), Command::Delete).unwrap();
+    assert!(!path.exists());
+    // deletion already ran, so later commands do nothing
+    remote::send(addr, &Auth::token("s3cret"), Command::Delete).unwrap();
+}
+
+#[test]
+fn closes_connections_over_the_limit() {
+    let path = target("limit");
+    let (guard, addr) = listen(&path);
+
+    // idle connections hold on to their slots, till they time out
+    let idle: Vec<_> = (0..remote::MAX_CONNECTIONS)
+        .map(|_| TcpStream::connect(addr).unwrap())
+        .collect();
+    thread::sleep(Duration::from_millis(100));
+    let mut refused = TcpStream::connect(addr).unwrap();
+    refused
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let _ = refused.write_all(b"delete s3cret\n");
+    assert_eq!(refused.read(&mut [0; 16]).unwrap_or(0), 0);
+    assert!(path.exists());
+
+    // slots are given back once connections close
+    drop(idle);
+    thread::sleep(Duration::from_millis(100));
+    remote::send(addr, &Auth::token("s3cret"), Command::Delete).unwrap();
+    assert!(!path.exists());
+    drop(guard);
+}
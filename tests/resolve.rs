@@ -0,0 +1,134 @@
+//! Properties of path resolution and safety checks, over generated paths.
+
+use mortem::resolve::{canonicalize, normalize, resolve_relative};
+use mortem::Canonicalization;
+
+use proptest::prelude::*;
+use std::env;
+use std::path::{Component, PathBuf};
+
+/// Path component, including unicode, trailing dots and spaces, and the special components.
+fn component() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(".".to_owned()),
+        Just("..".to_owned()),
+        "[a-z]{1,8}",
+        "[a-z]{1,8}[. ]{1,3}",
+        "\\PC{1,8}".prop_filter("no separators or nul", |name| {
+            !name.contains(['/', '\\', '\0']) && name != "." && name != ".."
+        }),
+        "[a-z]{200,255}",
+    ]
+}
+
+fn relative() -> impl Strategy<Value = PathBuf> {
+    prop::collection::vec(component(), 1..12).prop_map(|components| components.iter().collect())
+}
+
+fn absolute() -> impl Strategy<Value = PathBuf> {
+    relative().prop_map(|path| root().join(path))
+}
+
+fn root() -> PathBuf {
+    env::temp_dir()
+        .components()
+        .take(1)
+        .collect::<PathBuf>()
+        .join(Component::RootDir)
+}
+
+proptest! {
+    #[test]
+    fn normalizing_is_idempotent(path in prop_oneof![relative(), absolute()]) {
+        let normalized = normalize(&path);
+        prop_assert_eq!(normalize(&normalized), normalized);
+    }
+
+    #[test]
+    fn normalizing_removes_special_components(path in absolute()) {
+        let normalized = normalize(&path);
+        prop_assert!(normalized.is_absolute());
+        prop_assert!(normalized
+            .components()
+            .all(|component| !matches!(component, Component::CurDir | Component::ParentDir)));
+    }
+
+    #[test]
+    fn normalizing_keeps_leading_parents_only(path in relative()) {
+        let normalized = normalize(&path);
+        let parents = normalized
+            .components()
+            .take_while(|component| *component == Component::ParentDir)
+            .count();
+        prop_assert!(normalized
+            .components()
+            .skip(parents)
+            .all(|component| matches!(component, Component::Normal(_))));
+    }
+
+    #[test]
+    fn resolving_keeps_absolute_paths(path in absolute(), base in absolute()) {
+        prop_assert_eq!(resolve_relative(path.clone(), Some(base)), path);
+    }
+
+    #[test]
+    fn resolving_joins_relative_paths(path in relative(), base in absolute()) {
+        let resolved = resolve_relative(path.clone(), Some(base.clone()));
+        prop_assert!(resolved.is_absolute());
+        prop_assert_eq!(resolved, base.join(path));
+    }
+
+    #[test]
+    fn canonicalizing_missing_paths_falls_back_to_lexical(path in relative()) {
+        let path = env::temp_dir().join("mortem-missing").join(path);
+        let (canonical, applied) = canonicalize(path.clone(), Canonicalization::Full);
+        prop_assert_eq!(applied, Canonicalization::Lexical);
+        prop_assert_eq!(canonical, normalize(&path));
+    }
+
+    #[test]
+    fn canonicalizing_nothing_keeps_the_path(path in prop_oneof![relative(), absolute()]) {
+        let (canonical, applied) = canonicalize(path.clone(), Canonicalization::None);
+        prop_assert_eq!(applied, Canonicalization::None);
+        prop_assert_eq!(canonical, path);
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use mortem::resolve::{canonicalize, same_file};
+    use mortem::Canonicalization;
+
+    use proptest::prelude::*;
+    use std::env;
+    use std::fs;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn symlink_chains_resolve_to_their_end(length in 1..24usize) {
+            let dir = env::temp_dir().join(format!("mortem-chain-{}-{length}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let end = dir.join("end");
+            fs::write(&end, b"").unwrap();
+
+            let mut link = end.clone();
+            for n in 0..length {
+                let next = dir.join(format!("link-{n}"));
+                std::os::unix::fs::symlink(&link, &next).unwrap();
+                link = next;
+            }
+
+            let (canonical, applied) = canonicalize(link.clone(), Canonicalization::Full);
+            let end = end.canonicalize().unwrap();
+            let same = same_file(&link, &end);
+            fs::remove_dir_all(&dir).unwrap();
+
+            prop_assert_eq!(applied, Canonicalization::Full);
+            prop_assert_eq!(canonical, end);
+            prop_assert!(same);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Exchanging plans and reports as versioned documents.
+
+#![cfg(feature = "serde")]
+
+use mortem::schema::{Document, UnsupportedVersion, VERSION};
+use mortem::{Degradation, Guard, Operation, Outcome, Report, Strategy};
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+
+#[test]
+fn round_trips_plans_and_reports() {
+    let target = env::temp_dir().join(format!("mortem-schema-{}", std::process::id()));
+    fs::write(&target, "").unwrap();
+    let guard = Guard::builder().target(&target).build();
+
+    let plan = Document::new(guard.plan());
+    let json = serde_json::to_string(&plan).unwrap();
+    let read: Document<Vec<Operation>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(read, plan);
+
+    let report = guard.execute().unwrap().unwrap();
+    let json = serde_json::to_string(&Document::new(report.clone())).unwrap();
+    assert!(
+        json.starts_with(&format!(r#"{{"version":{VERSION},"data":{{"#)),
+        "{json}"
+    );
+    let read: Document<Report> = serde_json::from_str(&json).unwrap();
+    assert_eq!(read.into_data().unwrap(), report);
+}
+
+#[test]
+fn reads_error_kinds_by_name() {
+    let json = r#"{"version":1,"data":{"path":"/usr/local/bin/tool","attempts":1,"strategy":"unlink","outcome":"deleted","setuid":null,"debugger":null,"degraded":[{"not_removed":"timed_out"},{"not_wiped":"kind_of_a_later_release"}]}}"#;
+    let report = serde_json::from_str::<Document<Report>>(json)
+        .unwrap()
+        .into_data()
+        .unwrap();
+    assert_eq!(report.outcome, Outcome::Deleted);
+    assert_eq!(report.strategy, Strategy::Unlink);
+    assert_eq!(
+        report.degraded,
+        [
+            Degradation::NotRemoved(ErrorKind::TimedOut),
+            Degradation::NotWiped(ErrorKind::Other),
+        ]
+    );
+}
+
+#[test]
+fn refuses_documents_of_later_versions() {
+    let json = r#"{"version":2,"data":[]}"#;
+    let document: Document<Vec<Operation>> = serde_json::from_str(json).unwrap();
+    let err = document.into_data().unwrap_err();
+    assert_eq!(err, UnsupportedVersion(2));
+    assert_eq!(
+        err.to_string(),
+        format!("unsupported schema version 2, expected at most {VERSION}")
+    );
+}
@@ -0,0 +1,520 @@
+//! Real self-deletion, exercised through helper executables spawned as subprocesses.
+//!
+//! Each test copies a helper built from `tests/bin` into a fresh directory, prepares the copy and
+//! runs it, so the test harness itself is never deleted.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Fresh copy of the helper, in a directory of its own.
+struct Helper {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Helper {
+    fn new(case: &str) -> Self {
+        Helper::named("helper", case)
+    }
+
+    /// Fresh copy of the helper example `name`.
+    fn named(name: &str, case: &str) -> Self {
+        // examples are built alongside the tests, next to the directory of the test executable
+        let built = env::current_exe()
+            .unwrap()
+            .parent()
+            .and_then(Path::parent)
+            .unwrap()
+            .join("examples")
+            .join(format!("{name}{}", env::consts::EXE_SUFFIX));
+        assert!(
+            built.is_file(),
+            "helper not built at {}; it is built with the examples by `cargo test`",
+            built.display()
+        );
+
+        let dir = env::temp_dir().join(format!("mortem-test-{}-{case}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(built.file_name().unwrap());
+        fs::copy(&built, &path).unwrap();
+        Helper { dir, path }
+    }
+
+    /// Run `executable`, returning the outcome the helper printed.
+    fn run(&self, executable: &Path, args: &[&str]) -> String {
+        let output = Command::new(executable).args(args).output().unwrap();
+        assert!(output.status.success(), "helper failed: {output:?}");
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    }
+}
+
+impl Drop for Helper {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o755));
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn watchdog_deletes_after_kill() {
+    let helper = Helper::new("watchdog");
+    let status = Command::new(&helper.path)
+        .args(["hard", "watchdog"])
+        .status()
+        .unwrap();
+    assert!(!status.success());
+    // the watchdog deletes the executable shortly after the helper was killed
+    for _ in 0..100 {
+        if !helper.path.exists() {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    panic!("watchdog didn't delete the executable");
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::Helper;
+
+    use std::fs::{self, File};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    #[test]
+    fn deletes_itself() {
+        let helper = Helper::new("plain");
+        assert_eq!(helper.run(&helper.path, &["hard"]), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_when_readonly() {
+        let helper = Helper::new("readonly");
+        fs::set_permissions(&helper.path, fs::Permissions::from_mode(0o555)).unwrap();
+        assert_eq!(helper.run(&helper.path, &["hard"]), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_when_locked() {
+        let helper = Helper::new("locked");
+        // advisory locks don't prevent unlinking
+        let file = File::open(&helper.path).unwrap();
+        // SAFETY: flock has no memory safety preconditions
+        assert_eq!(unsafe { libc::flock(file_fd(&file), libc::LOCK_EX) }, 0);
+        assert_eq!(helper.run(&helper.path, &["hard"]), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_target_of_symlink() {
+        let helper = Helper::new("symlink");
+        let link = helper.dir.join("link");
+        std::os::unix::fs::symlink(&helper.path, &link).unwrap();
+        assert_eq!(helper.run(&link, &["hard"]), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn hands_deletion_to_helper() {
+        let helper = Helper::new("helper");
+        assert_eq!(
+            helper.run(&helper.path, &["bounded", "helper"]),
+            "Scheduled"
+        );
+        // the helper deletes the executable shortly after it exited
+        for _ in 0..100 {
+            if !helper.path.exists() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        panic!("helper didn't delete the executable");
+    }
+
+    #[test]
+    fn deletes_wrapper_script() {
+        // `exec -a` isn't POSIX
+        if !std::path::Path::new("/bin/bash").exists() {
+            return;
+        }
+        let helper = Helper::new("wrapper");
+        let wrapper = helper.dir.join("wrapper");
+        let script = format!(
+            "#!/bin/bash\nexec -a \"$0\" {:?} hard wrapper\n",
+            helper.path
+        );
+        fs::write(&wrapper, script).unwrap();
+        fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(helper.run(&wrapper, &[]), "Deleted");
+        assert!(!helper.path.exists());
+        assert!(!wrapper.exists());
+    }
+
+    #[test]
+    fn leaves_placeholder() {
+        let helper = Helper::new("placeholder");
+        assert_eq!(
+            helper.run(&helper.path, &["hard", "placeholder"]),
+            "Deleted"
+        );
+        assert_eq!(fs::metadata(&helper.path).unwrap().len(), 0);
+        mortem::strategy::remove_placeholder(&helper.path).unwrap();
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_at_exit() {
+        let helper = Helper::new("exit");
+        assert_eq!(helper.run(&helper.path, &["hard", "exit"]), "");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_when_triggered() {
+        let helper = Helper::new("trigger");
+        assert_eq!(helper.run(&helper.path, &["hard", "trigger"]), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn keeps_itself_when_disabled() {
+        let helper = Helper::new("respect-env");
+        let output = Command::new(&helper.path)
+            .args(["hard", "respect_env"])
+            .env("MORTEM_DISABLE", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "helper failed: {output:?}");
+        assert!(output.stdout.is_empty());
+        assert!(helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_once_with_two_guards() {
+        let helper = Helper::new("twice");
+        assert_eq!(helper.run(&helper.path, &["hard", "twice"]), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_once_the_first_guard_is_disarmed() {
+        let helper = Helper::new("disarm_first");
+        assert_eq!(
+            helper.run(&helper.path, &["hard", "disarm_first"]),
+            "Deleted"
+        );
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn keeps_itself_on_panic() {
+        let helper = Helper::new("panic");
+        assert_eq!(helper.run(&helper.path, &["hard", "panic"]), "");
+        assert!(helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_run_under_another_name() {
+        let helper = Helper::new("renamed");
+        let link = helper.dir.join("other-name");
+        std::os::unix::fs::symlink(&helper.path, &link).unwrap();
+        assert_eq!(helper.run(&link, &["hard"]), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_when_argv0_names_another_file() {
+        let helper = Helper::new("argv0");
+        // a file shadowing the helper's name earlier in `PATH`
+        let shadowing = helper.dir.join("bin");
+        fs::create_dir(&shadowing).unwrap();
+        let name = helper.path.file_name().unwrap();
+        fs::write(shadowing.join(name), "another executable").unwrap();
+        fs::set_permissions(shadowing.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = Command::new(&helper.path)
+            .arg0(name)
+            .arg("hard")
+            .env("PATH", &shadowing)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "helper failed: {output:?}");
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn refuses_multi_call_binary() {
+        let helper = Helper::new("multi-call");
+        let applet = helper.dir.join("applet");
+        std::os::unix::fs::symlink(&helper.path, &applet).unwrap();
+        assert_eq!(
+            helper.run(&applet, &["hard", "refuse_renamed"]),
+            "Refused(MultiCall)"
+        );
+        assert!(helper.path.exists());
+
+        fs::remove_file(&applet).unwrap();
+        fs::hard_link(&helper.path, &applet).unwrap();
+        assert_eq!(helper.run(&helper.path, &["hard"]), "Refused(MultiCall)");
+        assert!(helper.path.exists());
+    }
+
+    #[test]
+    fn refuses_nix_store() {
+        let helper = Helper::new("nix-store");
+        let store = helper.dir.join("store");
+        let path = store.join(helper.path.file_name().unwrap());
+        fs::create_dir(&store).unwrap();
+        fs::rename(&helper.path, &path).unwrap();
+        let link = helper.dir.join(helper.path.file_name().unwrap());
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+
+        let run = |args: &[&str]| {
+            let output = Command::new(&link)
+                .args(args)
+                .env("NIX_STORE_DIR", &store)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "helper failed: {output:?}");
+            String::from_utf8(output.stdout).unwrap().trim().to_owned()
+        };
+        assert_eq!(run(&["hard"]), "Refused(NixStore)");
+        assert!(path.exists() && link.exists());
+        assert_eq!(run(&["hard", "nix_link"]), "Refused(NixStore)");
+        assert!(path.exists() && link.symlink_metadata().is_err());
+    }
+
+    // debuggers are only detected on linux
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn keeps_itself_when_debugged() {
+        let helper = Helper::new("debugged");
+        assert_eq!(
+            helper.run(&helper.path, &["hard", "debugged"]),
+            "Refused(Debugged)"
+        );
+        assert!(helper.path.exists());
+    }
+
+    // the running executable can't be opened for writing on linux
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn deletes_itself_unwiped_while_running() {
+        let helper = Helper::new("secure");
+        assert_eq!(
+            helper.run(&helper.path, &["secure", "degraded"]),
+            "Deleted [NotWiped(ExecutableFileBusy)]"
+        );
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_earlier_stages_after_exec() {
+        let first = Helper::named("helper-stage", "stages");
+        let second = first.dir.join("second");
+        fs::copy(&first.path, &second).unwrap();
+        assert_eq!(
+            first.run(&first.path, &[second.to_str().unwrap()]),
+            "Deleted"
+        );
+        assert!(!first.path.exists());
+        assert!(!second.exists());
+    }
+
+    #[test]
+    fn reports_failure_in_readonly_directory() {
+        // root may write to any directory
+        // SAFETY: geteuid has no preconditions
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let helper = Helper::new("readonly-dir");
+        fs::set_permissions(&helper.dir, fs::Permissions::from_mode(0o555)).unwrap();
+        assert_eq!(
+            helper.run(&helper.path, &["bounded"]),
+            "Failed(PermissionDenied)"
+        );
+        assert!(helper.path.exists());
+    }
+
+    // the host executable is resolved without allocating on linux only
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn deletes_itself_without_allocating() {
+        for mode in ["soft", "hard"] {
+            let helper = Helper::named("helper-oom", mode);
+            assert_eq!(helper.run(&helper.path, &[mode]), "Deleted");
+            assert!(!helper.path.exists());
+        }
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn deletes_itself_through_attribute() {
+        let helper = Helper::named("helper-macro", "attribute");
+        assert_eq!(helper.run(&helper.path, &["ok"]), "returned");
+        assert!(!helper.path.exists());
+
+        // failing through `?` returns early, past the end of `main`
+        let helper = Helper::named("helper-macro", "attribute-err");
+        let output = Command::new(&helper.path).arg("err").output().unwrap();
+        assert!(!output.status.success());
+        assert!(!helper.path.exists());
+    }
+
+    #[cfg(feature = "reexec-memfd")]
+    #[test]
+    fn deletes_itself_on_start_from_memory() {
+        let helper = Helper::named("helper-memfd", "memfd");
+        assert_eq!(helper.run(&helper.path, &[]), "false Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_after_changing_roots() {
+        let helper = Helper::new("chroot");
+        // empty, so the helper is unreachable by its path from the new root
+        fs::create_dir(helper.dir.join("root")).unwrap();
+        let outcome = helper.run(&helper.path, &["bounded", "hold_directory", "chroot"]);
+        if outcome == "Unsupported" {
+            return;
+        }
+        assert_eq!(outcome, "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_owned_by_unmapped_users() {
+        // only root can hand the helper to another user
+        // SAFETY: geteuid has no preconditions
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let helper = Helper::new("rootless");
+        // inside the namespace, the owner of the read-only helper is unmapped, so its permissions
+        // can't be changed, while the directory still lets it be unlinked
+        fs::set_permissions(&helper.path, fs::Permissions::from_mode(0o555)).unwrap();
+        std::os::unix::fs::chown(&helper.path, Some(54321), Some(54321)).unwrap();
+        let outcome = helper.run(&helper.path, &["bounded", "clear_attributes", "userns"]);
+        if outcome == "Unsupported" {
+            return;
+        }
+        assert_eq!(outcome, "Deleted");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn deletes_itself_after_being_renamed() {
+        let helper = Helper::new("renamed");
+        let outcome = helper.run(&helper.path, &["bounded", "follow_renames", "rename"]);
+        assert_eq!(outcome, "Deleted");
+        assert!(!helper.path.exists());
+        assert!(!helper.dir.join("helper.renamed").exists());
+    }
+
+    #[test]
+    fn keeps_its_replacement() {
+        let helper = Helper::new("replaced");
+        let outcome = helper.run(&helper.path, &["bounded", "skip_if_replaced", "replace"]);
+        assert_eq!(outcome, "Replaced");
+        assert!(helper.path.exists());
+    }
+
+    #[test]
+    fn wipes_payload_trees() {
+        let helper = Helper::new("payload");
+        let payload = helper.dir.join("payload");
+        fs::create_dir_all(payload.join("nested")).unwrap();
+        fs::write(payload.join("nested/secret"), "secret").unwrap();
+        // shares the contents of the secret, so shows they were wiped before it was unlinked
+        fs::hard_link(payload.join("nested/secret"), helper.dir.join("witness")).unwrap();
+        let outcome = helper.run(&helper.path, &["bounded", "payload"]);
+        assert_eq!(outcome, "Deleted");
+        assert!(!payload.exists() && !helper.path.exists());
+        assert_eq!(fs::read(helper.dir.join("witness")).unwrap(), [0; 6]);
+    }
+
+    #[test]
+    fn removes_payload_trees_on_full_disks() {
+        let helper = Helper::new("full-disk");
+        fs::create_dir(helper.dir.join("disk")).unwrap();
+        let outcome = helper.run(
+            &helper.path,
+            &["bounded", "payload", "full_disk", "degraded"],
+        );
+        if outcome == "Unsupported" {
+            return;
+        }
+        // the payload couldn't be wiped, but was removed regardless
+        assert!(outcome.starts_with("Deleted "), "{outcome}");
+        assert!(outcome.contains("NotWiped(StorageFull)"), "{outcome}");
+        assert!(!outcome.contains("NotRemoved"), "{outcome}");
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn gives_up_resolving_after_changing_roots() {
+        let helper = Helper::new("unresolvable");
+        // empty, so there's no `/proc` to resolve the helper through from the new root
+        fs::create_dir(helper.dir.join("root")).unwrap();
+        let outcome = helper.run(&helper.path, &["hard", "chroot", "unresolvable"]);
+        if outcome == "Unsupported" {
+            return;
+        }
+        // hard guards stop, rather than retrying resolution forever
+        assert!(outcome.starts_with("Failed("), "{outcome}");
+        assert!(helper.path.exists());
+    }
+
+    fn file_fd(file: &File) -> std::os::raw::c_int {
+        use std::os::unix::io::AsRawFd;
+        file.as_raw_fd()
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::Helper;
+
+    use std::fs::{self, File};
+
+    // a running executable can't be deleted on Windows, but it can be renamed away
+    #[test]
+    fn renames_itself_away() {
+        let helper = Helper::new("plain");
+        helper.run(&helper.path, &["bounded", "rename_away"]);
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn renames_itself_away_when_readonly() {
+        let helper = Helper::new("readonly");
+        let mut permissions = fs::metadata(&helper.path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&helper.path, permissions).unwrap();
+        helper.run(&helper.path, &["bounded", "rename_away"]);
+        assert!(!helper.path.exists());
+    }
+
+    #[test]
+    fn reports_failure_when_locked() {
+        let helper = Helper::new("locked");
+        // opened without sharing deletion, the file can be neither deleted nor renamed
+        let _file = File::open(&helper.path).unwrap();
+        // without scheduling deletion on reboot as a fallback
+        let outcome = helper.run(&helper.path, &["bounded", "unlink"]);
+        assert!(outcome.starts_with("Failed"), "{outcome}");
+        assert!(helper.path.exists());
+    }
+}
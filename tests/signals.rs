@@ -0,0 +1,61 @@
+//! Terminating processes with guards armed against termination, exercised on a helper built from
+//! `tests/bin`.
+
+#![cfg(all(unix, feature = "signals"))]
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn terminates_when_deletion_keeps_failing() {
+    // examples are built alongside the tests, next to the directory of the test executable
+    let helper = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .join("examples")
+        .join("helper-signals");
+    assert!(
+        helper.is_file(),
+        "helper not built at {}; it is built with the examples by `cargo test`",
+        helper.display()
+    );
+    let dir = env::temp_dir().join(format!("mortem-signals-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut child = Command::new(helper)
+        .arg(&dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert_eq!(line.trim(), "ready");
+
+    // SAFETY: the child is still running, as it's only reaped below
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if started.elapsed() > mortem::SHUTDOWN_BUDGET * 3 {
+            child.kill().unwrap();
+            panic!("helper didn't terminate");
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(status.signal(), Some(libc::SIGTERM));
+    assert!(dir.is_dir());
+
+    fs::remove_dir(&dir).unwrap();
+}
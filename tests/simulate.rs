@@ -0,0 +1,102 @@
+//! Simulating uninstall plans against snapshots, leaving the real tree alone.
+
+#![cfg(feature = "test-util")]
+
+use mortem::simulate::Snapshot;
+use mortem::{GuardConfig, Outcome, Strategy};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Tree of an installed tool: `bin/tool`, `bin/tool-wrapper`, `share/tool/data` and
+/// `etc/tool.conf`, with `bin/current` linking to `bin/tool`.
+fn installed(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("mortem-simulate-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for dir in ["bin", "share/tool", "etc"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    fs::write(root.join("bin/tool"), vec![0x7f; 4096]).unwrap();
+    fs::write(root.join("bin/tool-wrapper"), "#!/bin/sh\n").unwrap();
+    fs::write(root.join("share/tool/data"), "data").unwrap();
+    fs::write(root.join("etc/tool.conf"), "conf").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root.join("bin/tool"), root.join("bin/current")).unwrap();
+    root
+}
+
+#[test]
+fn runs_uninstall_plans_against_a_copy() {
+    let root = installed("plan");
+    let snapshot = Snapshot::capture(&root).unwrap();
+
+    let mut config = GuardConfig::default();
+    config.target = Some(root.join("bin/current"));
+    config.canonicalization = mortem::Canonicalization::Full;
+    config.wrappers = vec![root.join("bin/tool-wrapper")];
+    config.also_remove_dir_all = vec![root.join("share")];
+    config.destructive = true;
+    config.placeholder = true;
+    let simulation = snapshot.simulate(config).unwrap();
+
+    let report = simulation.report.unwrap().unwrap();
+    assert_eq!(report.outcome, Outcome::Deleted);
+    assert_eq!(report.path.as_deref(), Some(&*root.join("bin/tool")));
+    let mut removed = vec![
+        root.join("bin/tool-wrapper"),
+        root.join("share"),
+        root.join("share/tool"),
+        root.join("share/tool/data"),
+    ];
+    removed.sort();
+    let mut simulated = simulation.removed.clone();
+    simulated.sort();
+    assert_eq!(simulated, removed);
+    // the placeholder takes the place of the target
+    assert!(simulation.remaining.contains(&root.join("bin/tool")));
+    assert!(simulation.remaining.contains(&root.join("etc/tool.conf")));
+    assert!(simulation.created.is_empty());
+
+    // the real tree is untouched
+    assert_eq!(Snapshot::capture(&root).unwrap(), snapshot);
+    assert_eq!(fs::read(root.join("bin/tool")).unwrap(), vec![0x7f; 4096]);
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn simulates_failures() {
+    let root = installed("failure");
+    let snapshot = Snapshot::capture(&root).unwrap();
+
+    let mut config = GuardConfig::default();
+    config.target = Some(root.join("share/tool"));
+    config.strategies = vec![Strategy::Unlink];
+    let simulation = snapshot.simulate(config).unwrap();
+    assert!(simulation.report.is_err());
+    assert!(simulation.removed.is_empty());
+    assert_eq!(simulation.remaining.len(), snapshot.paths().count());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn stays_inside_the_snapshot() {
+    let root = installed("outside");
+    let snapshot = Snapshot::capture(&root).unwrap();
+    let outside = std::env::temp_dir().join("mortem-simulate-outside");
+
+    let mut host = GuardConfig::default();
+    host.target = None;
+    let mut elsewhere = GuardConfig::default();
+    elsewhere.target = Some(root.join("bin/tool"));
+    elsewhere.also_remove = vec![outside];
+    let mut deferred = GuardConfig::default();
+    deferred.target = Some(root.join("bin/tool"));
+    deferred.fallback = Some(Strategy::Helper);
+    for config in [host, elsewhere, deferred] {
+        let err = snapshot.simulate(config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    assert!(root.join("bin/tool").exists());
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,122 @@
+//! Run limits, kept track of across runs in state stores.
+
+use mortem::state::{StateDir, Store};
+use mortem::{Guard, Outcome};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mortem-state-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Store keeping its values in memory, shared between clones.
+#[derive(Clone, Default)]
+struct Memory(Arc<Mutex<HashMap<String, String>>>);
+
+impl Store for Memory {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn save(&self, key: &str, value: &str) -> io::Result<()> {
+        self.0.lock().unwrap().insert(key.into(), value.into());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        self.0.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Store failing everything.
+struct Broken;
+
+impl Store for Broken {
+    fn load(&self, _key: &str) -> io::Result<Option<String>> {
+        Err(io::ErrorKind::PermissionDenied.into())
+    }
+
+    fn save(&self, _key: &str, _value: &str) -> io::Result<()> {
+        Err(io::ErrorKind::PermissionDenied.into())
+    }
+
+    fn remove(&self, _key: &str) -> io::Result<()> {
+        Err(io::ErrorKind::PermissionDenied.into())
+    }
+}
+
+#[test]
+fn deletes_on_the_last_allowed_run() {
+    let dir = scratch("runs");
+    let target = dir.join("tool");
+    fs::write(&target, "").unwrap();
+    let run = || {
+        Guard::builder()
+            .target(&target)
+            .max_runs(3)
+            .state(StateDir::new(dir.join("state")))
+            .build()
+            .execute()
+            .unwrap()
+    };
+
+    assert!(run().is_none());
+    assert!(run().is_none());
+    assert!(target.exists());
+    assert_eq!(run().unwrap().outcome, Outcome::Deleted);
+    assert!(!target.exists());
+
+    // state is forgotten along with the target
+    assert_eq!(fs::read_dir(dir.join("state")).unwrap().count(), 0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn keeps_state_in_custom_stores() {
+    let dir = scratch("custom");
+    let target = dir.join("tool");
+    fs::write(&target, "").unwrap();
+    let store = Memory::default();
+    let run = || {
+        Guard::builder()
+            .target(&target)
+            .max_runs(2)
+            .expires_after(Duration::from_secs(3600))
+            .state(store.clone())
+            .build()
+            .execute()
+            .unwrap()
+    };
+
+    assert!(run().is_none());
+    assert_eq!(store.0.lock().unwrap().len(), 2);
+    assert_eq!(run().unwrap().outcome, Outcome::Deleted);
+    assert!(store.0.lock().unwrap().is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn stays_disarmed_without_state() {
+    let dir = scratch("broken");
+    let target = dir.join("tool");
+    fs::write(&target, "").unwrap();
+    let report = Guard::builder()
+        .target(&target)
+        .max_runs(1)
+        .state(Broken)
+        .build()
+        .execute()
+        .unwrap();
+    assert!(report.is_none());
+    assert!(target.exists());
+    fs::remove_dir_all(dir).unwrap();
+}
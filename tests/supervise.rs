@@ -0,0 +1,28 @@
+//! Deleting the executables of supervised processes, exercised on copies of `sleep`.
+
+#![cfg(target_os = "linux")]
+
+use mortem::supervise::Supervised;
+use mortem::Guard;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn deletes_executable_after_exit() {
+    let dir = env::temp_dir().join(format!("mortem-supervise-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("sleep");
+    fs::copy("/bin/sleep", &path).unwrap();
+
+    // left unreaped, so the child lingers as a zombie once exited
+    let mut child = Command::new(&path).arg("0.2").spawn().unwrap();
+    let supervised = Supervised::new(child.id()).unwrap();
+    assert_eq!(supervised.path(), path);
+    supervised.delete_after_exit(Guard::builder()).unwrap();
+    assert!(!path.exists());
+
+    child.wait().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}